@@ -0,0 +1,220 @@
+#![forbid(unsafe_code)]
+
+use std::future::Future;
+use std::io::{self, BufRead, Read};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures_core::Stream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::JoinHandle;
+
+use crate::decoder::into_io_error;
+use crate::gzip::{DecompressOptions, GzipDecoder};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const READ_CHUNK_SIZE: usize = 1 << 16;
+
+/// Number of chunks which may be in flight between the tasks.
+const CHANNEL_CAPACITY: usize = 4;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// `AsyncRead` decompressing a gzip stream read from an `AsyncRead`.
+///
+/// The input is read by a task and inflated on a blocking thread, both spawned on
+/// the current tokio runtime, so it must be created from within one.
+pub struct AsyncGzipDecoder {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    inflating: Option<JoinHandle<()>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl AsyncGzipDecoder {
+    pub fn new<R: AsyncRead + Send + Unpin + 'static>(reader: R) -> Self {
+        Self::with_options(reader, DecompressOptions::default())
+    }
+
+    pub fn with_options<R: AsyncRead + Send + Unpin + 'static>(
+        reader: R,
+        options: DecompressOptions,
+    ) -> Self {
+        let (input_sender, input_receiver) = channel(CHANNEL_CAPACITY);
+        let (output_sender, output_receiver) = channel(CHANNEL_CAPACITY);
+        tokio::spawn(read_stage(reader, input_sender));
+        let inflating = tokio::task::spawn_blocking(move || {
+            inflate_stage(input_receiver, output_sender, options)
+        });
+        Self {
+            receiver: output_receiver,
+            inflating: Some(inflating),
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl AsyncGzipDecoder {
+    /// Turn into a stream of decompressed chunks, as sent by the inflating thread.
+    pub fn into_stream(self) -> impl Stream<Item = io::Result<Bytes>> + Send {
+        DecoderStream(self)
+    }
+
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Vec<u8>>>> {
+        match ready!(self.receiver.poll_recv(cx)) {
+            Some(chunk) => Poll::Ready(chunk.map(Some)),
+            None => {
+                // A closed channel means the output is over, unless the
+                // inflating thread died.
+                if let Some(inflating) = self.inflating.as_mut() {
+                    let result = ready!(Pin::new(inflating).poll(cx));
+                    self.inflating = None;
+                    result.map_err(|_| io::Error::other("inflating thread panicked"))?;
+                }
+                Poll::Ready(Ok(None))
+            }
+        }
+    }
+}
+
+impl AsyncRead for AsyncGzipDecoder {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pos == self.chunk.len() {
+            match ready!(self.poll_chunk(cx))? {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let size = std::cmp::min(self.chunk.len() - self.pos, buf.remaining());
+        buf.put_slice(&self.chunk[self.pos..self.pos + size]);
+        self.pos += size;
+        Poll::Ready(Ok(()))
+    }
+}
+
+struct DecoderStream(AsyncGzipDecoder);
+
+impl Stream for DecoderStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let decoder = &mut self.0;
+        // Hand out what was left over by reads first.
+        if decoder.pos < decoder.chunk.len() {
+            let chunk = std::mem::take(&mut decoder.chunk);
+            let pos = std::mem::replace(&mut decoder.pos, 0);
+            return Poll::Ready(Some(Ok(Bytes::from(chunk).slice(pos..))));
+        }
+        let chunk = ready!(decoder.poll_chunk(cx)).transpose();
+        Poll::Ready(chunk.map(|chunk| chunk.map(Bytes::from)))
+    }
+}
+
+/// Decompress `reader` into `writer`, returning the decompressed size.
+///
+/// Reading, inflating and writing overlap, each waiting for the next one when
+/// it falls behind.
+pub async fn copy_decode_async<R, W>(reader: R, mut writer: W) -> Result<u64>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Unpin,
+{
+    let mut decoder = AsyncGzipDecoder::new(reader);
+    let size = tokio::io::copy(&mut decoder, &mut writer).await?;
+    writer.flush().await?;
+    Ok(size)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// `BufRead` over the chunks sent by the reading task.
+struct ChannelReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl BufRead for ChannelReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.chunk.len() {
+            // A closed channel means the input is over.
+            if let Some(chunk) = self.receiver.blocking_recv() {
+                self.chunk = chunk?;
+                self.pos = 0;
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = std::cmp::min(self.pos + amount, self.chunk.len());
+    }
+}
+
+async fn read_stage<R: AsyncRead + Unpin>(mut reader: R, sender: Sender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        let result = match reader.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(size) => {
+                chunk.truncate(size);
+                Ok(chunk)
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => Err(err),
+        };
+        let failed = result.is_err();
+        if sender.send(result).await.is_err() || failed {
+            break;
+        }
+    }
+}
+
+fn inflate_stage(
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    sender: Sender<io::Result<Vec<u8>>>,
+    options: DecompressOptions,
+) {
+    let reader = ChannelReader {
+        receiver,
+        chunk: Vec::new(),
+        pos: 0,
+    };
+    let mut decoder = GzipDecoder::with_options(reader, options);
+    loop {
+        let result = decoder.fill_buffer().map(|data| data.to_vec());
+        let (result, done) = match result {
+            Ok(data) if data.is_empty() => return,
+            Ok(data) => {
+                decoder.consume_buffer(data.len());
+                (Ok(data), false)
+            }
+            Err(err) => (Err(into_io_error(err)), true),
+        };
+        if sender.blocking_send(result).is_err() || done {
+            return;
+        }
+    }
+}
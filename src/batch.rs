@@ -0,0 +1,119 @@
+#![forbid(unsafe_code)]
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::gzip::{DecompressOptions, GzipDecoder};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug, Default)]
+pub struct BatchOptions {
+    /// Number of files decompressed at once. Zero means one per available CPU.
+    pub jobs: usize,
+    /// Directory the decompressed files are written to. By default each one is
+    /// written next to its input.
+    pub output_dir: Option<PathBuf>,
+    pub decompress: DecompressOptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileStats {
+    pub output: PathBuf,
+    pub compressed_size: u64,
+    pub decompressed_size: u64,
+}
+
+#[derive(Debug)]
+pub struct FileResult {
+    pub input: PathBuf,
+    pub result: Result<FileStats>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Decompress every input into a file named after it without its ".gz" suffix,
+/// spreading the files over a pool of threads.
+///
+/// A failing file does not stop the others. The results come in input order,
+/// and a failed file leaves no output behind.
+pub fn decompress_many(
+    inputs: impl IntoIterator<Item = PathBuf>,
+    options: &BatchOptions,
+) -> Vec<FileResult> {
+    let inputs = inputs.into_iter().collect::<Vec<_>>();
+    let jobs = match options.jobs {
+        0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        jobs => jobs,
+    };
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..inputs.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..std::cmp::min(jobs, inputs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(i) else {
+                    break;
+                };
+                let result = decompress_file(input, options)
+                    .with_context(|| format!("failed to decompress {}", input.display()));
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    inputs
+        .into_iter()
+        .zip(results)
+        .map(|(input, result)| FileResult {
+            input,
+            result: result.expect("every input is processed"),
+        })
+        .collect()
+}
+
+fn decompress_file(input: &Path, options: &BatchOptions) -> Result<FileStats> {
+    let output = output_path(input, options.output_dir.as_deref())?;
+    let file = File::open(input)?;
+    let compressed_size = file.metadata()?.len();
+    let mut decoder = GzipDecoder::with_options(BufReader::new(file), options.decompress.clone());
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    let result = io::copy(&mut decoder, &mut writer).and_then(|size| {
+        writer.flush()?;
+        Ok(size)
+    });
+    match result {
+        Ok(decompressed_size) => Ok(FileStats {
+            output,
+            compressed_size,
+            decompressed_size,
+        }),
+        Err(err) => {
+            drop(writer);
+            let _ = fs::remove_file(&output);
+            Err(err.into())
+        }
+    }
+}
+
+fn output_path(input: &Path, output_dir: Option<&Path>) -> Result<PathBuf> {
+    let name = input
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".gz"))
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| anyhow!("unknown suffix"))?;
+    let dir = output_dir
+        .or_else(|| input.parent())
+        .unwrap_or(Path::new(""));
+    Ok(dir.join(name))
+}
@@ -0,0 +1,93 @@
+#![forbid(unsafe_code)]
+
+use anyhow::{ensure, Result};
+use std::io::{self, Write};
+
+use crate::decoder::into_io_error;
+use crate::encoder::{CompressOptions, GzipEncoder};
+use crate::gzip::ExtraField;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Empty member closing a BGZF file. Readers of BAM files tell a complete file
+/// from a truncated one by its presence.
+pub const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// FEXTRA field of the EOF member: the "BC" subfield with a block size of 28.
+pub(crate) const EOF_EXTRA: [u8; 6] = [0x42, 0x43, 0x02, 0x00, 0x1b, 0x00];
+
+/// Input bytes compressed into each member, as htslib does, so that members stay
+/// under 64K even when the data does not compress.
+const BLOCK_SIZE: usize = 0xff00;
+
+/// Offset of BSIZE in the header written by `BgzfEncoder`.
+const BSIZE_OFFSET: usize = 16;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Encoder writing BGZF: gzip members holding up to 64K of input each, with their
+/// size in the "BC" extra subfield, followed by the EOF member.
+pub struct BgzfEncoder<W: Write> {
+    output: W,
+    options: CompressOptions,
+    block: Vec<u8>,
+}
+
+impl<W: Write> BgzfEncoder<W> {
+    /// Create an encoder. Any extra subfields in `options` are replaced by the
+    /// "BC" one.
+    pub fn new(output: W, mut options: CompressOptions) -> Result<Self> {
+        ensure!(options.level <= 9, "invalid compression level");
+        options.extra = vec![ExtraField::bgzf_block_size(0)];
+        Ok(Self {
+            output,
+            options,
+            block: Vec::with_capacity(BLOCK_SIZE),
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Write the remaining input and the EOF member, and return the output.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_member()?;
+        self.output.write_all(&BGZF_EOF)?;
+        Ok(self.output)
+    }
+
+    fn write_member(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = GzipEncoder::new(Vec::new(), self.options.clone())?;
+        encoder.write_all(&self.block)?;
+        let mut member = encoder.finish()?;
+        let size = u16::try_from(member.len() - 1)?;
+        member[BSIZE_OFFSET..BSIZE_OFFSET + 2].copy_from_slice(&size.to_le_bytes());
+        self.output.write_all(&member)?;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = std::cmp::min(buf.len(), BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        if self.block.len() == BLOCK_SIZE {
+            self.write_member().map_err(into_io_error)?;
+        }
+        Ok(len)
+    }
+
+    /// End the current member, so that everything written so far can be decoded.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_member().map_err(into_io_error)?;
+        self.output.flush()
+    }
+}
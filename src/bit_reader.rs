@@ -32,60 +32,212 @@ impl BitSequence {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn concat(self, other: Self) -> Self {
         assert!(self.len + other.len <= 16, "Too big");
         let new_bits = self.bits | other.bits << self.len;
         BitSequence::new(new_bits, self.len + other.len)
     }
+
+    /// The same bits in the opposite order, e.g. to turn a Huffman code into the
+    /// order it is written in.
+    pub fn reverse(self) -> Self {
+        match self.len {
+            0 => self,
+            len => Self::new(self.bits.reverse_bits() >> (16 - len), len),
+        }
+    }
+
+    /// Split into the first `n` bits and the rest, so that `a.concat(b)` is undone.
+    pub fn split_at(self, n: u8) -> (Self, Self) {
+        assert!(n <= self.len, "n is bigger than the length");
+        let rest = self.bits.checked_shr(n as u32).unwrap_or(0);
+        (Self::new(self.bits, n), Self::new(rest, self.len - n))
+    }
+
+    /// The bits, least significant first.
+    pub fn iter(&self) -> impl Iterator<Item = bool> {
+        let bits = self.bits;
+        (0..self.len).map(move |i| bits >> i & 1 == 1)
+    }
+}
+
+impl From<u8> for BitSequence {
+    fn from(byte: u8) -> Self {
+        Self::new(byte as u16, 8)
+    }
+}
+
+impl From<u16> for BitSequence {
+    fn from(bits: u16) -> Self {
+        Self::new(bits, 16)
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Reader of the bits of a stream, least significant first in each byte.
+///
+/// Bits are buffered 64 at a time: first the pending ones, from bytes already
+/// consumed from the stream, then a look-ahead loaded at once from the buffer of
+/// the stream, whose bytes are only consumed as their bits are read. The stream
+/// thus never runs ahead of the bits read. For slices and cursors the buffer is
+/// the whole input, so the look-ahead is always full.
+#[derive(Clone)]
 pub struct BitReader<T> {
     stream: T,
-    bit_seq: BitSequence,
+    bits: u64,
+    len: u8,
+    /// Leading bits of `bits` whose bytes are consumed already, at most 16.
+    pending: u8,
+}
+
+/// The `len` least significant bits set.
+fn low_bits(len: u8) -> u64 {
+    u64::MAX.checked_shr(64 - len as u32).unwrap_or(0)
 }
 
 impl<T: BufRead> BitReader<T> {
+    #[allow(unused)]
     pub fn new(stream: T) -> Self {
+        Self::with_pending(stream, BitSequence::new(0, 0))
+    }
+
+    /// Create a reader which yields `pending` before the bits of `stream`.
+    pub fn with_pending(stream: T, pending: BitSequence) -> Self {
         Self {
             stream,
-            bit_seq: BitSequence::new(0, 0),
+            bits: pending.bits() as u64,
+            len: pending.len(),
+            pending: pending.len(),
         }
     }
 
-    pub fn read_bits(&mut self, len: u8) -> io::Result<BitSequence> {
-        assert!(len <= 16, "len is bigger than 16");
+    /// Yield `bits` after the pending ones and before the rest of the stream, e.g.
+    /// to start at a bit offset: the bits of its byte are primed and the stream
+    /// starts at the next byte. At most 16 bits can be pending.
+    pub fn prime(&mut self, bits: BitSequence) {
+        let pending = self.pending().concat(bits);
+        self.bits = pending.bits() as u64;
+        self.len = pending.len();
+        self.pending = pending.len();
+    }
+
+    /// Bits already fetched from the stream but not read yet.
+    pub fn pending(&self) -> BitSequence {
+        BitSequence::new(self.bits as u16, self.pending)
+    }
 
-        if self.bit_seq.len() >= len {
-            let old = BitSequence::new(self.bit_seq.bits & ((1 << len) - 1), len);
-            self.bit_seq.bits >>= len;
-            self.bit_seq.len -= len;
-            return Ok(old);
+    /// Forget the look-ahead, e.g. before the stream is read from elsewhere.
+    fn drop_look_ahead(&mut self) {
+        self.bits &= low_bits(self.pending);
+        self.len = self.pending;
+    }
+
+    /// Load the look-ahead afresh from the buffer of the stream, 8 bytes at once
+    /// unless it runs short.
+    fn refill(&mut self) -> io::Result<()> {
+        self.drop_look_ahead();
+        let buf = self.stream.fill_buf()?;
+        let room = ((64 - self.pending) / 8) as usize;
+        if let Some(word) = buf.get(..8) {
+            self.bits |= u64::from_le_bytes(word.try_into().unwrap()) << self.pending;
+            self.len = self.pending + 8 * room as u8;
+            self.bits &= low_bits(self.len);
+        } else {
+            for &byte in buf.iter().take(room) {
+                self.bits |= (byte as u64) << self.len;
+                self.len += 8;
+            }
         }
+        Ok(())
+    }
 
-        let vital_len = len - self.bit_seq.len();
-        let mut temp_bytes: [u8; 2] = [0, 0];
-        let temp_size = if vital_len > 8 { 2 } else { 1 };
+    /// Take `len` buffered bits, consuming the bytes of the look-ahead they reach.
+    fn take(&mut self, len: u8) -> u64 {
+        debug_assert!(len <= self.len);
+        let value = self.bits & low_bits(len);
+        self.bits = self.bits.checked_shr(len as u32).unwrap_or(0);
+        self.len -= len;
+        if len <= self.pending {
+            self.pending -= len;
+        } else {
+            let bytes = (len - self.pending).div_ceil(8);
+            self.stream.consume(bytes as usize);
+            self.pending = self.pending + 8 * bytes - len;
+        }
+        value
+    }
+
+    pub fn read_bits(&mut self, len: u8) -> io::Result<BitSequence> {
+        assert!(len <= 16, "len is bigger than 16");
+        if self.len < len {
+            self.refill()?;
+        }
+        if self.len < len {
+            // The buffer runs short, e.g. at a refill of the stream: read the bytes
+            // one by one, keeping those read if the stream ends.
+            self.drop_look_ahead();
+            while self.pending < len {
+                let mut byte = [0];
+                self.stream.read_exact(&mut byte)?;
+                self.bits |= (byte[0] as u64) << self.pending;
+                self.pending += 8;
+                self.len = self.pending;
+            }
+        }
+        Ok(BitSequence::new(self.take(len) as u16, len))
+    }
 
-        self.stream.read_exact(&mut temp_bytes[..temp_size])?;
+    /// Read up to 32 bits, the first ones read being the least significant.
+    pub fn read_bits_u32(&mut self, len: u8) -> io::Result<u32> {
+        assert!(len <= 32, "len is bigger than 32");
+        let low = self.read_bits(len.min(16))?.bits() as u32;
+        if len <= 16 {
+            return Ok(low);
+        }
+        let high = self.read_bits(len - 16)?.bits() as u32;
+        Ok(low | high << 16)
+    }
 
-        let byte = u16::from_le_bytes(temp_bytes);
-        let rest = BitSequence::new(byte, vital_len);
-        let new_len = 8 * temp_size as u8 - vital_len;
-        let mut new_buf = BitSequence::new(byte >> vital_len, new_len);
+    /// Look at the next `len` bits without reading them. Fewer bits are returned
+    /// when the buffer of the stream runs short, e.g. at the end of the input.
+    pub fn peek_bits(&mut self, len: u8) -> io::Result<BitSequence> {
+        assert!(len <= 16, "len is bigger than 16");
+        if self.len < len {
+            self.refill()?;
+        }
+        Ok(BitSequence::new(self.bits as u16, len.min(self.len)))
+    }
 
-        std::mem::swap(&mut new_buf, &mut self.bit_seq);
+    pub fn get_ref(&self) -> &T {
+        &self.stream
+    }
 
-        Ok(new_buf.concat(rest))
+    /// Return the underlying reader, dropping the unread bits of the current byte.
+    pub fn into_inner(self) -> T {
+        self.stream
     }
 
-    /// Discard all the unread bits in the current byte and return a mutable reference
-    /// to the underlying reader.
-    pub fn borrow_reader_from_boundary(&mut self) -> &mut T {
-        self.bit_seq = BitSequence::new(0u16, 0u8);
+    /// Mutable access to the underlying reader. Bytes read from it directly skip
+    /// over the pending bits, see `align_to_byte`.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.drop_look_ahead();
         &mut self.stream
     }
+
+    /// Discard the unread bits of the current byte, so that the next bits come from
+    /// the next byte of the underlying reader. Returns the number of bits dropped.
+    pub fn align_to_byte(&mut self) -> u8 {
+        let dropped = self.pending;
+        self.bits = 0;
+        self.len = 0;
+        self.pending = 0;
+        dropped
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -113,12 +265,118 @@ mod tests {
     }
 
     #[test]
-    fn borrow_reader_from_boundary() -> io::Result<()> {
+    fn align_to_byte() -> io::Result<()> {
         let data: &[u8] = &[0b01100011, 0b11011011, 0b10101111];
         let mut reader = BitReader::new(data);
+        assert_eq!(reader.align_to_byte(), 0);
         assert_eq!(reader.read_bits(3)?, BitSequence::new(0b011, 3));
-        assert_eq!(reader.borrow_reader_from_boundary().read_u8()?, 0b11011011);
+        assert_eq!(reader.align_to_byte(), 5);
+        assert_eq!(reader.align_to_byte(), 0);
+        assert_eq!(reader.get_mut().read_u8()?, 0b11011011);
         assert_eq!(reader.read_bits(8)?, BitSequence::new(0b10101111, 8));
         Ok(())
     }
+
+    #[test]
+    fn prime() -> io::Result<()> {
+        let data: &[u8] = &[0b01100011];
+        let mut reader = BitReader::new(data);
+        reader.prime(BitSequence::new(0b101, 3));
+        assert_eq!(reader.read_bits(1)?, BitSequence::new(0b1, 1));
+        reader.prime(BitSequence::new(0b1111, 4));
+        assert_eq!(reader.read_bits(9)?, BitSequence::new(0b011111110, 9));
+        assert_eq!(reader.read_bits(5)?, BitSequence::new(0b01100, 5));
+        Ok(())
+    }
+
+    #[test]
+    fn peek_bits() -> io::Result<()> {
+        let data: &[u8] = &[0b01100011, 0b11011011, 0b10101111];
+        let mut reader = BitReader::new(data);
+        assert_eq!(reader.read_bits(3)?, BitSequence::new(0b011, 3));
+        assert_eq!(reader.peek_bits(9)?, BitSequence::new(0b101101100, 9));
+        assert_eq!(reader.read_bits(9)?, BitSequence::new(0b101101100, 9));
+        assert_eq!(reader.peek_bits(16)?, BitSequence::new(0b101011111101, 12));
+        assert_eq!(reader.read_bits(12)?, BitSequence::new(0b101011111101, 12));
+        assert_eq!(reader.peek_bits(1)?, BitSequence::new(0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn small_buffer() -> io::Result<()> {
+        // Reads straddling the refills of the stream give the same bits.
+        let data = (0..64u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>();
+        let mut whole = BitReader::new(data.as_slice());
+        let mut split = BitReader::new(io::BufReader::with_capacity(3, data.as_slice()));
+        for len in (1..=16).cycle().take(60) {
+            let peeked = split.peek_bits(len)?;
+            let bits = whole.read_bits(len)?;
+            assert_eq!(peeked, BitSequence::new(bits.bits(), peeked.len()));
+            assert_eq!(split.read_bits(len)?, bits);
+        }
+        assert_eq!(split.pending(), whole.pending());
+        Ok(())
+    }
+
+    #[test]
+    fn look_ahead() -> io::Result<()> {
+        // Only the bytes of the bits read are consumed from the stream.
+        let data: &[u8] = &[0xff, 0x00, 0xab, 0xcd, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+        let mut reader = BitReader::new(data);
+        assert_eq!(reader.peek_bits(16)?, BitSequence::from(0x00ffu16));
+        assert_eq!(reader.get_ref().len(), 10);
+        assert_eq!(reader.read_bits(9)?, BitSequence::new(0xff, 9));
+        assert_eq!(reader.get_ref().len(), 8);
+        assert_eq!(reader.pending(), BitSequence::new(0, 7));
+        assert_eq!(reader.align_to_byte(), 7);
+        assert_eq!(reader.get_mut().read_u8()?, 0xab);
+        assert_eq!(reader.read_bits(16)?, BitSequence::from(0x12cdu16));
+        assert_eq!(reader.into_inner(), &data[5..]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_bits_u32() -> io::Result<()> {
+        let data: &[u8] = &[0x78, 0x56, 0x34, 0x12, 0b10101010, 0xff, 0xff, 0xff, 0xff];
+        let mut reader = BitReader::new(data);
+        assert_eq!(reader.read_bits_u32(32)?, 0x12345678);
+        assert_eq!(reader.read_bits_u32(3)?, 0b010);
+        assert_eq!(reader.read_bits_u32(20)?, 0xffff5);
+        assert_eq!(reader.read_bits(16)?, BitSequence::new(0xffff, 16));
+        assert_eq!(
+            reader.read_bits_u32(17).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bit_sequence() {
+        let seq = BitSequence::new(0b10110, 5);
+        assert_eq!(seq.reverse(), BitSequence::new(0b01101, 5));
+        assert_eq!(seq.reverse().reverse(), seq);
+        assert_eq!(BitSequence::new(0, 0).reverse(), BitSequence::new(0, 0));
+        assert_eq!(
+            BitSequence::from(0x8001u16).reverse(),
+            BitSequence::from(0x8001u16)
+        );
+
+        let (first, rest) = seq.split_at(2);
+        assert_eq!(first, BitSequence::new(0b10, 2));
+        assert_eq!(rest, BitSequence::new(0b101, 3));
+        assert_eq!(first.concat(rest), seq);
+        assert_eq!(seq.split_at(5), (seq, BitSequence::new(0, 0)));
+        assert_eq!(
+            BitSequence::from(0xabu8).split_at(0).1,
+            BitSequence::new(0xab, 8)
+        );
+        assert_eq!(BitSequence::from(0xffffu16).split_at(16).0.len(), 16);
+
+        let bits = seq.iter().collect::<Vec<_>>();
+        assert_eq!(bits, [false, true, true, false, true]);
+        assert_eq!(
+            BitSequence::from(0xffu8).iter().filter(|&bit| bit).count(),
+            8
+        );
+    }
 }
@@ -0,0 +1,114 @@
+#![forbid(unsafe_code)]
+
+use crate::bit_reader::BitSequence;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Bit writer packing bits starting from the least significant one, as deflate
+/// expects. Complete bytes are kept until taken with `take_bytes`.
+#[derive(Clone, Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    bits: u64,
+    len: u8,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the `len` low bits of `bits`.
+    pub fn write_bits(&mut self, bits: u32, len: u8) {
+        assert!(len <= 32, "len is bigger than 32");
+        self.bits |= (bits as u64 & ((1 << len) - 1)) << self.len;
+        self.len += len;
+        while self.len >= 8 {
+            self.buf.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    /// Write the bits of `seq` in the order they would be read back.
+    pub fn write_sequence(&mut self, seq: BitSequence) {
+        self.write_bits(seq.bits() as u32, seq.len());
+    }
+
+    /// Pad the current byte with zero bits.
+    pub fn align_to_byte(&mut self) {
+        if self.len > 0 {
+            self.buf.push(self.bits as u8);
+            self.bits = 0;
+            self.len = 0;
+        }
+    }
+
+    /// Write whole bytes. The writer must be at a byte boundary.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        assert_eq!(self.len, 0, "not at a byte boundary");
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Number of bits written since the last byte boundary.
+    pub fn pending_len(&self) -> u8 {
+        self.len
+    }
+
+    /// Take the complete bytes written so far.
+    pub fn take_bytes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_reader::BitReader;
+
+    #[test]
+    fn write_bits() -> std::io::Result<()> {
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b1, 1);
+        writer.write_bits(0b01, 2);
+        writer.write_bits(0b100, 3);
+        writer.write_bits(0b1101, 4);
+        assert_eq!(writer.pending_len(), 2);
+        writer.write_bits(0b10110, 5);
+        writer.align_to_byte();
+        writer.write_bytes(&[0xab]);
+        writer.write_bits(0x12345, 17);
+        writer.align_to_byte();
+
+        let data = writer.take_bytes();
+        assert_eq!(data.len(), 6);
+        let mut reader = BitReader::new(data.as_slice());
+        assert_eq!(reader.read_bits(1)?, BitSequence::new(0b1, 1));
+        assert_eq!(reader.read_bits(2)?, BitSequence::new(0b01, 2));
+        assert_eq!(reader.read_bits(3)?, BitSequence::new(0b100, 3));
+        assert_eq!(reader.read_bits(4)?, BitSequence::new(0b1101, 4));
+        assert_eq!(reader.read_bits(5)?, BitSequence::new(0b10110, 5));
+        assert_eq!(reader.read_bits(1)?, BitSequence::new(0, 1));
+        assert_eq!(reader.read_bits(8)?, BitSequence::new(0xab, 8));
+        assert_eq!(reader.read_bits(12)?, BitSequence::new(0x345, 12));
+        assert_eq!(reader.read_bits(4)?, BitSequence::new(0x2, 4));
+        assert_eq!(reader.read_bits(1)?, BitSequence::new(0b1, 1));
+        assert!(writer.take_bytes().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn write_sequence() -> std::io::Result<()> {
+        let seq = BitSequence::new(0b110, 3).concat(BitSequence::from(0xa5u8));
+        let mut writer = BitWriter::new();
+        writer.write_sequence(seq);
+        writer.write_sequence(BitSequence::new(0b10110, 5).reverse());
+        let data = writer.take_bytes();
+        let mut reader = BitReader::new(data.as_slice());
+        assert_eq!(reader.read_bits(11)?, seq);
+        assert_eq!(reader.read_bits(5)?, BitSequence::new(0b01101, 5));
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+#![forbid(unsafe_code)]
+
+use std::error::Error;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use futures_core::Stream;
+
+use crate::core::{InflateState, InflateStatus};
+use crate::decoder::{into_io_error, DEFAULT_BUFFER_SIZE};
+use crate::gzip::{header_len, GzipReader, ID1, ID2};
+use crate::tracking_writer::TrackingWriter;
+
+////////////////////////////////////////////////////////////////////////////////
+
+const FOOTER_SIZE: usize = 8;
+
+enum BodyState {
+    Header,
+    Deflate,
+    Footer,
+    Done,
+}
+
+enum Step {
+    Output(Bytes),
+    NeedInput,
+    Done,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Stream decompressing a gzip encoded stream of chunks, such as the body of an
+/// HTTP response with `Content-Encoding: gzip`.
+///
+/// The chunks are fed to the inflate state machine as they come, whatever their
+/// boundaries. Concatenated members are decoded one after another.
+pub struct GzipBodyDecoder<S> {
+    body: S,
+    body_done: bool,
+    input: Vec<u8>,
+    state: BodyState,
+    inflate: InflateState,
+    output: TrackingWriter<Vec<u8>>,
+    chunk_size: usize,
+}
+
+impl<S> GzipBodyDecoder<S> {
+    pub fn new(body: S) -> Self {
+        Self {
+            body,
+            body_done: false,
+            input: Vec::new(),
+            state: BodyState::Header,
+            inflate: InflateState::new(),
+            output: TrackingWriter::new(Vec::new()),
+            chunk_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Cut the decompressed data into chunks of `size` bytes, give or take a
+    /// match, instead of 64K.
+    pub fn set_chunk_size(&mut self, size: usize) {
+        assert!(size > 0, "chunk size must be positive");
+        self.chunk_size = size;
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.body
+    }
+
+    pub fn into_inner(self) -> S {
+        self.body
+    }
+
+    /// Decode as much of the buffered input as possible.
+    fn step(&mut self) -> Result<Step> {
+        loop {
+            match self.state {
+                BodyState::Header => {
+                    if self.input.is_empty() && self.body_done {
+                        self.state = BodyState::Done;
+                        continue;
+                    }
+                    if self.input.len() >= 2 && self.input[..2] != [ID1, ID2] {
+                        bail!("wrong id values");
+                    }
+                    let Some(len) = header_len(&self.input) else {
+                        return Ok(Step::NeedInput);
+                    };
+                    GzipReader::new(&self.input[10..len]).parse_header(&self.input[..10])?;
+                    self.input.drain(..len);
+                    self.state = BodyState::Deflate;
+                }
+                BodyState::Deflate => {
+                    let (consumed, status) =
+                        self.inflate
+                            .inflate(&self.input, &mut self.output, self.chunk_size)?;
+                    self.input.drain(..consumed);
+                    let output = std::mem::take(self.output.get_mut());
+                    if status == InflateStatus::StreamEnd {
+                        self.inflate.align_to_byte();
+                        self.state = BodyState::Footer;
+                    }
+                    if !output.is_empty() {
+                        return Ok(Step::Output(output.into()));
+                    }
+                    if status == InflateStatus::NeedInput {
+                        return Ok(Step::NeedInput);
+                    }
+                }
+                BodyState::Footer => {
+                    if self.input.len() < FOOTER_SIZE {
+                        return Ok(Step::NeedInput);
+                    }
+                    let footer = self.input.drain(..FOOTER_SIZE).collect::<Vec<_>>();
+                    let crc32 = u32::from_le_bytes(footer[..4].try_into().unwrap());
+                    let size = u32::from_le_bytes(footer[4..].try_into().unwrap());
+                    if self.output.byte_count() as u32 != size {
+                        bail!("length check failed");
+                    }
+                    if self.output.checksum() != crc32 {
+                        bail!("crc32 check failed");
+                    }
+                    self.output.reset();
+                    self.inflate.reset();
+                    self.state = BodyState::Header;
+                }
+                BodyState::Done => return Ok(Step::Done),
+            }
+        }
+    }
+}
+
+impl<S, E> Stream for GzipBodyDecoder<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.step() {
+                Ok(Step::Output(data)) => return Poll::Ready(Some(Ok(data))),
+                Ok(Step::Done) => return Poll::Ready(None),
+                Ok(Step::NeedInput) => {}
+                Err(err) => {
+                    self.state = BodyState::Done;
+                    return Poll::Ready(Some(Err(into_io_error(err))));
+                }
+            }
+            if self.body_done {
+                self.state = BodyState::Done;
+                return Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())));
+            }
+            match Pin::new(&mut self.body).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.input.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(err))) => {
+                    self.state = BodyState::Done;
+                    return Poll::Ready(Some(Err(io::Error::other(err))));
+                }
+                Poll::Ready(None) => self.body_done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
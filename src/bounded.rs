@@ -0,0 +1,61 @@
+#![forbid(unsafe_code)]
+
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+
+use crate::decoder::PartialOutput;
+use crate::gzip::GzipDecoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Sizes of a gzip stream decompressed in full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecompressStats {
+    pub compressed_size: u64,
+    pub decompressed_size: u64,
+}
+
+/// Outcome of `decompress_bounded`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bounded {
+    /// The whole stream was decompressed and checked.
+    Complete(DecompressStats),
+    /// The stream decompresses to more than the cap, of which `written` bytes were
+    /// written. The rest of the stream is not checked.
+    Truncated { written: u64 },
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Decompress at most `max_out` bytes of `input` to `output`, e.g. to preview the
+/// start of a compressed log. Reaching the cap is not an error, the result tells
+/// whether it was. Errors are reported as by `decompress`.
+pub fn decompress_bounded<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    max_out: u64,
+) -> Result<Bounded> {
+    let mut decoder = GzipDecoder::new(input);
+    loop {
+        let bytes_written = decoder.total_out();
+        let data = decoder
+            .fill_buffer()
+            .map_err(|err| err.context(PartialOutput { bytes_written }))?;
+        if data.is_empty() {
+            break;
+        }
+        let room = max_out - bytes_written;
+        if data.len() as u64 > room {
+            output.write_all(&data[..room as usize])?;
+            return Ok(Bounded::Truncated { written: max_out });
+        }
+        output.write_all(data)?;
+        let size = data.len();
+        decoder.consume_buffer(size);
+    }
+    Ok(Bounded::Complete(DecompressStats {
+        compressed_size: decoder.total_in(),
+        decompressed_size: decoder.total_out(),
+    }))
+}
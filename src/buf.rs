@@ -0,0 +1,39 @@
+#![forbid(unsafe_code)]
+
+use std::io::BufRead;
+
+use anyhow::Result;
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::gzip::GzipDecoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Decompress the gzip data in `input`, e.g. a `Bytes` holding a request body.
+pub fn decompress_bytes<B: Buf>(input: B) -> Result<BytesMut> {
+    let mut output = BytesMut::new();
+    crate::decompress_to(input.reader(), &mut output)?;
+    Ok(output)
+}
+
+/// Decompress the gzip data in `input` lazily, in chunks of about 64K. The chunks
+/// can be handed out as they come, e.g. as the frames of a response body.
+///
+/// The iterator stops after the first error.
+pub fn decompress_chunks<B: Buf>(input: B) -> impl Iterator<Item = Result<Bytes>> {
+    let mut decoder = Some(GzipDecoder::new(input.reader()));
+    std::iter::from_fn(move || {
+        let result = decoder.as_mut()?.fill_buffer().map(Bytes::copy_from_slice);
+        match &result {
+            Ok(chunk) if !chunk.is_empty() => {
+                decoder.as_mut()?.consume(chunk.len());
+                Some(result)
+            }
+            Ok(_) => None,
+            Err(_) => {
+                decoder = None;
+                Some(result)
+            }
+        }
+    })
+}
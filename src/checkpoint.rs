@@ -0,0 +1,194 @@
+#![forbid(unsafe_code)]
+
+use std::io::{Read, Write};
+
+use anyhow::{bail, ensure, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::bit_reader::BitSequence;
+
+////////////////////////////////////////////////////////////////////////////////
+
+const MAGIC: &[u8; 4] = b"RGZC";
+const VERSION: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Framing {
+    Raw,
+    GzipHeader,
+    GzipBody,
+    GzipDone,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum BlockCheckpoint {
+    Header,
+    Stored {
+        remaining: usize,
+        is_final: bool,
+    },
+    Huffman {
+        lit_len_lengths: Vec<u8>,
+        dist_lengths: Vec<u8>,
+        is_final: bool,
+    },
+    Done,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Block boundary reported to the callback of `GzipDecoder::set_checkpoint_callback`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointInfo {
+    /// Offset of the byte holding the first bit of the next block.
+    pub compressed_offset: u64,
+    /// Offset of the first bit of the next block, in bits from the start of the input.
+    pub bit_offset: u64,
+    pub uncompressed_offset: u64,
+    /// CRC-32 of the window of the member at this point.
+    pub window_hash: u32,
+}
+
+/// Full state of a decoder, restorable with the `restore` constructor of the
+/// decoder it was taken from.
+///
+/// The reader passed to `restore` must continue exactly where the reader of the
+/// checkpointed decoder stopped, that is at `input_offset()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub(crate) framing: Framing,
+    pub(crate) input_offset: u64,
+    pub(crate) pending_bits: BitSequence,
+    pub(crate) block: BlockCheckpoint,
+    pub(crate) byte_count: u64,
+    pub(crate) checksum: u32,
+    pub(crate) window: Vec<u8>,
+}
+
+impl Checkpoint {
+    /// Offset in the input at which decoding resumes.
+    pub fn input_offset(&self) -> u64 {
+        self.input_offset
+    }
+
+    /// Number of decompressed bytes of the current stream (or gzip member) so far.
+    pub fn byte_count(&self) -> u64 {
+        self.byte_count
+    }
+
+    /// Checksum of the decompressed bytes of the current stream (or gzip member) so far.
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u8(VERSION)?;
+        writer.write_u8(match self.framing {
+            Framing::Raw => 0,
+            Framing::GzipHeader => 1,
+            Framing::GzipBody => 2,
+            Framing::GzipDone => 3,
+        })?;
+        writer.write_u64::<LittleEndian>(self.input_offset)?;
+        writer.write_u16::<LittleEndian>(self.pending_bits.bits())?;
+        writer.write_u8(self.pending_bits.len())?;
+        match &self.block {
+            BlockCheckpoint::Header => writer.write_u8(0)?,
+            BlockCheckpoint::Stored {
+                remaining,
+                is_final,
+            } => {
+                writer.write_u8(1)?;
+                writer.write_u8(*is_final as u8)?;
+                writer.write_u16::<LittleEndian>(*remaining as u16)?;
+            }
+            BlockCheckpoint::Huffman {
+                lit_len_lengths,
+                dist_lengths,
+                is_final,
+            } => {
+                writer.write_u8(2)?;
+                writer.write_u8(*is_final as u8)?;
+                for lengths in [lit_len_lengths, dist_lengths] {
+                    writer.write_u16::<LittleEndian>(lengths.len() as u16)?;
+                    writer.write_all(lengths)?;
+                }
+            }
+            BlockCheckpoint::Done => writer.write_u8(3)?,
+        }
+        writer.write_u64::<LittleEndian>(self.byte_count)?;
+        writer.write_u32::<LittleEndian>(self.checksum)?;
+        writer.write_u32::<LittleEndian>(self.window.len() as u32)?;
+        writer.write_all(&self.window)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        ensure!(&magic == MAGIC, "not a checkpoint");
+        ensure!(
+            reader.read_u8()? == VERSION,
+            "unsupported checkpoint version"
+        );
+        let framing = match reader.read_u8()? {
+            0 => Framing::Raw,
+            1 => Framing::GzipHeader,
+            2 => Framing::GzipBody,
+            3 => Framing::GzipDone,
+            _ => bail!("invalid checkpoint framing"),
+        };
+        let input_offset = reader.read_u64::<LittleEndian>()?;
+        let bits = reader.read_u16::<LittleEndian>()?;
+        let len = reader.read_u8()?;
+        ensure!(len < 16, "invalid checkpoint pending bits");
+        let pending_bits = BitSequence::new(bits, len);
+        let block = match reader.read_u8()? {
+            0 => BlockCheckpoint::Header,
+            1 => {
+                let is_final = reader.read_u8()? != 0;
+                let remaining = reader.read_u16::<LittleEndian>()? as usize;
+                BlockCheckpoint::Stored {
+                    remaining,
+                    is_final,
+                }
+            }
+            2 => {
+                let is_final = reader.read_u8()? != 0;
+                let mut read_lengths = || -> Result<Vec<u8>> {
+                    let mut lengths = vec![0; reader.read_u16::<LittleEndian>()? as usize];
+                    reader.read_exact(&mut lengths)?;
+                    Ok(lengths)
+                };
+                let lit_len_lengths = read_lengths()?;
+                let dist_lengths = read_lengths()?;
+                BlockCheckpoint::Huffman {
+                    lit_len_lengths,
+                    dist_lengths,
+                    is_final,
+                }
+            }
+            3 => BlockCheckpoint::Done,
+            _ => bail!("invalid checkpoint block state"),
+        };
+        let byte_count = reader.read_u64::<LittleEndian>()?;
+        let checksum = reader.read_u32::<LittleEndian>()?;
+        let window_len = reader.read_u32::<LittleEndian>()? as usize;
+        ensure!(
+            window_len <= crate::WINDOW_SIZE,
+            "invalid checkpoint window size"
+        );
+        let mut window = vec![0; window_len];
+        reader.read_exact(&mut window)?;
+        Ok(Self {
+            framing,
+            input_offset,
+            pending_bits,
+            block,
+            byte_count,
+            checksum,
+            window,
+        })
+    }
+}
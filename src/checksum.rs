@@ -0,0 +1,338 @@
+#![forbid(unsafe_code)]
+
+#[cfg(any(feature = "crc32", feature = "crc32c"))]
+use crc::{Crc, Digest};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "crc32")]
+static CRC_32: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// A running checksum over the decompressed data.
+pub trait Checksum {
+    fn update(&mut self, data: &[u8]);
+
+    /// Value of the checksum over all the data seen since the last reset.
+    fn value(&self) -> u32;
+
+    fn reset(&mut self);
+}
+
+/// Checksum of the decoders and writers created without one: crc32, or none when
+/// the `crc32` feature is disabled.
+#[cfg(feature = "crc32")]
+pub type DefaultChecksum = Crc32;
+#[cfg(not(feature = "crc32"))]
+pub type DefaultChecksum = NoChecksum;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// CRC-32 as used by the gzip footer.
+#[cfg(feature = "crc32")]
+#[derive(Clone)]
+pub struct Crc32 {
+    digest: Digest<'static, u32>,
+}
+
+#[cfg(feature = "crc32")]
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self {
+            digest: CRC_32.digest(),
+        }
+    }
+}
+
+#[cfg(feature = "crc32")]
+impl Crc32 {
+    /// Continue a CRC-32 computation which has reached `value` so far.
+    pub fn resume(value: u32) -> Self {
+        Self {
+            digest: CRC_32.digest_with_initial((value ^ 0xffffffff).reverse_bits()),
+        }
+    }
+}
+
+#[cfg(feature = "crc32")]
+impl Checksum for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        let data = match crate::simd::crc32(self.value(), data) {
+            Some((value, folded)) => {
+                *self = Self::resume(value);
+                &data[folded..]
+            }
+            None => data,
+        };
+        self.digest.update(data);
+    }
+
+    fn value(&self) -> u32 {
+        self.digest.clone().finalize()
+    }
+
+    fn reset(&mut self) {
+        self.digest = CRC_32.digest();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Placeholder for decoders whose checksum is computed elsewhere, or not at all.
+#[derive(Clone, Default)]
+pub struct NoChecksum;
+
+impl Checksum for NoChecksum {
+    fn update(&mut self, _data: &[u8]) {}
+
+    fn value(&self) -> u32 {
+        0
+    }
+
+    fn reset(&mut self) {}
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "crc32c")]
+static CRC_32C: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISCSI);
+
+/// CRC-32C (Castagnoli).
+#[cfg(feature = "crc32c")]
+#[derive(Clone)]
+pub struct Crc32c {
+    digest: Digest<'static, u32>,
+}
+
+#[cfg(feature = "crc32c")]
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self {
+            digest: CRC_32C.digest(),
+        }
+    }
+}
+
+#[cfg(feature = "crc32c")]
+impl Checksum for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.digest.update(data);
+    }
+
+    fn value(&self) -> u32 {
+        self.digest.clone().finalize()
+    }
+
+    fn reset(&mut self) {
+        self.digest = CRC_32C.digest();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "zlib")]
+const ADLER_MOD: u32 = 65521;
+
+/// Bytes which can be summed before the sums may overflow.
+#[cfg(feature = "zlib")]
+const ADLER_CHUNK: usize = 5552;
+
+/// Adler-32 as used by the zlib trailer.
+#[cfg(feature = "zlib")]
+#[derive(Clone)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+#[cfg(feature = "zlib")]
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self { a: 1, b: 0 }
+    }
+}
+
+#[cfg(feature = "zlib")]
+impl Adler32 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continue an Adler-32 computation which has reached `value` so far.
+    pub fn resume(value: u32) -> Self {
+        Self {
+            a: value & 0xffff,
+            b: value >> 16,
+        }
+    }
+
+    /// Extend the checksum with that of the `len` bytes following the data seen so
+    /// far, whose checksum is `other`, as zlib's `adler32_combine` does.
+    pub fn combine(&mut self, other: &Adler32, len: u64) {
+        let modulo = ADLER_MOD as u64;
+        let rem = len % modulo;
+        let (a1, b1) = (self.a as u64, self.b as u64);
+        let (a2, b2) = (other.a as u64, other.b as u64);
+        self.a = ((a1 + a2 + modulo - 1) % modulo) as u32;
+        self.b = ((rem * a1 + b1 + b2 + modulo - rem) % modulo) as u32;
+    }
+
+    /// Value of the checksum, the same as `value`.
+    pub fn finalize(&self) -> u32 {
+        self.value()
+    }
+}
+
+#[cfg(feature = "zlib")]
+impl Checksum for Adler32 {
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(ADLER_CHUNK) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    fn value(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// XXH32 with a fixed seed.
+#[cfg(feature = "xxh32")]
+#[derive(Clone)]
+pub struct Xxh32 {
+    seed: u32,
+    state: xxhash_rust::xxh32::Xxh32,
+}
+
+#[cfg(feature = "xxh32")]
+impl Xxh32 {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            state: xxhash_rust::xxh32::Xxh32::new(seed),
+        }
+    }
+}
+
+#[cfg(feature = "xxh32")]
+impl Default for Xxh32 {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(feature = "xxh32")]
+impl Checksum for Xxh32 {
+    fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn value(&self) -> u32 {
+        self.state.digest()
+    }
+
+    fn reset(&mut self) {
+        self.state.reset(self.seed);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "crc32", feature = "crc32c"))]
+    fn check<C: Checksum>(mut checksum: C, expected: u32) {
+        checksum.update(b"1234");
+        checksum.update(b"56789");
+        assert_eq!(checksum.value(), expected);
+        checksum.reset();
+        checksum.update(b"123456789");
+        assert_eq!(checksum.value(), expected);
+    }
+
+    #[cfg(feature = "crc32")]
+    #[test]
+    fn crc32() {
+        check(Crc32::default(), 0xcbf43926);
+    }
+
+    #[cfg(feature = "crc32")]
+    #[test]
+    fn crc32_resume() {
+        let mut checksum = Crc32::default();
+        checksum.update(b"1234");
+        let mut checksum = Crc32::resume(checksum.value());
+        checksum.update(b"56789");
+        assert_eq!(checksum.value(), 0xcbf43926);
+        assert_eq!(Crc32::resume(0).value(), 0);
+    }
+
+    #[test]
+    fn no_checksum() {
+        let mut checksum = NoChecksum;
+        checksum.update(b"123456789");
+        assert_eq!(checksum.value(), 0);
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn adler32() {
+        let mut checksum = Adler32::default();
+        assert_eq!(checksum.value(), 1);
+        checksum.update(b"Wikipedia");
+        assert_eq!(checksum.value(), 0x11e60398);
+        checksum.reset();
+        checksum.update(&[0xff; 100_000]);
+        assert_eq!(checksum.value(), 0x149a302c);
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn adler32_combine() {
+        let data = [0xffu8; 100_000];
+        for split in [0, 1, 5552, 65521, 70_000, 100_000] {
+            let mut first = Adler32::new();
+            first.update(&data[..split]);
+            let mut second = Adler32::new();
+            second.update(&data[split..]);
+            first.combine(&second, (data.len() - split) as u64);
+            assert_eq!(first.finalize(), 0x149a302c);
+        }
+
+        let mut checksum = Adler32::new();
+        checksum.update(b"Wiki");
+        let mut checksum = Adler32::resume(checksum.value());
+        checksum.update(b"pedia");
+        assert_eq!(checksum.finalize(), 0x11e60398);
+    }
+
+    #[cfg(feature = "crc32c")]
+    #[test]
+    fn crc32c() {
+        check(Crc32c::default(), 0xe3069283);
+    }
+
+    #[cfg(feature = "xxh32")]
+    #[test]
+    fn xxh32() {
+        let mut checksum = Xxh32::default();
+        assert_eq!(checksum.value(), 0x02cc5d05);
+        checksum.update(b"abc");
+        assert_eq!(checksum.value(), 0x32d153ff);
+        checksum.reset();
+        assert_eq!(checksum.value(), 0x02cc5d05);
+    }
+}
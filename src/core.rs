@@ -0,0 +1,611 @@
+#![forbid(unsafe_code)]
+
+//! The inflate state machine. It does no I/O of its own: the caller hands it the
+//! input it has at hand and a `TrackingWriter`, which receives the output and keeps
+//! the window. The streaming decoders are adapters feeding it from a `BufRead`.
+
+use std::io::{self, BufRead, Write};
+
+use anyhow::{bail, ensure, Result};
+
+use crate::bit_reader::{BitReader, BitSequence};
+use crate::checkpoint::BlockCheckpoint;
+use crate::checksum::Checksum;
+use crate::deflate::{CompressionType, DeflateReader};
+use crate::huffman_coding::{
+    decode_litlen_distance_trees, fixed_litlen_distance_trees, DistanceToken, HuffmanCoding,
+    LitLenToken,
+};
+#[cfg(feature = "trace-decode")]
+use crate::trace::{TraceEvent, TraceSink, Tracer};
+use crate::tracking_writer::TrackingWriter;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InflateStatus {
+    /// The input ended in the middle of a block header or a symbol. Call again with
+    /// the unconsumed input followed by more.
+    NeedInput,
+    /// The output limit was reached.
+    OutputFull,
+    /// A block ended and another one follows.
+    BlockEnd,
+    /// The final block ended.
+    StreamEnd,
+}
+
+#[derive(Clone)]
+enum BlockState {
+    Header,
+    Stored {
+        remaining: usize,
+        is_final: bool,
+    },
+    Huffman {
+        lit_len: HuffmanCoding<LitLenToken>,
+        dist: HuffmanCoding<DistanceToken>,
+        is_final: bool,
+    },
+    Done,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// State of a raw deflate stream decoder working on input slices.
+///
+/// Input is consumed a whole block header or symbol at a time, so a call may leave
+/// a few bytes unconsumed, which must be passed again, followed by more input.
+#[derive(Clone)]
+pub struct InflateState {
+    pending: BitSequence,
+    block: BlockState,
+    block_type: Option<CompressionType>,
+    #[cfg(feature = "trace-decode")]
+    trace: Option<Tracer>,
+}
+
+impl Default for InflateState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InflateState {
+    pub fn new() -> Self {
+        Self {
+            pending: BitSequence::new(0, 0),
+            block: BlockState::Header,
+            block_type: None,
+            #[cfg(feature = "trace-decode")]
+            trace: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.block, BlockState::Done)
+    }
+
+    /// Whether the decoder stands between two blocks.
+    pub fn is_at_block_boundary(&self) -> bool {
+        matches!(self.block, BlockState::Header | BlockState::Done)
+    }
+
+    /// Type of the current block, or of the last one between blocks. Unknown for
+    /// a compressed block decoding was restored in the middle of.
+    pub fn block_type(&self) -> Option<CompressionType> {
+        self.block_type
+    }
+
+    /// Decode `bits` before the input handed to `inflate` next, as zlib's
+    /// `inflatePrime`, e.g. to resume at a bit offset. At most 16 bits can be
+    /// pending in all.
+    pub fn prime(&mut self, bits: BitSequence) -> Result<()> {
+        ensure!(
+            self.pending.len() + bits.len() <= 16,
+            "too many bits to prime"
+        );
+        self.pending = self.pending.concat(bits);
+        Ok(())
+    }
+
+    /// Bits of the consumed input which were not decoded yet, less than a byte
+    /// unless some were primed.
+    pub fn pending_bits(&self) -> u8 {
+        self.pending.len()
+    }
+
+    /// Decode `input` into `output`, until about `limit` bytes were written, a block
+    /// ends or the input runs out. Returns the number of input bytes consumed.
+    ///
+    /// A match may take the output up to 257 bytes past `limit`.
+    pub fn inflate<W: Write, C: Checksum>(
+        &mut self,
+        input: &[u8],
+        output: &mut TrackingWriter<W, C>,
+        limit: usize,
+    ) -> Result<(usize, InflateStatus)> {
+        let mut reader = DeflateReader::new(BitReader::with_pending(input, self.pending));
+        #[cfg(feature = "trace-decode")]
+        if let Some(tracer) = &mut self.trace {
+            tracer.start_input(input.len(), self.pending.len());
+        }
+        let status = self.run(&mut reader, output, limit);
+        #[cfg(feature = "trace-decode")]
+        if let Some(tracer) = &mut self.trace {
+            let rdr = reader.bit_reader();
+            let bit_offset = tracer.bit_offset(rdr.get_ref().len(), rdr.pending().len());
+            if let Err(err) = &status {
+                tracer.record(TraceEvent::Error {
+                    bit_offset,
+                    message: format!("{:#}", err),
+                });
+            }
+            tracer.position = bit_offset;
+        }
+        self.pending = reader.bit_reader().pending();
+        Ok((input.len() - reader.bit_reader().get_ref().len(), status?))
+    }
+
+    /// Record a trace of the decoding to `sink`: the block headers, the trees
+    /// and the first `max_symbols` symbols of each block.
+    #[cfg(feature = "trace-decode")]
+    pub fn set_trace<S: TraceSink + 'static>(&mut self, sink: S, max_symbols: usize) {
+        self.trace = Some(Tracer::new(sink, max_symbols));
+    }
+
+    #[cfg(feature = "trace-decode")]
+    pub(crate) fn record_trace(&mut self, event: TraceEvent) {
+        if let Some(tracer) = &mut self.trace {
+            tracer.record(event);
+        }
+    }
+
+    /// Set the bit offset of the next unread bit reported in the trace, which
+    /// otherwise counts the bits handed to `inflate` since `set_trace`.
+    #[cfg(feature = "trace-decode")]
+    pub fn set_trace_position(&mut self, bit_offset: u64) {
+        if let Some(tracer) = &mut self.trace {
+            tracer.position = bit_offset;
+        }
+    }
+
+    /// Drop the bits left of the current byte, e.g. at the end of the stream.
+    /// Returns the number of bits dropped.
+    pub fn align_to_byte(&mut self) -> u8 {
+        std::mem::replace(&mut self.pending, BitSequence::new(0, 0)).len()
+    }
+
+    pub(crate) fn pending(&self) -> BitSequence {
+        self.pending
+    }
+
+    /// Start decoding a new stream. The pending bits are kept, see `align_to_byte`.
+    pub fn reset(&mut self) {
+        self.block = BlockState::Header;
+    }
+
+    pub(crate) fn block_checkpoint(&self) -> BlockCheckpoint {
+        match &self.block {
+            BlockState::Header => BlockCheckpoint::Header,
+            BlockState::Stored {
+                remaining,
+                is_final,
+            } => BlockCheckpoint::Stored {
+                remaining: *remaining,
+                is_final: *is_final,
+            },
+            BlockState::Huffman {
+                lit_len,
+                dist,
+                is_final,
+            } => BlockCheckpoint::Huffman {
+                lit_len_lengths: lit_len.lengths().to_vec(),
+                dist_lengths: dist.lengths().to_vec(),
+                is_final: *is_final,
+            },
+            BlockState::Done => BlockCheckpoint::Done,
+        }
+    }
+
+    pub(crate) fn from_checkpoint(pending: BitSequence, block: &BlockCheckpoint) -> Result<Self> {
+        let block = match block {
+            BlockCheckpoint::Header => BlockState::Header,
+            BlockCheckpoint::Stored {
+                remaining,
+                is_final,
+            } => BlockState::Stored {
+                remaining: *remaining,
+                is_final: *is_final,
+            },
+            BlockCheckpoint::Huffman {
+                lit_len_lengths,
+                dist_lengths,
+                is_final,
+            } => BlockState::Huffman {
+                lit_len: HuffmanCoding::from_lengths(lit_len_lengths)?,
+                dist: HuffmanCoding::from_lengths(dist_lengths)?,
+                is_final: *is_final,
+            },
+            BlockCheckpoint::Done => BlockState::Done,
+        };
+        let block_type = match block {
+            BlockState::Stored { .. } => Some(CompressionType::Uncompressed),
+            _ => None,
+        };
+        Ok(Self {
+            pending,
+            block,
+            block_type,
+            #[cfg(feature = "trace-decode")]
+            trace: None,
+        })
+    }
+
+    fn run<W: Write, C: Checksum>(
+        &mut self,
+        reader: &mut DeflateReader<&[u8]>,
+        output: &mut TrackingWriter<W, C>,
+        limit: usize,
+    ) -> Result<InflateStatus> {
+        let start = output.byte_count();
+        loop {
+            let available = limit.saturating_sub(output.byte_count() - start);
+            match &mut self.block {
+                BlockState::Header => {
+                    let saved = reader.clone();
+                    let result = read_block_header(reader);
+                    #[cfg(feature = "trace-decode")]
+                    if let Some(tracer) = &mut self.trace {
+                        if !matches!(&result, Err(err) if is_eof(err)) {
+                            trace_block(
+                                tracer,
+                                &saved,
+                                result.as_ref().ok().map(|(_, block)| block),
+                            );
+                        }
+                    }
+                    match result {
+                        Ok((block_type, block)) => {
+                            self.block = block;
+                            self.block_type = Some(block_type);
+                        }
+                        Err(err) if is_eof(&err) => {
+                            *reader = saved;
+                            return Ok(InflateStatus::NeedInput);
+                        }
+                        Err(err) => return Err(err),
+                    }
+                    if let BlockState::Stored {
+                        remaining: 0,
+                        is_final,
+                    } = self.block
+                    {
+                        return Ok(self.finish_block(is_final));
+                    }
+                }
+                BlockState::Stored {
+                    remaining,
+                    is_final,
+                } => {
+                    let is_final = *is_final;
+                    reader.bit_reader_mut().align_to_byte();
+                    let data = reader.bit_reader_mut().get_mut();
+                    if data.is_empty() {
+                        return Ok(InflateStatus::NeedInput);
+                    }
+                    if available == 0 {
+                        return Ok(InflateStatus::OutputFull);
+                    }
+                    let size = (*remaining).min(data.len()).min(available);
+                    output.write_all(&data[..size])?;
+                    *data = &data[size..];
+                    *remaining -= size;
+                    if *remaining == 0 {
+                        return Ok(self.finish_block(is_final));
+                    }
+                }
+                BlockState::Huffman {
+                    lit_len,
+                    dist,
+                    is_final,
+                } => {
+                    let is_final = *is_final;
+                    #[cfg(feature = "trace-decode")]
+                    if let Some(tracer) = self.trace.as_mut().filter(|t| t.wants_symbols()) {
+                        let status = trace_symbols(
+                            reader.bit_reader_mut(),
+                            lit_len,
+                            dist,
+                            output,
+                            tracer,
+                            available,
+                        )?;
+                        match status {
+                            Some(InflateStatus::BlockEnd) => return Ok(self.finish_block(is_final)),
+                            Some(status) => return Ok(status),
+                            None => continue,
+                        }
+                    }
+                    let mut literals = Vec::new();
+                    let result = decode_symbols(
+                        reader.bit_reader_mut(),
+                        lit_len,
+                        dist,
+                        output,
+                        &mut literals,
+                        available,
+                    );
+                    output.write_all(&literals)?;
+                    return match result? {
+                        InflateStatus::BlockEnd => Ok(self.finish_block(is_final)),
+                        status => Ok(status),
+                    };
+                }
+                BlockState::Done => return Ok(InflateStatus::StreamEnd),
+            }
+        }
+    }
+
+    fn finish_block(&mut self, is_final: bool) -> InflateStatus {
+        if is_final {
+            self.block = BlockState::Done;
+            InflateStatus::StreamEnd
+        } else {
+            self.block = BlockState::Header;
+            InflateStatus::BlockEnd
+        }
+    }
+}
+
+fn read_block_header(reader: &mut DeflateReader<&[u8]>) -> Result<(CompressionType, BlockState)> {
+    let (header, rdr) = match reader.next_block() {
+        Some(block) => block?,
+        None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    };
+    let is_final = header.is_final;
+    let block = match header.compression_type {
+        CompressionType::Uncompressed => {
+            rdr.align_to_byte();
+            let lengths = rdr.read_bits_u32(32)?;
+            let length = lengths as u16;
+            if length != !(lengths >> 16) as u16 {
+                bail!("nlen check failed");
+            }
+            BlockState::Stored {
+                remaining: length as usize,
+                is_final,
+            }
+        }
+        CompressionType::FixedTree | CompressionType::DynamicTree => {
+            let (lit_len, dist) = match header.compression_type {
+                CompressionType::FixedTree => fixed_litlen_distance_trees()?,
+                _ => decode_litlen_distance_trees(rdr)?,
+            };
+            BlockState::Huffman {
+                lit_len,
+                dist,
+                is_final,
+            }
+        }
+        CompressionType::Reserved => bail!("unsupported block type"),
+    };
+    Ok((header.compression_type, block))
+}
+
+/// Decode symbols until the end of the block, until `limit` bytes are decoded or
+/// until the input ends. Runs of literals are gathered in `literals` and written
+/// out together with the match following them.
+fn decode_symbols<W: Write, C: Checksum>(
+    rdr: &mut BitReader<&[u8]>,
+    lit_len: &HuffmanCoding<LitLenToken>,
+    dist: &HuffmanCoding<DistanceToken>,
+    writer: &mut TrackingWriter<W, C>,
+    literals: &mut Vec<u8>,
+    limit: usize,
+) -> Result<InflateStatus> {
+    let start = writer.byte_count();
+    while writer.byte_count() - start + literals.len() < limit {
+        let saved = rdr.clone();
+        match read_symbol(rdr, lit_len, dist) {
+            Ok(Symbol::Literal(value)) => literals.push(value),
+            Ok(Symbol::Match { distance, length }) => {
+                writer.write_literals_and_previous(literals, distance, length)?;
+                literals.clear();
+            }
+            Ok(Symbol::EndOfBlock) => return Ok(InflateStatus::BlockEnd),
+            Err(err) if is_eof(&err) => {
+                *rdr = saved;
+                return Ok(InflateStatus::NeedInput);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(InflateStatus::OutputFull)
+}
+
+pub(crate) enum Symbol {
+    Literal(u8),
+    Match { distance: usize, length: usize },
+    EndOfBlock,
+}
+
+pub(crate) fn read_symbol<T: BufRead>(
+    rdr: &mut BitReader<T>,
+    lit_len: &HuffmanCoding<LitLenToken>,
+    dist: &HuffmanCoding<DistanceToken>,
+) -> Result<Symbol> {
+    Ok(match lit_len.read_symbol(rdr)? {
+        LitLenToken::Literal(value) => Symbol::Literal(value),
+        LitLenToken::Length { base, extra_bits } => {
+            let length = base + rdr.read_bits(extra_bits)?.bits();
+            let token = dist.read_symbol(rdr)?;
+            let distance = token.base as usize + rdr.read_bits(token.extra_bits)?.bits() as usize;
+            Symbol::Match {
+                distance,
+                length: length as usize,
+            }
+        }
+        LitLenToken::EndOfBlock => Symbol::EndOfBlock,
+    })
+}
+
+/// Decode symbols one at a time and record them, as long as the tracer wants
+/// them. Returns `None` once it does not, and the status otherwise.
+#[cfg(feature = "trace-decode")]
+fn trace_symbols<W: Write, C: Checksum>(
+    rdr: &mut BitReader<&[u8]>,
+    lit_len: &HuffmanCoding<LitLenToken>,
+    dist: &HuffmanCoding<DistanceToken>,
+    writer: &mut TrackingWriter<W, C>,
+    tracer: &mut Tracer,
+    limit: usize,
+) -> Result<Option<InflateStatus>> {
+    let start = writer.byte_count();
+    while tracer.wants_symbols() {
+        if writer.byte_count() - start >= limit {
+            return Ok(Some(InflateStatus::OutputFull));
+        }
+        let saved = rdr.clone();
+        match read_symbol(rdr, lit_len, dist) {
+            Ok(Symbol::Literal(value)) => {
+                tracer.record(TraceEvent::Literal(value));
+                writer.write_all(&[value])?;
+            }
+            Ok(Symbol::Match { distance, length }) => {
+                tracer.record(TraceEvent::Match { length, distance });
+                writer.write_previous(distance, length)?;
+            }
+            Ok(Symbol::EndOfBlock) => {
+                tracer.record(TraceEvent::EndOfBlock);
+                return Ok(Some(InflateStatus::BlockEnd));
+            }
+            Err(err) if is_eof(&err) => {
+                *rdr = saved;
+                return Ok(Some(InflateStatus::NeedInput));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// Record the header of the block `reader` stands at, and the summary of `block`
+/// if it could be read.
+#[cfg(feature = "trace-decode")]
+fn trace_block(tracer: &mut Tracer, reader: &DeflateReader<&[u8]>, block: Option<&BlockState>) {
+    let rdr = reader.bit_reader();
+    let bit_offset = tracer.bit_offset(rdr.get_ref().len(), rdr.pending().len());
+    if let Some(Ok((header, _))) = reader.clone().next_block() {
+        tracer.record(TraceEvent::BlockHeader {
+            bit_offset,
+            is_final: header.is_final,
+            block_type: header.compression_type,
+        });
+    }
+    match block {
+        Some(BlockState::Stored { remaining, .. }) => {
+            tracer.record(TraceEvent::StoredLength(*remaining))
+        }
+        Some(BlockState::Huffman { lit_len, dist, .. }) => {
+            let summary = |lengths: &[u8]| {
+                let used = lengths.iter().filter(|&&len| len > 0).count();
+                (used, lengths.iter().copied().max().unwrap_or(0))
+            };
+            let (lit_len_codes, lit_len_max_length) = summary(lit_len.lengths());
+            let (dist_codes, dist_max_length) = summary(dist.lengths());
+            tracer.record(TraceEvent::Trees {
+                lit_len_codes,
+                lit_len_max_length,
+                dist_codes,
+                dist_max_length,
+            });
+        }
+        _ => {}
+    }
+}
+
+fn is_eof(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use super::*;
+
+    /// A raw deflate stream made of a single dynamic block, and its decompressed data.
+    fn sample() -> (Vec<u8>, Vec<u8>) {
+        let gzip: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+        let mut expected = Vec::new();
+        crate::decompress(gzip, &mut expected).unwrap();
+        // Skip the 10 byte header (no optional fields) and the 8 byte footer.
+        (gzip[10..gzip.len() - 8].to_vec(), expected)
+    }
+
+    #[test]
+    fn byte_by_byte() -> Result<()> {
+        let (input, expected) = sample();
+        let mut state = InflateState::new();
+        let mut output = TrackingWriter::new(Vec::new());
+        let mut pos = 0;
+        let mut end = 0;
+        loop {
+            let (consumed, status) = state.inflate(&input[pos..end], &mut output, usize::MAX)?;
+            pos += consumed;
+            match status {
+                InflateStatus::NeedInput => {
+                    assert!(end < input.len());
+                    end += 1;
+                }
+                InflateStatus::StreamEnd => break,
+                _ => {}
+            }
+        }
+        assert_eq!(pos, input.len());
+        assert_eq!(output.into_inner(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_block() -> Result<()> {
+        let input = [203, 72, 205, 201, 201, 87, 200, 64, 39, 185, 0];
+        let mut state = InflateState::new();
+        let mut output = TrackingWriter::new(Vec::new());
+        let (consumed, status) = state.inflate(&input, &mut output, usize::MAX)?;
+        assert_eq!((consumed, status), (input.len(), InflateStatus::StreamEnd));
+        assert_eq!(output.into_inner(), b"hello hello hello hello\n");
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_block_invalid_distance() {
+        // BFINAL, BTYPE = 01, length symbol 257 (0000001) and distance symbol 30
+        // (11110), the codes most significant bit first.
+        let input = [0b00000011, 0b00111110, 0];
+        let mut state = InflateState::new();
+        let mut output = TrackingWriter::new(Vec::new());
+        let err = state.inflate(&input, &mut output, usize::MAX).unwrap_err();
+        assert_eq!(err.to_string(), "invalid distance code 30");
+    }
+
+    #[test]
+    fn output_limit() -> Result<()> {
+        let (input, expected) = sample();
+        let mut state = InflateState::new();
+        let mut output = TrackingWriter::new(Vec::new());
+        let mut pos = 0;
+        while !state.is_finished() {
+            let before = output.byte_count();
+            let (consumed, status) = state.inflate(&input[pos..], &mut output, 100)?;
+            assert_ne!(status, InflateStatus::NeedInput);
+            assert!(output.byte_count() - before < 100 + 258);
+            pos += consumed;
+        }
+        assert_eq!(pos, input.len());
+        assert_eq!(output.into_inner(), expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, BufRead, Read};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reader counting the bytes consumed from the inner reader.
+#[derive(Clone)]
+pub struct CountingReader<T> {
+    inner: T,
+    count: u64,
+}
+
+impl<T> CountingReader<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_count(inner, 0)
+    }
+
+    pub fn with_count(inner: T, count: u64) -> Self {
+        Self { inner, count }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read> Read for CountingReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.count += size as u64;
+        Ok(size)
+    }
+}
+
+impl<T: BufRead> BufRead for CountingReader<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+        self.count += amount as u64;
+    }
+}
@@ -0,0 +1,604 @@
+#![forbid(unsafe_code)]
+
+use std::fmt;
+use std::io::{self, BufRead, IoSlice, Read, Write};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::checkpoint::{Checkpoint, Framing};
+use crate::checksum::{Checksum, DefaultChecksum};
+use crate::core::{InflateState, InflateStatus};
+use crate::counting_reader::CountingReader;
+use crate::deflate::BlockUsage;
+#[cfg(all(feature = "gzip", feature = "trace-decode"))]
+use crate::trace::TraceEvent;
+#[cfg(feature = "trace-decode")]
+use crate::trace::TraceSink;
+use crate::tracking_writer::{TrackingWriter, HISTORY_SIZE};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Size of the sliding window, the maximum distance a deflate match can reach back.
+pub const WINDOW_SIZE: usize = HISTORY_SIZE;
+
+/// Decoding pauses once this many bytes are buffered, by default.
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 1 << 16;
+
+/// LEN and NLEN of an empty stored block, which zlib writes on a full flush.
+#[cfg(feature = "gzip")]
+const SYNC_MARKER: [u8; 4] = [0, 0, 0xff, 0xff];
+
+/// Start of a gzip member compressed with deflate.
+#[cfg(feature = "gzip")]
+const MEMBER_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// Point of the input decoding can restart from, see `DeflateDecoder::skip_to_sync_point`.
+#[cfg(feature = "gzip")]
+pub(crate) enum SyncPoint {
+    Block,
+    Member,
+}
+
+/// Reader whose buffer can be extended past the end of the buffer of the inner
+/// reader, for the input the core needs in one piece.
+#[derive(Clone)]
+struct SpliceReader<R> {
+    inner: R,
+    carry: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> SpliceReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            carry: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Make the next `fill_buf` return the unconsumed part of the current buffer
+    /// followed by one more byte. Returns false if the inner reader is over.
+    fn extend_buffer(&mut self) -> io::Result<bool> {
+        if self.pos == self.carry.len() {
+            self.carry.clear();
+            self.pos = 0;
+            let buf = self.inner.fill_buf()?;
+            self.carry.extend_from_slice(buf);
+            let size = buf.len();
+            self.inner.consume(size);
+        }
+        let buf = self.inner.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        self.carry.push(buf[0]);
+        self.inner.consume(1);
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for SpliceReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl<R: BufRead> BufRead for SpliceReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos < self.carry.len() {
+            return Ok(&self.carry[self.pos..]);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if self.pos < self.carry.len() {
+            self.pos = std::cmp::min(self.pos + amount, self.carry.len());
+        } else {
+            self.inner.consume(amount);
+        }
+    }
+}
+
+/// Context of the errors of `decompress` and the like, telling how much of the
+/// output was written before the failure. It can be had with `downcast_ref`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartialOutput {
+    /// Number of decompressed bytes written and flushed to the output.
+    pub bytes_written: u64,
+}
+
+impl fmt::Display for PartialOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed after writing {} bytes", self.bytes_written)
+    }
+}
+
+/// Buffer of the decoded data. When verifying, the data is dropped as it comes,
+/// only going through the checksum and the window of the `TrackingWriter`.
+#[derive(Clone)]
+struct OutputBuffer {
+    data: Vec<u8>,
+    discard: bool,
+}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        Self {
+            data: Vec::with_capacity(DEFAULT_BUFFER_SIZE),
+            discard: false,
+        }
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.discard {
+            self.data.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        if self.discard {
+            return Ok(bufs.iter().map(|buf| buf.len()).sum());
+        }
+        self.data.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Streaming decoder of a raw deflate stream.
+///
+/// Cloning the decoder deep copies its window and trees, so a clone can be used to
+/// speculatively decode ahead and be dropped afterwards.
+#[derive(Clone)]
+pub struct DeflateDecoder<R, C = DefaultChecksum> {
+    reader: CountingReader<SpliceReader<R>>,
+    state: InflateState,
+    writer: TrackingWriter<OutputBuffer, C>,
+    consumed: usize,
+    total_out: u64,
+    buffer_size: usize,
+    /// Error met after decoding the data in the buffer, returned once it is read.
+    error: Option<Arc<anyhow::Error>>,
+    /// Input offset and number of pending bits the decoder started from.
+    start: (u64, u8),
+    /// Number of blocks of the current stream decoded so far.
+    blocks: u64,
+    emitted: u64,
+    usage: BlockUsage,
+}
+
+impl<R: BufRead> DeflateDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_checksum(reader, DefaultChecksum::default())
+    }
+}
+
+impl<R: BufRead, C: Checksum> DeflateDecoder<R, C> {
+    pub fn with_checksum(reader: R, checksum: C) -> Self {
+        Self {
+            reader: CountingReader::new(SpliceReader::new(reader)),
+            state: InflateState::new(),
+            writer: TrackingWriter::with_checksum(OutputBuffer::default(), checksum),
+            consumed: 0,
+            total_out: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            error: None,
+            start: (0, 0),
+            blocks: 0,
+            emitted: 0,
+            usage: BlockUsage::default(),
+        }
+    }
+
+    /// Decode about `size` bytes at a time, 64K by default, a match possibly going
+    /// past it. This is what `fill_buf` returns, and so the size of the writes
+    /// `std::io::copy` makes.
+    pub fn set_buffer_size(&mut self, size: usize) {
+        assert!(size > 0, "buffer size must be positive");
+        self.buffer_size = size;
+        self.writer.get_mut().data.shrink_to(size);
+    }
+
+    /// Limit the window, and so the distance of the matches, to `size` bytes out
+    /// of 32K. Decoding fails on a match reaching further, but the window only
+    /// takes `size` bytes of memory, which along with a small buffer size suits
+    /// memory-constrained targets.
+    pub fn set_window_size(&mut self, size: usize) {
+        self.writer.set_window_size(size);
+    }
+
+    #[cfg(feature = "gzip")]
+    pub(crate) fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Record a trace of the decoding to `sink`: the block headers with their bit
+    /// offset in the input, a summary of the trees, the first `max_symbols`
+    /// symbols of each block and the error decoding fails with, if any.
+    #[cfg(feature = "trace-decode")]
+    pub fn set_trace<S: TraceSink + 'static>(&mut self, sink: S, max_symbols: usize) {
+        self.state.set_trace(sink, max_symbols);
+    }
+
+    #[cfg(all(feature = "gzip", feature = "trace-decode"))]
+    pub(crate) fn record_trace(&mut self, event: TraceEvent) {
+        self.state.record_trace(event);
+    }
+
+    /// Return at most `n` decoded bytes, decoding no more than needed for them, a
+    /// match possibly going past `n` and the rest being kept for the next call. The
+    /// bytes are consumed as with `read`, and an empty slice means the stream is over.
+    pub fn decode_up_to(&mut self, n: usize) -> Result<&[u8]> {
+        assert!(n > 0, "decode limit must be positive");
+        self.fill_buffer_up_to(n)?;
+        Ok(self.take_buffer(n))
+    }
+
+    /// Checksum of the data decoded so far.
+    pub fn checksum(&self) -> u32 {
+        self.writer.checksum()
+    }
+
+    /// Number of bytes decoded so far.
+    pub fn byte_count(&self) -> usize {
+        self.writer.byte_count()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.is_finished()
+    }
+
+    /// Number of bytes consumed from the reader so far.
+    pub fn total_in(&self) -> u64 {
+        self.reader.count()
+    }
+
+    /// Offset of the next unread bit, in bits from the start of the input. Unlike
+    /// `total_in`, it accounts for the bits already fetched but not decoded yet.
+    pub fn bit_position(&self) -> u64 {
+        self.total_in() * 8 - self.state.pending_bits() as u64
+    }
+
+    /// Number of input bytes fully decoded so far, i.e. the offset of the byte
+    /// holding the next unread bit.
+    pub fn position(&self) -> u64 {
+        self.bit_position() / 8
+    }
+
+    /// Number of decompressed bytes read from the decoder so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Number of input bits decoded by this decoder, i.e. `bit_position` minus the
+    /// position it started from, which is not zero after `restore`.
+    pub fn bits_consumed(&self) -> u64 {
+        let (offset, pending_bits) = self.start;
+        (self.total_in() - offset) * 8 + pending_bits as u64 - self.state.pending_bits() as u64
+    }
+
+    /// Number of decompressed bytes produced so far. Unlike `total_out`, it counts
+    /// the decoded data not read yet.
+    pub fn bytes_emitted(&self) -> u64 {
+        self.emitted
+    }
+
+    /// Index of the block being decoded in the current stream, or the number of
+    /// blocks once it is over. Blocks are counted from where decoding started, e.g.
+    /// from the checkpoint the decoder was restored from.
+    pub fn current_block_index(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Blocks of each type decoded so far in the current stream, and the bytes they
+    /// produced. A compressed block decoding was restored in the middle of is left
+    /// out, its type being unknown.
+    pub fn block_usage(&self) -> BlockUsage {
+        self.usage
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader.get_ref().inner
+    }
+
+    /// Return the underlying reader. Once the stream is finished, it is positioned
+    /// right after the end of the deflate data.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner().inner
+    }
+
+    /// Whether the decoder stands between two blocks and all the decoded data was read.
+    pub fn is_at_block_boundary(&self) -> bool {
+        self.state.is_at_block_boundary() && self.consumed == self.writer.get_ref().data.len()
+    }
+
+    /// The current window, oldest byte first. Only the first `window_len()` bytes are valid.
+    pub fn window_snapshot(&self) -> [u8; WINDOW_SIZE] {
+        self.writer.history_snapshot().0
+    }
+
+    pub fn window_len(&self) -> usize {
+        self.writer.history_len()
+    }
+
+    /// Capture the full decoder state. All the decoded data must be read beforehand.
+    pub fn checkpoint(&self) -> Result<Checkpoint> {
+        self.checkpoint_with(Framing::Raw)
+    }
+
+    /// Continue decoding from `checkpoint`. `checksum` must continue the checksum
+    /// recorded in the checkpoint, e.g. `Crc32::resume(checkpoint.checksum())`.
+    pub fn restore(reader: R, checkpoint: &Checkpoint, checksum: C) -> Result<Self> {
+        ensure!(
+            checkpoint.framing == Framing::Raw,
+            "checkpoint was taken from a different decoder"
+        );
+        Self::from_checkpoint(reader, checkpoint, checksum)
+    }
+
+    pub(crate) fn checkpoint_with(&self, framing: Framing) -> Result<Checkpoint> {
+        ensure!(
+            self.consumed == self.writer.get_ref().data.len(),
+            "decoded data must be read before taking a checkpoint"
+        );
+        let (window, window_len) = self.writer.history_snapshot();
+        Ok(Checkpoint {
+            framing,
+            input_offset: self.total_in(),
+            pending_bits: self.state.pending(),
+            block: self.state.block_checkpoint(),
+            byte_count: self.writer.byte_count() as u64,
+            checksum: self.writer.checksum(),
+            window: window[..window_len].to_vec(),
+        })
+    }
+
+    pub(crate) fn from_checkpoint(reader: R, checkpoint: &Checkpoint, checksum: C) -> Result<Self> {
+        Ok(Self {
+            reader: CountingReader::with_count(SpliceReader::new(reader), checkpoint.input_offset),
+            state: InflateState::from_checkpoint(checkpoint.pending_bits, &checkpoint.block)?,
+            writer: TrackingWriter::resume(
+                OutputBuffer::default(),
+                checksum,
+                &checkpoint.window,
+                checkpoint.byte_count as usize,
+            ),
+            consumed: 0,
+            total_out: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            error: None,
+            start: (checkpoint.input_offset, checkpoint.pending_bits.len()),
+            blocks: 0,
+            emitted: 0,
+            usage: BlockUsage::default(),
+        })
+    }
+
+    /// Start decoding a new stream from the current byte boundary.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn reset(&mut self) {
+        self.writer.reset();
+        self.state.reset();
+        self.blocks = 0;
+        self.usage = BlockUsage::default();
+    }
+
+    /// Only check the stream from now on: the decoded data goes through the
+    /// checksum and the window, and `fill_buffer` decodes to the end of the stream
+    /// without returning any.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn discard_output(&mut self) {
+        self.writer.get_mut().discard = true;
+    }
+
+    /// Make `dictionary` the window matches of the stream can refer to.
+    #[cfg(feature = "zlib")]
+    pub(crate) fn set_dictionary(&mut self, dictionary: &[u8]) {
+        self.writer.set_dictionary(dictionary);
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zlib"))]
+    pub(crate) fn borrow_reader_from_boundary(&mut self) -> &mut impl BufRead {
+        self.state.align_to_byte();
+        &mut self.reader
+    }
+
+    /// Skip the input up to the next block following an empty stored block, or up
+    /// to the next gzip magic bytes, whichever comes first, and make the next block
+    /// start a new stream. Returns where it stopped and the number of bytes skipped,
+    /// or `None` if the input ended first.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn skip_to_sync_point(&mut self) -> Result<Option<(SyncPoint, u64)>> {
+        self.state.align_to_byte();
+        self.state.reset();
+        self.writer.reset();
+        self.error = None;
+        self.blocks = 0;
+        self.usage = BlockUsage::default();
+        let mut skipped = 0;
+        loop {
+            let data = self.reader.fill_buf()?;
+            let found = (0..data.len()).find_map(|i| {
+                if data[i..].starts_with(&SYNC_MARKER) {
+                    Some((SyncPoint::Block, i + SYNC_MARKER.len()))
+                } else if data[i..].starts_with(&MEMBER_MAGIC) {
+                    Some((SyncPoint::Member, i))
+                } else {
+                    None
+                }
+            });
+            if let Some((point, len)) = found {
+                self.reader.consume(len);
+                return Ok(Some((point, skipped + len as u64)));
+            }
+
+            // The end of the buffer may be the start of a marker.
+            let len = data.len();
+            let keep = (1..std::cmp::min(len, 3) + 1)
+                .rev()
+                .find(|&n| {
+                    SYNC_MARKER.starts_with(&data[len - n..])
+                        || MEMBER_MAGIC.starts_with(&data[len - n..])
+                })
+                .unwrap_or(0);
+            self.reader.consume(len - keep);
+            skipped += (len - keep) as u64;
+            if keep == len && !self.reader.get_mut().extend_buffer()? {
+                self.reader.consume(len);
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Return the buffered input, to be handed out as is instead of being decoded.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn fill_input(&mut self) -> Result<&[u8]> {
+        Ok(self.reader.fill_buf()?)
+    }
+
+    /// Consume input handed out as is, counting it as output.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn consume_input(&mut self, amount: usize) {
+        self.reader.consume(amount);
+        self.total_out += amount as u64;
+        self.emitted += amount as u64;
+    }
+
+    /// Return the buffered decoded data, decoding more if the buffer is empty.
+    /// An empty slice means the stream is over.
+    ///
+    /// The data decoded before an error is returned first, and the error with the
+    /// next call.
+    pub(crate) fn fill_buffer(&mut self) -> Result<&[u8]> {
+        self.fill_buffer_up_to(self.buffer_size)
+    }
+
+    /// Same as `fill_buffer`, decoding about `limit` bytes instead of the buffer size.
+    pub(crate) fn fill_buffer_up_to(&mut self, limit: usize) -> Result<&[u8]> {
+        if self.consumed == self.writer.get_ref().data.len() {
+            if let Some(err) = self.error.take() {
+                return Err(Arc::try_unwrap(err).unwrap_or_else(|err| anyhow!("{:#}", err)));
+            }
+            self.writer.get_mut().data.clear();
+            self.consumed = 0;
+            if let Err(err) = self.decode_buffer(limit) {
+                if self.writer.get_ref().data.is_empty() {
+                    return Err(err);
+                }
+                self.error = Some(Arc::new(err));
+            }
+        }
+        Ok(&self.writer.get_ref().data[self.consumed..])
+    }
+
+    fn decode_buffer(&mut self, size: usize) -> Result<()> {
+        while !self.is_finished() && self.writer.get_ref().data.len() < size {
+            let limit = size - self.writer.get_ref().data.len();
+            #[cfg(feature = "trace-decode")]
+            self.state.set_trace_position(self.bit_position());
+            let input = self.reader.fill_buf()?;
+            let input_len = input.len();
+            let byte_count = self.writer.byte_count();
+            let result = self.state.inflate(input, &mut self.writer, limit);
+            let emitted = (self.writer.byte_count() - byte_count) as u64;
+            self.emitted += emitted;
+            if let Some(block_type) = self.state.block_type() {
+                let ended = matches!(
+                    result,
+                    Ok((_, InflateStatus::BlockEnd | InflateStatus::StreamEnd))
+                );
+                self.usage.record(block_type, emitted, ended);
+            }
+            let (size, status) = result?;
+            self.reader.consume(size);
+            match status {
+                InflateStatus::NeedInput => {
+                    // The next buffer of the reader follows, unless the core needs
+                    // the unconsumed bytes of this one and more in one piece.
+                    if input_len == 0
+                        || size < input_len && !self.reader.get_mut().extend_buffer()?
+                    {
+                        bail!("unexpected eof in deflate stream");
+                    }
+                }
+                InflateStatus::OutputFull => {}
+                InflateStatus::BlockEnd | InflateStatus::StreamEnd => {
+                    self.blocks += 1;
+                    if !self.writer.get_ref().data.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Consume up to `amount` bytes of the buffered decoded data and return them.
+    pub(crate) fn take_buffer(&mut self, amount: usize) -> &[u8] {
+        let start = self.consumed;
+        self.consume_buffer(amount);
+        &self.writer.get_ref().data[start..self.consumed]
+    }
+
+    /// Consume up to `amount` bytes of input handed out as is and return them.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn take_input(&mut self, amount: usize) -> Result<&[u8]> {
+        let input = self.reader.fill_buf()?;
+        let amount = std::cmp::min(amount, input.len());
+        let output = self.writer.get_mut();
+        output.data.clear();
+        output.data.extend_from_slice(&input[..amount]);
+        self.consumed = 0;
+        self.consume_input(amount);
+        Ok(&self.writer.get_ref().data[..amount])
+    }
+
+    pub(crate) fn consume_buffer(&mut self, amount: usize) {
+        let amount = std::cmp::min(amount, self.writer.get_ref().data.len() - self.consumed);
+        self.consumed += amount;
+        self.total_out += amount as u64;
+    }
+}
+
+impl<R: BufRead, C: Checksum> Read for DeflateDecoder<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl<R: BufRead, C: Checksum> BufRead for DeflateDecoder<R, C> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill_buffer().map_err(into_io_error)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consume_buffer(amount)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub(crate) fn into_io_error(err: anyhow::Error) -> io::Error {
+    match err.downcast::<io::Error>() {
+        Ok(err) => err,
+        Err(err) => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
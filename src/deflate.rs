@@ -1,20 +1,74 @@
 #![forbid(unsafe_code)]
 
-use std::io::BufRead;
+use std::io::{self, BufRead, Read};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use crate::bit_reader::BitReader;
+use crate::core::{read_symbol, Symbol};
+use crate::counting_reader::CountingReader;
+use crate::huffman_coding::{decode_litlen_distance_trees, fixed_litlen_distance_trees};
+
+pub use crate::huffman_coding::{canonical_codes, lengths_from_frequencies};
+
+/// Longest code deflate allows.
+pub const MAX_CODE_LENGTH: u8 = 15;
+
+/// Code lengths of the literal/length symbols in fixed blocks, as in RFC 1951
+/// 3.2.6. Symbols 286 and 287 have codes, but never occur.
+pub const FIXED_LIT_LEN_LENGTHS: [u8; 288] = fixed_lit_len_lengths();
+
+/// Length of the codes of the distance symbols in fixed blocks. The codes are the
+/// symbols themselves; 30 and 31 never occur.
+pub const FIXED_DISTANCE_BITS: u8 = 5;
+
+/// Smallest length of each length symbol, from 257 on, and its number of extra bits.
+pub const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Smallest distance of each distance symbol and its number of extra bits.
+pub const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which the lengths of the code length code are stored in dynamic blocks.
+pub const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const fn fixed_lit_len_lengths() -> [u8; 288] {
+    let mut lengths = [8; 288];
+    let mut symbol = 144;
+    while symbol < 256 {
+        lengths[symbol] = 9;
+        symbol += 1;
+    }
+    while symbol < 280 {
+        lengths[symbol] = 7;
+        symbol += 1;
+    }
+    lengths
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BlockHeader {
     pub is_final: bool,
     pub compression_type: CompressionType,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CompressionType {
     Uncompressed = 0,
     FixedTree = 1,
@@ -24,6 +78,7 @@ pub enum CompressionType {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+#[derive(Clone)]
 pub struct DeflateReader<T> {
     bit_reader: BitReader<T>,
 }
@@ -33,6 +88,14 @@ impl<T: BufRead> DeflateReader<T> {
         Self { bit_reader }
     }
 
+    pub fn bit_reader(&self) -> &BitReader<T> {
+        &self.bit_reader
+    }
+
+    pub fn bit_reader_mut(&mut self) -> &mut BitReader<T> {
+        &mut self.bit_reader
+    }
+
     pub fn next_block(&mut self) -> Option<Result<(BlockHeader, &mut BitReader<T>)>> {
         let is_final = self.bit_reader.read_bits(1).ok()?.bits() == 1;
         let compression_type = match self.bit_reader.read_bits(2).ok()?.bits() {
@@ -50,3 +113,236 @@ impl<T: BufRead> DeflateReader<T> {
         )))
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A block of a raw deflate stream, with its position in the stream in bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub header: BlockHeader,
+    /// Position of the first bit of the block header.
+    pub start_bit: u64,
+    /// Position just past the last bit of the block.
+    pub end_bit: u64,
+    /// Size of the decompressed data of the block.
+    pub size: u64,
+    /// Symbol statistics, when recorded.
+    pub stats: Option<BlockStats>,
+    /// Huffman trees of a dynamic block, when recorded.
+    pub trees: Option<BlockTrees>,
+}
+
+/// Symbols of a block, see `BlockHeaders::record_stats`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockStats {
+    /// Literal symbols, or the data bytes of a stored block.
+    pub literals: u64,
+    pub matches: u64,
+    /// Bytes produced by the matches.
+    pub match_bytes: u64,
+    pub max_match_length: u16,
+    /// Match counts by distance, bucket `i` holding distances in `2^i..2^(i + 1)`.
+    pub distance_histogram: [u64; 16],
+    /// Number of literal/length and distance code lengths, both zero for stored blocks.
+    pub lit_len_codes: usize,
+    pub distance_codes: usize,
+}
+
+/// Huffman trees of a dynamic block, see `BlockHeaders::record_trees`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockTrees {
+    pub lit_len: Vec<SymbolCode>,
+    pub distance: Vec<SymbolCode>,
+}
+
+/// Canonical code of a symbol, most significant bit first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymbolCode {
+    pub symbol: u16,
+    pub length: u8,
+    pub code: u16,
+}
+
+impl SymbolCode {
+    /// Codes of the symbols with a nonzero length among `lengths`, indexed by symbol.
+    pub fn from_lengths(lengths: &[u8]) -> Vec<Self> {
+        canonical_codes(lengths)
+            .into_iter()
+            .zip(lengths)
+            .enumerate()
+            .filter(|(_, (_, &length))| length > 0)
+            .map(|(symbol, (code, &length))| Self {
+                symbol: symbol as u16,
+                length,
+                code,
+            })
+            .collect()
+    }
+
+    /// Codes of a Huffman code for `frequencies` no longer than `MAX_CODE_LENGTH`,
+    /// for the symbols with a nonzero frequency.
+    pub fn from_frequencies(frequencies: &[u32]) -> Vec<Self> {
+        Self::from_lengths(&lengths_from_frequencies(frequencies, MAX_CODE_LENGTH))
+    }
+}
+
+impl BlockStats {
+    pub fn average_match_length(&self) -> f64 {
+        if self.matches == 0 {
+            return 0.0;
+        }
+        self.match_bytes as f64 / self.matches as f64
+    }
+}
+
+/// Number of blocks of each type and the bytes they produced, see
+/// `DeflateDecoder::block_usage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockUsage {
+    pub stored_blocks: u64,
+    pub stored_bytes: u64,
+    pub fixed_blocks: u64,
+    pub fixed_bytes: u64,
+    pub dynamic_blocks: u64,
+    pub dynamic_bytes: u64,
+}
+
+impl BlockUsage {
+    pub fn blocks(&self) -> u64 {
+        self.stored_blocks + self.fixed_blocks + self.dynamic_blocks
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.stored_bytes + self.fixed_bytes + self.dynamic_bytes
+    }
+
+    /// Add `bytes` produced by a block of the given type, and the block itself
+    /// if it ended.
+    pub(crate) fn record(&mut self, block_type: CompressionType, bytes: u64, ended: bool) {
+        let (blocks, total) = match block_type {
+            CompressionType::Uncompressed => (&mut self.stored_blocks, &mut self.stored_bytes),
+            CompressionType::FixedTree => (&mut self.fixed_blocks, &mut self.fixed_bytes),
+            CompressionType::DynamicTree => (&mut self.dynamic_blocks, &mut self.dynamic_bytes),
+            CompressionType::Reserved => return,
+        };
+        *total += bytes;
+        *blocks += ended as u64;
+    }
+}
+
+/// Iterator over the blocks of a raw deflate stream, ending after the final one.
+///
+/// No data is written out: stored blocks are skipped, and the symbols of
+/// compressed ones are decoded without copying their matches. Distances are not
+/// checked against the window.
+pub struct BlockHeaders<T> {
+    reader: DeflateReader<CountingReader<T>>,
+    done: bool,
+    record_stats: bool,
+    record_trees: bool,
+}
+
+impl<T: BufRead> BlockHeaders<T> {
+    pub fn new(reader: T) -> Self {
+        Self {
+            reader: DeflateReader::new(BitReader::new(CountingReader::new(reader))),
+            done: false,
+            record_stats: false,
+            record_trees: false,
+        }
+    }
+
+    /// Count the symbols of the blocks from now on, see `BlockInfo::stats`.
+    pub fn record_stats(&mut self) {
+        self.record_stats = true;
+    }
+
+    /// Keep the codes of the dynamic blocks from now on, see `BlockInfo::trees`.
+    pub fn record_trees(&mut self) {
+        self.record_trees = true;
+    }
+
+    /// Number of bits read so far.
+    pub fn bit_position(&self) -> u64 {
+        let bit_reader = self.reader.bit_reader();
+        bit_reader.get_ref().count() * 8 - bit_reader.pending().len() as u64
+    }
+
+    /// Return the inner reader. After the final block, it stands at the byte following it.
+    pub fn into_inner(self) -> T {
+        self.reader.bit_reader.into_inner().into_inner()
+    }
+
+    fn read_block(&mut self) -> Result<BlockInfo> {
+        let start_bit = self.bit_position();
+        let (header, rdr) = match self.reader.next_block() {
+            Some(block) => block?,
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        };
+        let mut stats = BlockStats::default();
+        let mut trees = None;
+        match header.compression_type {
+            CompressionType::Uncompressed => {
+                rdr.align_to_byte();
+                let lengths = rdr.read_bits_u32(32)?;
+                let length = lengths as u16;
+                if length != !(lengths >> 16) as u16 {
+                    bail!("nlen check failed");
+                }
+                let skipped = io::copy(&mut rdr.get_mut().take(length as u64), &mut io::sink())?;
+                if skipped != length as u64 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                stats.literals = skipped;
+            }
+            CompressionType::FixedTree | CompressionType::DynamicTree => {
+                let (lit_len, dist) = match header.compression_type {
+                    CompressionType::FixedTree => fixed_litlen_distance_trees()?,
+                    _ => decode_litlen_distance_trees(rdr)?,
+                };
+                if self.record_trees && header.compression_type == CompressionType::DynamicTree {
+                    trees = Some(BlockTrees {
+                        lit_len: SymbolCode::from_lengths(lit_len.lengths()),
+                        distance: SymbolCode::from_lengths(dist.lengths()),
+                    });
+                }
+                stats.lit_len_codes = lit_len.lengths().len();
+                stats.distance_codes = dist.lengths().len();
+                loop {
+                    match read_symbol(rdr, &lit_len, &dist)? {
+                        Symbol::Literal(_) => stats.literals += 1,
+                        Symbol::Match { distance, length } => {
+                            stats.distance_histogram[distance.ilog2() as usize] += 1;
+                            stats.matches += 1;
+                            stats.match_bytes += length as u64;
+                            stats.max_match_length = stats.max_match_length.max(length as u16);
+                        }
+                        Symbol::EndOfBlock => break,
+                    }
+                }
+            }
+            CompressionType::Reserved => bail!("unsupported block type"),
+        }
+        Ok(BlockInfo {
+            header,
+            start_bit,
+            end_bit: self.bit_position(),
+            size: stats.literals + stats.match_bytes,
+            stats: self.record_stats.then_some(stats),
+            trees,
+        })
+    }
+}
+
+impl<T: BufRead> Iterator for BlockHeaders<T> {
+    type Item = Result<BlockInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let block = self.read_block();
+        self.done = block.as_ref().map_or(true, |block| block.header.is_final);
+        Some(block)
+    }
+}
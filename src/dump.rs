@@ -0,0 +1,226 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, Write};
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::checksum::{Checksum, Crc32};
+use crate::decoder::DeflateDecoder;
+use crate::deflate::{BlockHeaders, BlockInfo, CompressionType};
+use crate::gzip::{MemberFlags, CM_DEFLATE, ID1, ID2};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Write an annotated hex dump of the gzip stream `data` to `output`: the header
+/// of each member field by field, its deflate data split at the blocks, which are
+/// marked with their bit offsets, and the footer decoded and checked.
+///
+/// Broken data is dumped up to the error, then the rest as is, and the error is
+/// returned.
+pub fn hex_dump<W: Write>(data: &[u8], output: W) -> Result<()> {
+    let mut dump = Dump {
+        data,
+        output,
+        offset: 0,
+    };
+    let mut index = 0;
+    while dump.offset < data.len() {
+        writeln!(dump.output, "{:08x}  -- member {}", dump.offset, index)?;
+        if let Err(err) = dump.member() {
+            writeln!(dump.output, "{:08x}  -- error: {:#}", dump.offset, err)?;
+            let rest = data.len() - dump.offset;
+            dump.field(rest, "unparsed")?;
+            return Err(err);
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+/// Annotation of a `stored` checksum or size with the `computed` one, if known.
+fn check(stored: String, computed: Option<String>) -> String {
+    match computed {
+        Some(computed) if computed == stored => format!("{} (ok)", stored),
+        Some(computed) => format!("{} (computed {})", stored, computed),
+        None => stored,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+struct Dump<'a, W> {
+    data: &'a [u8],
+    output: W,
+    offset: usize,
+}
+
+impl<'a, W: Write> Dump<'a, W> {
+    /// The next `len` bytes, which are part of `what`.
+    fn next(&self, len: usize, what: &str) -> Result<&'a [u8]> {
+        self.data
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| anyhow!("{} is truncated", what))
+    }
+
+    /// Dump the next `len` bytes, with `label` on their first line.
+    fn field(&mut self, len: usize, label: &str) -> io::Result<()> {
+        let bytes = &self.data[self.offset..self.offset + len];
+        for (index, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let label = if index == 0 { label } else { "" };
+            let offset = self.offset + index * BYTES_PER_LINE;
+            let line = format!("{:08x}  {:<47}  {}", offset, hex, label);
+            writeln!(self.output, "{}", line.trim_end())?;
+        }
+        self.offset += len;
+        Ok(())
+    }
+
+    fn member(&mut self) -> Result<()> {
+        let start = self.offset;
+        let header = self.next(10, "header")?;
+        ensure!(header[..2] == [ID1, ID2], "wrong id values");
+        self.field(2, "ID1 ID2")?;
+        let method = match header[2] {
+            CM_DEFLATE => "deflate",
+            _ => "unknown",
+        };
+        self.field(1, &format!("CM = {} ({})", header[2], method))?;
+        let flags = MemberFlags(header[3]);
+        self.field(1, &format!("FLG = {}", flags))?;
+        let mtime = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        self.field(4, &format!("MTIME = {}", mtime))?;
+        self.field(1, &format!("XFL = {}", header[8]))?;
+        self.field(1, &format!("OS = {}", header[9]))?;
+        ensure!(header[2] == CM_DEFLATE, "unsupported compression method");
+
+        if flags.has_extra() {
+            let xlen = self.next(2, "extra field")?;
+            let xlen = u16::from_le_bytes([xlen[0], xlen[1]]) as usize;
+            self.field(2, &format!("XLEN = {}", xlen))?;
+            self.next(xlen, "extra field")?;
+            self.field(xlen, "FEXTRA")?;
+        }
+        for (present, field) in [
+            (flags.has_name(), "FNAME"),
+            (flags.has_comment(), "FCOMMENT"),
+        ] {
+            if present {
+                let rest = &self.data[self.offset..];
+                let len = rest
+                    .iter()
+                    .position(|&byte| byte == 0)
+                    .ok_or_else(|| anyhow!("{} is not terminated", field))?;
+                let value = String::from_utf8_lossy(&rest[..len]);
+                self.field(len + 1, &format!("{} = {:?}", field, value))?;
+            }
+        }
+        if flags.has_crc() {
+            let crc16 = self.next(2, "header crc")?;
+            let mut crc32 = Crc32::default();
+            crc32.update(&self.data[start..self.offset]);
+            let hcrc = check(
+                format!("{:#06x}", u16::from_le_bytes([crc16[0], crc16[1]])),
+                Some(format!("{:#06x}", crc32.value() as u16)),
+            );
+            self.field(2, &format!("HCRC = {}", hcrc))?;
+        }
+
+        let deflate = self.offset;
+        let (computed, end_bit) = self.blocks()?;
+        let footer = self.next(8, "footer")?;
+        let crc32 = u32::from_le_bytes(footer[..4].try_into().unwrap());
+        let size = u32::from_le_bytes(footer[4..].try_into().unwrap());
+        writeln!(
+            self.output,
+            "{:08x}  -- footer, after {} bytes of deflate data ending at bit {}",
+            self.offset,
+            self.offset - deflate,
+            end_bit
+        )?;
+        let crc32 = check(
+            format!("{:#010x}", crc32),
+            computed.map(|(crc32, _)| format!("{:#010x}", crc32)),
+        );
+        self.field(4, &format!("CRC32 = {}", crc32))?;
+        let size = check(size.to_string(), computed.map(|(_, size)| size.to_string()));
+        self.field(4, &format!("ISIZE = {}", size))?;
+        Ok(())
+    }
+
+    /// Dump the deflate data, returning the CRC-32 and the size of its decompressed
+    /// data, unless it does not decode, and its length in bits.
+    fn blocks(&mut self) -> Result<(Option<(u32, u32)>, u64)> {
+        let data = &self.data[self.offset..];
+        let mut blocks = BlockHeaders::new(data);
+        let mut infos = Vec::new();
+        let error = loop {
+            match blocks.next() {
+                Some(Ok(block)) => infos.push(block),
+                Some(Err(err)) => break Some(err),
+                None => break None,
+            }
+        };
+        let end_bit = blocks.bit_position();
+
+        let deflate = self.offset;
+        let failed_start = infos.last().map_or(0, |block| block.end_bit);
+        for (index, block) in infos.iter().enumerate() {
+            self.block_marker(deflate, index, block.start_bit, &describe(block))?;
+            // A byte shared by two blocks is dumped with the second one.
+            let end = match infos.get(index + 1) {
+                Some(next) => next.start_bit / 8,
+                None if error.is_some() => failed_start / 8,
+                None => end_bit.div_ceil(8),
+            };
+            self.field(deflate + end as usize - self.offset, "")?;
+        }
+        if let Some(err) = error {
+            self.block_marker(deflate, infos.len(), failed_start, "broken")?;
+            let end = end_bit.div_ceil(8) as usize;
+            self.field(deflate + end - self.offset, "")?;
+            return Err(err);
+        }
+
+        let mut decoder = DeflateDecoder::with_checksum(data, Crc32::default());
+        let computed = io::copy(&mut decoder, &mut io::sink())
+            .ok()
+            .map(|size| (decoder.checksum(), size as u32));
+        Ok((computed, end_bit))
+    }
+
+    fn block_marker(
+        &mut self,
+        deflate: usize,
+        index: usize,
+        bit: u64,
+        info: &str,
+    ) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "{:08x}  -- block {} at deflate bit {} (bit {} of the byte): {}",
+            deflate + (bit / 8) as usize,
+            index,
+            bit,
+            bit % 8,
+            info
+        )
+    }
+}
+
+fn describe(block: &BlockInfo) -> String {
+    let kind = match block.header.compression_type {
+        CompressionType::Uncompressed => "stored",
+        CompressionType::FixedTree => "fixed",
+        CompressionType::DynamicTree => "dynamic",
+        CompressionType::Reserved => "reserved",
+    };
+    let last = if block.header.is_final { ", final" } else { "" };
+    format!("{}{}, {} bytes", kind, last, block.size)
+}
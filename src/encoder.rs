@@ -0,0 +1,546 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, Read, Write};
+
+use anyhow::{ensure, Result};
+
+use crate::bit_reader::BitSequence;
+use crate::bit_writer::BitWriter;
+#[cfg(feature = "zlib")]
+use crate::checksum::Adler32;
+use crate::checksum::{Checksum, Crc32};
+use crate::deflate::{CODE_LENGTH_ORDER, DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+use crate::gzip::{CompressionHint, CompressionMethod, ExtraField, MemberHeader};
+use crate::huffman_coding::{codes_from_lengths, lengths_from_frequencies};
+use crate::lz77::{MatchParams, Matcher, Token};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Number of input bytes compressed into one block.
+const BLOCK_SIZE: usize = 1 << 15;
+
+/// Matcher parameters of each compression level, as in zlib: good length, longest
+/// deferred match, nice length and longest chain. Level 0 looks for no matches and
+/// levels up to 3 take matches greedily.
+const LEVELS: [MatchParams; 10] = [
+    level(0, 0, 0, 0),
+    level(4, 0, 8, 4),
+    level(4, 0, 16, 8),
+    level(4, 0, 32, 32),
+    level(4, 4, 16, 16),
+    level(8, 16, 32, 32),
+    level(8, 16, 128, 128),
+    level(8, 32, 128, 256),
+    level(32, 128, 258, 1024),
+    level(32, 258, 258, 4096),
+];
+
+const END_OF_BLOCK: usize = 256;
+
+const OS: u8 = if cfg!(unix) {
+    3
+} else if cfg!(windows) {
+    11
+} else {
+    255
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    #[default]
+    Default,
+    /// Only Huffman code the literals, without looking for matches. Faster, and as
+    /// good for data without repetitions, e.g. already compressed.
+    HuffmanOnly,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressOptions {
+    /// From 0 (no compression) to 9 (best compression).
+    pub level: u32,
+    pub strategy: Strategy,
+    /// XFL byte of the header. By default, set from the level as gzip does.
+    pub extra_flags: Option<u8>,
+    /// OS byte of the header. Defaults to the current OS.
+    pub os: u8,
+    /// Subfields of the FEXTRA header field. The field is omitted when empty.
+    pub extra: Vec<ExtraField>,
+    /// Protect the header with a CRC16 (FHCRC), which some strict readers require.
+    pub header_crc: bool,
+    /// FNAME of the header, the name of the file which was compressed.
+    pub name: Option<String>,
+    /// MTIME of the header, in seconds since the epoch. Zero means unknown.
+    pub modification_time: u32,
+    /// Leave out of the header what differs between runs and platforms, whatever
+    /// the fields above say: MTIME is zero, FNAME is omitted and OS is 255.
+    pub deterministic: bool,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        Self {
+            level: 6,
+            strategy: Strategy::Default,
+            extra_flags: None,
+            os: OS,
+            extra: Vec::new(),
+            header_crc: false,
+            name: None,
+            modification_time: 0,
+            deterministic: false,
+        }
+    }
+}
+
+impl CompressOptions {
+    /// Options giving byte-identical output for the same input and settings on any
+    /// platform, for reproducible builds: MTIME is zero, FNAME is omitted and OS is
+    /// set to 255 (unknown), even if a name or time is set later on.
+    pub fn deterministic() -> Self {
+        Self {
+            os: 255,
+            deterministic: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Compress `input` into a single gzip member.
+pub fn compress<R: Read, W: Write>(
+    mut input: R,
+    output: W,
+    options: CompressOptions,
+) -> Result<()> {
+    let mut encoder = GzipEncoder::new(output, options)?;
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Gzip encoder compressing the data written to it into a single member. Only a
+/// block of input and the window are kept in memory. The member is not complete
+/// until `finish` is called.
+pub struct GzipEncoder<W: Write> {
+    output: W,
+    encoder: DeflateEncoder,
+    writer: BitWriter,
+    /// Input not compressed yet, up to a block.
+    block: Vec<u8>,
+    crc32: Crc32,
+    size: u32,
+}
+
+impl<W: Write> GzipEncoder<W> {
+    /// Create an encoder and write the member header to `output`.
+    pub fn new(output: W, mut options: CompressOptions) -> Result<Self> {
+        if options.deterministic {
+            options.modification_time = 0;
+            options.name = None;
+            options.os = 255;
+        }
+        let extra_flags = options
+            .extra_flags
+            .unwrap_or_else(|| CompressionHint::for_level(options.level).into());
+        let extra = if options.extra.is_empty() {
+            None
+        } else {
+            Some(ExtraField::encode(&options.extra)?)
+        };
+        let header = MemberHeader {
+            compression_method: CompressionMethod::Deflate,
+            modification_time: options.modification_time,
+            extra,
+            name: options.name.take(),
+            comment: None,
+            extra_flags,
+            os: options.os,
+            has_crc: options.header_crc,
+            is_text: false,
+        };
+        Self::with_header(output, header, options)
+    }
+
+    /// Create an encoder writing `header` as is, e.g. with a comment, instead of
+    /// the header fields of `options`.
+    pub fn with_header(
+        mut output: W,
+        header: MemberHeader,
+        options: CompressOptions,
+    ) -> Result<Self> {
+        ensure!(options.level <= 9, "invalid compression level");
+        header.write_to(&mut output)?;
+        Ok(Self {
+            output,
+            encoder: DeflateEncoder::new(LEVELS[options.level as usize], options.strategy),
+            writer: BitWriter::new(),
+            block: Vec::with_capacity(BLOCK_SIZE),
+            crc32: Crc32::default(),
+            size: 0,
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// The output, to which compressed data is appended as it is produced.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Compress the remaining input, write the footer and return the output.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_block(true)?;
+        self.writer.align_to_byte();
+        self.output.write_all(&self.writer.take_bytes())?;
+        self.output.write_all(&self.crc32.value().to_le_bytes())?;
+        self.output.write_all(&self.size.to_le_bytes())?;
+        Ok(self.output)
+    }
+
+    fn write_block(&mut self, is_final: bool) -> io::Result<()> {
+        self.crc32.update(&self.block);
+        self.size = self.size.wrapping_add(self.block.len() as u32);
+        self.encoder
+            .write_block(&self.block, is_final, &mut self.writer);
+        self.block.clear();
+        self.output.write_all(&self.writer.take_bytes())
+    }
+}
+
+impl<W: Write> Write for GzipEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full block is only compressed once more input arrives, since the last
+        // block has to be marked as final.
+        if self.block.len() == BLOCK_SIZE && !buf.is_empty() {
+            self.write_block(false)?;
+        }
+        let len = std::cmp::min(buf.len(), BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Compress `data` into a zlib stream.
+#[cfg(feature = "zlib")]
+pub(crate) fn compress_zlib(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(LEVELS[level as usize], Strategy::Default);
+    let mut writer = BitWriter::new();
+    // Deflate with a 32K window, FCHECK making the pair a multiple of 31.
+    writer.write_bytes(&[0x78, 0x9c]);
+    let blocks = data.len().div_ceil(BLOCK_SIZE).max(1);
+    for i in 0..blocks {
+        let block = &data[std::cmp::min(i * BLOCK_SIZE, data.len())
+            ..std::cmp::min((i + 1) * BLOCK_SIZE, data.len())];
+        encoder.write_block(block, i + 1 == blocks, &mut writer);
+    }
+    writer.align_to_byte();
+    let mut adler32 = Adler32::default();
+    adler32.update(data);
+    writer.write_bytes(&adler32.value().to_be_bytes());
+    writer.take_bytes()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Deflate encoder splitting each block into tokens with an LZ77 matcher.
+struct DeflateEncoder {
+    matcher: Matcher,
+    params: MatchParams,
+    strategy: Strategy,
+}
+
+impl DeflateEncoder {
+    fn new(params: MatchParams, strategy: Strategy) -> Self {
+        Self {
+            matcher: Matcher::new(params),
+            params,
+            strategy,
+        }
+    }
+
+    /// Compress `data` as the next block, or as a stored block if that is smaller.
+    fn write_block(&mut self, data: &[u8], is_final: bool, writer: &mut BitWriter) {
+        let stored_bits =
+            3 + (8 - (writer.pending_len() as usize + 3) % 8) % 8 + 32 + 8 * data.len();
+        let compressed = (self.params.max_chain > 0).then(|| {
+            let tokens = match self.strategy {
+                Strategy::Default => self.matcher.tokenize(data),
+                Strategy::HuffmanOnly => data.iter().map(|&value| Token::Literal(value)).collect(),
+            };
+            let block = HuffmanBlock::new(&tokens);
+            (block.size_in_bits(&tokens), block, tokens)
+        });
+        match compressed {
+            Some((bits, block, tokens)) if bits < stored_bits => {
+                block.write(&tokens, is_final, writer)
+            }
+            _ => write_stored(data, is_final, writer),
+        }
+    }
+}
+
+fn write_stored(data: &[u8], is_final: bool, writer: &mut BitWriter) {
+    writer.write_bits(is_final as u32, 1);
+    writer.write_bits(0, 2);
+    writer.align_to_byte();
+    writer.write_bits(data.len() as u32, 16);
+    writer.write_bits(!data.len() as u32, 16);
+    writer.write_bytes(data);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Codes of a block compressed with dynamic Huffman codes.
+struct HuffmanBlock {
+    lit_len_lengths: Vec<u8>,
+    lit_len_codes: Vec<u16>,
+    dist_lengths: Vec<u8>,
+    dist_codes: Vec<u16>,
+    code_length_lengths: Vec<u8>,
+    code_length_codes: Vec<u16>,
+    /// Both code length sequences, run length encoded as (symbol, extra bits).
+    code_length_symbols: Vec<(u8, u8)>,
+    num_code_lengths: usize,
+}
+
+impl HuffmanBlock {
+    fn new(tokens: &[Token]) -> Self {
+        let mut lit_len_freqs = [0u32; 286];
+        let mut dist_freqs = [0u32; 30];
+        for &token in tokens {
+            match token {
+                Token::Literal(value) => lit_len_freqs[value as usize] += 1,
+                Token::Match { length, distance } => {
+                    lit_len_freqs[257 + length_code(length)] += 1;
+                    dist_freqs[distance_code(distance)] += 1;
+                }
+            }
+        }
+        lit_len_freqs[END_OF_BLOCK] = 1;
+
+        let mut lit_len_lengths = lengths_from_frequencies(&lit_len_freqs, 15);
+        let mut dist_lengths = lengths_from_frequencies(&dist_freqs, 15);
+        if dist_lengths.iter().all(|&len| len == 0) {
+            dist_lengths[0] = 1;
+        }
+        lit_len_lengths.truncate(std::cmp::max(257, used_len(&lit_len_lengths)));
+        dist_lengths.truncate(used_len(&dist_lengths));
+
+        // The decoder expects the runs not to cross from one code to the other.
+        let mut code_length_symbols = Vec::new();
+        run_length_encode(&lit_len_lengths, &mut code_length_symbols);
+        run_length_encode(&dist_lengths, &mut code_length_symbols);
+        let mut code_length_freqs = [0u32; 19];
+        for &(symbol, _) in &code_length_symbols {
+            code_length_freqs[symbol as usize] += 1;
+        }
+        let code_length_lengths = lengths_from_frequencies(&code_length_freqs, 7);
+        let ordered = CODE_LENGTH_ORDER.map(|symbol| code_length_lengths[symbol]);
+
+        Self {
+            lit_len_codes: codes_from_lengths(&lit_len_lengths),
+            lit_len_lengths,
+            dist_codes: codes_from_lengths(&dist_lengths),
+            dist_lengths,
+            code_length_codes: codes_from_lengths(&code_length_lengths),
+            code_length_lengths,
+            code_length_symbols,
+            num_code_lengths: std::cmp::max(4, used_len(&ordered)),
+        }
+    }
+
+    fn size_in_bits(&self, tokens: &[Token]) -> usize {
+        let mut bits = 3 + 5 + 5 + 4 + 3 * self.num_code_lengths;
+        for &(symbol, _) in &self.code_length_symbols {
+            bits += self.code_length_lengths[symbol as usize] as usize
+                + code_length_extra_bits(symbol) as usize;
+        }
+        for &token in tokens {
+            bits += match token {
+                Token::Literal(value) => self.lit_len_lengths[value as usize] as usize,
+                Token::Match { length, distance } => {
+                    let length_code = length_code(length);
+                    let distance_code = distance_code(distance);
+                    (self.lit_len_lengths[257 + length_code]
+                        + LENGTH_EXTRA[length_code]
+                        + self.dist_lengths[distance_code]
+                        + DIST_EXTRA[distance_code]) as usize
+                }
+            };
+        }
+        bits + self.lit_len_lengths[END_OF_BLOCK] as usize
+    }
+
+    fn write(&self, tokens: &[Token], is_final: bool, writer: &mut BitWriter) {
+        writer.write_bits(is_final as u32, 1);
+        writer.write_bits(2, 2);
+        writer.write_bits(self.lit_len_lengths.len() as u32 - 257, 5);
+        writer.write_bits(self.dist_lengths.len() as u32 - 1, 5);
+        writer.write_bits(self.num_code_lengths as u32 - 4, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..self.num_code_lengths] {
+            writer.write_bits(self.code_length_lengths[symbol] as u32, 3);
+        }
+        for &(symbol, extra) in &self.code_length_symbols {
+            let symbol = symbol as usize;
+            writer.write_bits(
+                self.code_length_codes[symbol] as u32,
+                self.code_length_lengths[symbol],
+            );
+            writer.write_bits(extra as u32, code_length_extra_bits(symbol as u8));
+        }
+
+        for &token in tokens {
+            match token {
+                Token::Literal(value) => self.write_lit_len(value as usize, writer),
+                Token::Match { length, distance } => {
+                    let code = length_code(length);
+                    self.write_lit_len(257 + code, writer);
+                    writer.write_bits((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code]);
+                    let code = distance_code(distance);
+                    writer.write_sequence(BitSequence::new(
+                        self.dist_codes[code],
+                        self.dist_lengths[code],
+                    ));
+                    writer.write_bits((distance - DIST_BASE[code]) as u32, DIST_EXTRA[code]);
+                }
+            }
+        }
+        self.write_lit_len(END_OF_BLOCK, writer);
+    }
+
+    fn write_lit_len(&self, symbol: usize, writer: &mut BitWriter) {
+        writer.write_sequence(BitSequence::new(
+            self.lit_len_codes[symbol],
+            self.lit_len_lengths[symbol],
+        ));
+    }
+}
+
+const fn level(
+    good_length: usize,
+    max_lazy: usize,
+    nice_length: usize,
+    max_chain: usize,
+) -> MatchParams {
+    MatchParams {
+        max_chain,
+        good_length,
+        max_lazy,
+        nice_length,
+    }
+}
+
+/// Number of lengths up to the last non-zero one.
+fn used_len(lengths: &[u8]) -> usize {
+    lengths
+        .iter()
+        .rposition(|&len| len != 0)
+        .map_or(0, |i| i + 1)
+}
+
+fn length_code(length: u16) -> usize {
+    LENGTH_BASE.partition_point(|&base| base <= length) - 1
+}
+
+fn distance_code(distance: u16) -> usize {
+    DIST_BASE.partition_point(|&base| base <= distance) - 1
+}
+
+fn code_length_extra_bits(symbol: u8) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Run length encode code lengths: 16 repeats the previous length 3-6 times, 17 and
+/// 18 repeat a zero length 3-10 and 11-138 times.
+fn run_length_encode(lengths: &[u8], symbols: &mut Vec<(u8, u8)>) {
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let mut run = lengths[i..]
+            .iter()
+            .take_while(|&&len| len == length)
+            .count();
+        i += run;
+        if length == 0 {
+            while run >= 11 {
+                let size = std::cmp::min(run, 138);
+                symbols.push((18, (size - 11) as u8));
+                run -= size;
+            }
+            if run >= 3 {
+                symbols.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else {
+            symbols.push((length, 0));
+            run -= 1;
+            while run >= 3 {
+                let size = std::cmp::min(run, 6);
+                symbols.push((16, (size - 3) as u8));
+                run -= size;
+            }
+        }
+        symbols.extend(std::iter::repeat_n((length, 0), run));
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        for length in 3..=258 {
+            let code = length_code(length);
+            assert!(length - LENGTH_BASE[code] < 1 << LENGTH_EXTRA[code]);
+        }
+        assert_eq!(length_code(258), 28);
+        assert_eq!(length_code(257), 27);
+        for distance in 1..=32768u32 {
+            let code = distance_code(distance as u16);
+            assert!((distance as u16 - DIST_BASE[code]) < 1 << DIST_EXTRA[code]);
+        }
+    }
+
+    #[test]
+    fn run_lengths() {
+        let lengths = [[0; 20].as_slice(), &[5; 9], &[0; 2], &[7; 3], &[0; 150]].concat();
+        let mut symbols = Vec::new();
+        run_length_encode(&lengths, &mut symbols);
+        assert_eq!(
+            symbols,
+            [
+                (18, 9),
+                (5, 0),
+                (16, 3),
+                (5, 0),
+                (5, 0),
+                (0, 0),
+                (0, 0),
+                (7, 0),
+                (7, 0),
+                (7, 0),
+                (18, 127),
+                (18, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+#![forbid(unsafe_code)]
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use anyhow::{bail, Result};
+
+use crate::gzip::{ExtraField, GzipReader, Subfield};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Largest ratio of decompressed to compressed size deflate can reach: a match
+/// of 258 bytes takes at least 2 bits.
+const MAX_RATIO: u64 = 1032;
+
+/// Size of the shortest deflate stream, a single empty block.
+const MIN_DEFLATE_SIZE: u64 = 2;
+
+const FOOTER_SIZE: u64 = 8;
+
+/// Decompressed size of a gzip file, see `estimate_decompressed_size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// Sum of the ISIZE fields of the members found.
+    pub size: u64,
+    /// Whether `size` is certain. ISIZE is the member size modulo 2^32, so it is
+    /// not when a member is long enough to have decompressed to 4 GiB or more.
+    pub exact: bool,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Estimate the decompressed size of a gzip file from the ISIZE fields of its
+/// members, without decompressing it.
+///
+/// Members are skipped by the size recorded in their BGZF "BC" subfield. From
+/// the first member without one, the rest of the input is taken as a single
+/// member, as `gzip -l` does: the size of any other member after it is missed.
+pub fn estimate_decompressed_size<R: Read + Seek>(mut reader: R) -> Result<SizeEstimate> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut estimate = SizeEstimate {
+        size: 0,
+        exact: true,
+    };
+    let mut pos = 0;
+    while pos < len {
+        reader.seek(SeekFrom::Start(pos))?;
+        let mut gzip_reader = GzipReader::new(BufReader::new(&mut reader));
+        let header = match gzip_reader.read_header() {
+            Some(header) => header?,
+            None => bail!("member is truncated"),
+        };
+        let (header, member_reader) = gzip_reader.parse_header(&header)?;
+        let data_start = member_reader.into_inner().stream_position()?;
+        let block_size = header
+            .extra
+            .and_then(|extra| ExtraField::decode(&extra).ok())
+            .and_then(|fields| {
+                fields.iter().find_map(|field| match field.interpret() {
+                    Ok(Subfield::BgzfBlockSize(size)) => Some(size),
+                    _ => None,
+                })
+            });
+        let end = match block_size {
+            Some(block_size) => pos + block_size as u64 + 1,
+            None => len,
+        };
+        if end > len || end < data_start + MIN_DEFLATE_SIZE + FOOTER_SIZE {
+            bail!("member is truncated");
+        }
+
+        reader.seek(SeekFrom::Start(end - 4))?;
+        let mut isize = [0; 4];
+        reader.read_exact(&mut isize)?;
+        estimate.size += u32::from_le_bytes(isize) as u64;
+        if (end - pos) * MAX_RATIO >= 1 << 32 {
+            estimate.exact = false;
+        }
+        pos = end;
+    }
+    Ok(estimate)
+}
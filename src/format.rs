@@ -0,0 +1,171 @@
+#![forbid(unsafe_code)]
+
+use anyhow::{bail, Result};
+use std::io::{self, BufRead, Read, Write};
+
+use crate::decoder::DeflateDecoder;
+use crate::gzip::{GzipDecoder, ID1, ID2};
+use crate::zlib::ZlibDecoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+    Raw,
+    /// Neither gzip nor zlib, and not starting with a valid deflate block header,
+    /// e.g. uncompressed data.
+    Unknown,
+}
+
+/// Guess the format of a stream from its first bytes, without consuming them.
+///
+/// Raw deflate has no magic bytes, so `Format::Raw` only tells the data starts
+/// with something which could be a deflate block header.
+pub fn sniff_format<R: BufRead>(reader: &mut R) -> Result<Format> {
+    let data = reader.fill_buf()?;
+    Ok(match *data {
+        [ID1, ID2, ..] => Format::Gzip,
+        [cmf, flg, ..] if is_zlib_header(cmf, flg) => Format::Zlib,
+        _ if is_deflate_block_header(data) => Format::Raw,
+        _ => Format::Unknown,
+    })
+}
+
+/// Decompress `input` as long as it is gzip or zlib data, up to `max_layers`
+/// times, and write the innermost payload to `output`. Returns the number of layers
+/// removed.
+pub fn decompress_nested<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    max_layers: usize,
+) -> Result<usize> {
+    let mut reader: Box<dyn BufRead + '_> = Box::new(input);
+    let mut layers = 0;
+    loop {
+        reader = match sniff_format(&mut reader)? {
+            Format::Gzip => Box::new(GzipDecoder::new(reader)),
+            Format::Zlib => Box::new(ZlibDecoder::new(reader)),
+            Format::Raw | Format::Unknown => break,
+        };
+        layers += 1;
+        if layers > max_layers {
+            bail!("more than {} compression layers", max_layers);
+        }
+    }
+    io::copy(&mut reader, &mut output)?;
+    Ok(layers)
+}
+
+fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    cmf & 0x0f == 8 && cmf >> 4 <= 7 && u16::from_be_bytes([cmf, flg]).is_multiple_of(31)
+}
+
+fn is_deflate_block_header(data: &[u8]) -> bool {
+    match *data {
+        // Stored: LEN followed by its complement after the byte boundary.
+        [first, len_lo, len_hi, nlen_lo, nlen_hi, ..] if (first >> 1) & 3 == 0 => {
+            u16::from_le_bytes([len_lo, len_hi]) == !u16::from_le_bytes([nlen_lo, nlen_hi])
+        }
+        // Dynamic: at most 286 literal/length codes and 30 distance codes.
+        [first, second, ..] if (first >> 1) & 3 == 2 => {
+            let hlit = first >> 3;
+            let hdist = second & 0x1f;
+            hlit <= 29 && hdist <= 29
+        }
+        [first, ..] => (first >> 1) & 3 == 1,
+        [] => false,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone)]
+enum Inner<R> {
+    Gzip(Box<GzipDecoder<R>>),
+    Zlib(ZlibDecoder<R>),
+    Raw(DeflateDecoder<R>),
+}
+
+/// Decoder of a gzip, zlib or raw deflate stream, telling them apart by their
+/// first bytes: anything which is neither gzip nor zlib is decoded as raw deflate.
+#[derive(Clone)]
+pub struct AnyDecoder<R> {
+    inner: Inner<R>,
+}
+
+impl<R: BufRead> AnyDecoder<R> {
+    /// Create a decoder for the format of the stream, reading but not consuming its
+    /// first bytes.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let inner = match sniff_format(&mut reader)? {
+            Format::Gzip => Inner::Gzip(Box::new(GzipDecoder::new(reader))),
+            Format::Zlib => Inner::Zlib(ZlibDecoder::new(reader)),
+            Format::Raw | Format::Unknown => Inner::Raw(DeflateDecoder::new(reader)),
+        };
+        Ok(Self { inner })
+    }
+
+    pub fn format(&self) -> Format {
+        match self.inner {
+            Inner::Gzip(_) => Format::Gzip,
+            Inner::Zlib(_) => Format::Zlib,
+            Inner::Raw(_) => Format::Raw,
+        }
+    }
+
+    /// Number of bytes consumed from the reader so far.
+    pub fn total_in(&self) -> u64 {
+        match &self.inner {
+            Inner::Gzip(decoder) => decoder.total_in(),
+            Inner::Zlib(decoder) => decoder.total_in(),
+            Inner::Raw(decoder) => decoder.total_in(),
+        }
+    }
+
+    /// Number of decompressed bytes read from the decoder so far.
+    pub fn total_out(&self) -> u64 {
+        match &self.inner {
+            Inner::Gzip(decoder) => decoder.total_out(),
+            Inner::Zlib(decoder) => decoder.total_out(),
+            Inner::Raw(decoder) => decoder.total_out(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        match self.inner {
+            Inner::Gzip(decoder) => decoder.into_inner(),
+            Inner::Zlib(decoder) => decoder.into_inner(),
+            Inner::Raw(decoder) => decoder.into_inner(),
+        }
+    }
+}
+
+impl<R: BufRead> Read for AnyDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl<R: BufRead> BufRead for AnyDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match &mut self.inner {
+            Inner::Gzip(decoder) => decoder.fill_buf(),
+            Inner::Zlib(decoder) => decoder.fill_buf(),
+            Inner::Raw(decoder) => decoder.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match &mut self.inner {
+            Inner::Gzip(decoder) => decoder.consume(amount),
+            Inner::Zlib(decoder) => decoder.consume(amount),
+            Inner::Raw(decoder) => decoder.consume(amount),
+        }
+    }
+}
@@ -1,14 +1,31 @@
 #![forbid(unsafe_code)]
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use crc::Crc;
-use std::io::BufRead;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::num::NonZeroU64;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::bgzf::EOF_EXTRA;
+#[cfg(feature = "zlib")]
+use crate::bit_reader::BitSequence;
+#[cfg(feature = "zlib")]
+use crate::checkpoint::BlockCheckpoint;
+use crate::checkpoint::{Checkpoint, CheckpointInfo, Framing};
+use crate::checksum::{Checksum, Crc32, NoChecksum};
+use crate::decoder::{into_io_error, DeflateDecoder, SyncPoint, DEFAULT_BUFFER_SIZE, WINDOW_SIZE};
+use crate::deflate::BlockUsage;
+#[cfg(feature = "trace-decode")]
+use crate::trace::{TraceEvent, TraceSink};
 ////////////////////////////////////////////////////////////////////////////////
 
-const ID1: u8 = 0x1f;
-const ID2: u8 = 0x8b;
+pub(crate) const ID1: u8 = 0x1f;
+pub(crate) const ID2: u8 = 0x8b;
 
-const CM_DEFLATE: u8 = 8;
+pub(crate) const CM_DEFLATE: u8 = 8;
 
 const FTEXT_OFFSET: u8 = 0;
 const FHCRC_OFFSET: u8 = 1;
@@ -16,13 +33,18 @@ const FEXTRA_OFFSET: u8 = 2;
 const FNAME_OFFSET: u8 = 3;
 const FCOMMENT_OFFSET: u8 = 4;
 
+/// XFL values telling the slowest and the fastest algorithm were used.
+const XFL_SLOWEST: u8 = 2;
+const XFL_FASTEST: u8 = 4;
+
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MemberHeader {
     pub compression_method: CompressionMethod,
     pub modification_time: u32,
     pub extra: Option<Vec<u8>>,
+    /// FNAME and FCOMMENT, in ISO 8859-1, see `decode_latin1`.
     pub name: Option<String>,
     pub comment: Option<String>,
     pub extra_flags: u8,
@@ -32,30 +54,58 @@ pub struct MemberHeader {
 }
 
 impl MemberHeader {
-    pub fn crc16(&self) -> u16 {
+    /// CRC16 of the header, as stored with FHCRC.
+    pub fn crc16(&self) -> Result<u16> {
         let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let mut digest = crc.digest();
+        Ok((crc.checksum(&self.encode()?) & 0xffff) as u16)
+    }
 
-        digest.update(&[ID1, ID2, self.compression_method.into(), self.flags().0]);
-        digest.update(&self.modification_time.to_le_bytes());
-        digest.update(&[self.extra_flags, self.os]);
+    /// Write the header as stored in a member, with its CRC16 if `has_crc`, e.g. to
+    /// splice a rewritten header in front of the rest of a member.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut header = self.encode()?;
+        if self.has_crc {
+            header.extend_from_slice(&self.crc16()?.to_le_bytes());
+        }
+        writer.write_all(&header)?;
+        Ok(())
+    }
 
+    /// The bytes of the header up to its CRC16.
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut header = vec![ID1, ID2, self.compression_method.into(), self.flags().0];
+        header.extend_from_slice(&self.modification_time.to_le_bytes());
+        header.extend_from_slice(&[self.extra_flags, self.os]);
         if let Some(extra) = &self.extra {
-            digest.update(&(extra.len() as u16).to_le_bytes());
-            digest.update(extra);
+            let len = u16::try_from(extra.len()).map_err(|_| anyhow!("extra field is too long"))?;
+            header.extend_from_slice(&len.to_le_bytes());
+            header.extend_from_slice(extra);
         }
-
-        if let Some(name) = &self.name {
-            digest.update(name.as_bytes());
-            digest.update(&[0]);
+        for (text, field) in [(&self.name, "name"), (&self.comment, "comment")] {
+            if let Some(text) = text {
+                ensure!(!text.contains('\0'), "{} must not contain nul bytes", field);
+                let bytes = encode_latin1(text)
+                    .ok_or_else(|| anyhow!("{} is not ISO 8859-1 text", field))?;
+                header.extend_from_slice(&bytes);
+                header.push(0);
+            }
         }
+        Ok(header)
+    }
 
-        if let Some(comment) = &self.comment {
-            digest.update(comment.as_bytes());
-            digest.update(&[0]);
-        }
+    /// The subfields of the FEXTRA field, decoded, see `ExtraField::interpret`.
+    pub fn subfields(&self) -> Result<Vec<Subfield>> {
+        let Some(extra) = &self.extra else {
+            return Ok(Vec::new());
+        };
+        ExtraField::decode(extra)?
+            .iter()
+            .map(ExtraField::interpret)
+            .collect()
+    }
 
-        (digest.finalize() & 0xffff) as u16
+    pub fn compression_hint(&self) -> CompressionHint {
+        self.extra_flags.into()
     }
 
     pub fn flags(&self) -> MemberFlags {
@@ -71,6 +121,47 @@ impl MemberHeader {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Meaning of the XFL byte of a deflate member.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionHint {
+    /// Compressed with maximum compression, the slowest algorithm.
+    Slowest,
+    /// Compressed with the fastest algorithm.
+    Fastest,
+    Other(u8),
+}
+
+impl CompressionHint {
+    /// Hint gzip writes for a compression level.
+    pub fn for_level(level: u32) -> Self {
+        match level {
+            9 => Self::Slowest,
+            1 => Self::Fastest,
+            _ => Self::Other(0),
+        }
+    }
+}
+
+impl From<u8> for CompressionHint {
+    fn from(value: u8) -> Self {
+        match value {
+            XFL_SLOWEST => Self::Slowest,
+            XFL_FASTEST => Self::Fastest,
+            x => Self::Other(x),
+        }
+    }
+}
+
+impl From<CompressionHint> for u8 {
+    fn from(hint: CompressionHint) -> u8 {
+        match hint {
+            CompressionHint::Slowest => XFL_SLOWEST,
+            CompressionHint::Fastest => XFL_FASTEST,
+            CompressionHint::Other(x) => x,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum CompressionMethod {
     Deflate,
@@ -97,11 +188,32 @@ impl From<CompressionMethod> for u8 {
 
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct MemberFlags(u8);
+/// FLG byte of a member header.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MemberFlags(pub u8);
 
-#[allow(unused)]
 impl MemberFlags {
+    pub const FTEXT: Self = Self(1 << FTEXT_OFFSET);
+    pub const FHCRC: Self = Self(1 << FHCRC_OFFSET);
+    pub const FEXTRA: Self = Self(1 << FEXTRA_OFFSET);
+    pub const FNAME: Self = Self(1 << FNAME_OFFSET);
+    pub const FCOMMENT: Self = Self(1 << FCOMMENT_OFFSET);
+    /// Bits 5 to 7, which must be zero.
+    pub const RESERVED: Self = Self(0xe0);
+
+    const NAMES: [(Self, &'static str); 5] = [
+        (Self::FTEXT, "FTEXT"),
+        (Self::FHCRC, "FHCRC"),
+        (Self::FEXTRA, "FEXTRA"),
+        (Self::FNAME, "FNAME"),
+        (Self::FCOMMENT, "FCOMMENT"),
+    ];
+
+    /// Whether all the bits set in `flags` are set.
+    pub fn contains(&self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
     fn bit(&self, n: u8) -> bool {
         (self.0 >> n) & 1 != 0
     }
@@ -155,14 +267,167 @@ impl MemberFlags {
     }
 }
 
+/// The flags set separated by `|`, reserved bits as a number, e.g.
+/// `FHCRC | FNAME | 0x20`, or `none`.
+impl fmt::Display for MemberFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| name.to_string())
+            .collect::<Vec<_>>();
+        let reserved = self.0 & Self::RESERVED.0;
+        if reserved != 0 {
+            names.push(format!("{:#04x}", reserved));
+        }
+        match names.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", names.join(" | ")),
+        }
+    }
+}
+
+impl fmt::Debug for MemberFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MemberFlags({})", self)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MemberFooter {
     pub data_crc32: u32,
+    /// Size of the decompressed data modulo 2^32 (ISIZE).
     pub data_size: u32,
 }
 
+impl MemberFooter {
+    /// Write the footer as stored at the end of a member.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&self.data_crc32.to_le_bytes())?;
+        writer.write_all(&self.data_size.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Summary of a decoded member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemberInfo {
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub modification_time: u32,
+    pub os: u8,
+    /// CRC-32 of the decompressed data, from the footer.
+    pub crc32: u32,
+    /// Size of the decompressed data modulo 2^32, from the footer.
+    pub size: u32,
+    /// Byte range of the member in the input, header and footer included, so that
+    /// it can be copied out as is.
+    pub compressed_range: Range<u64>,
+    /// Blocks of each type the member is made of, which tells apart compressors.
+    pub blocks: BlockUsage,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Subfield of the FEXTRA header field, identified by the two bytes SI1 and SI2.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtraField {
+    pub id: [u8; 2],
+    pub data: Vec<u8>,
+}
+
+impl ExtraField {
+    pub fn new(id: [u8; 2], data: Vec<u8>) -> Self {
+        Self { id, data }
+    }
+
+    /// BGZF "BC" subfield holding the size of the whole member minus one.
+    pub fn bgzf_block_size(block_size: u16) -> Self {
+        Self::new(*b"BC", block_size.to_le_bytes().to_vec())
+    }
+
+    /// Encode subfields as the contents of the FEXTRA field, without XLEN.
+    pub fn encode(fields: &[ExtraField]) -> Result<Vec<u8>> {
+        let mut extra = Vec::new();
+        for field in fields {
+            let len = u16::try_from(field.data.len())
+                .map_err(|_| anyhow!("extra subfield is too long"))?;
+            extra.extend_from_slice(&field.id);
+            extra.extend_from_slice(&len.to_le_bytes());
+            extra.extend_from_slice(&field.data);
+        }
+        ensure!(extra.len() <= u16::MAX as usize, "extra field is too long");
+        Ok(extra)
+    }
+
+    /// Decode the contents of the FEXTRA field into its subfields.
+    pub fn decode(mut extra: &[u8]) -> Result<Vec<ExtraField>> {
+        let mut fields = Vec::new();
+        while !extra.is_empty() {
+            let Some(len) = extra.get(2..4) else {
+                bail!("extra subfield overruns xlen");
+            };
+            let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+            let Some(data) = extra.get(4..4 + len) else {
+                bail!("extra subfield overruns xlen");
+            };
+            fields.push(Self::new([extra[0], extra[1]], data.to_vec()));
+            extra = &extra[4 + len..];
+        }
+        Ok(fields)
+    }
+
+    /// Decode the subfield according to its id. Fails if a documented subfield is
+    /// malformed.
+    pub fn interpret(&self) -> Result<Subfield> {
+        Ok(match &self.id {
+            b"BC" => {
+                let size = <[u8; 2]>::try_from(self.data.as_slice())
+                    .map_err(|_| anyhow!("malformed bc subfield"))?;
+                Subfield::BgzfBlockSize(u16::from_le_bytes(size))
+            }
+            b"RA" => {
+                let words = self
+                    .data
+                    .chunks(2)
+                    .map(|word| <[u8; 2]>::try_from(word).map(u16::from_le_bytes))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| anyhow!("malformed ra subfield"))?;
+                ensure!(
+                    words.len() >= 3 && words.len() - 3 == words[2] as usize,
+                    "malformed ra subfield"
+                );
+                Subfield::RandomAccess {
+                    version: words[0],
+                    chunk_length: words[1],
+                    chunk_sizes: words[3..].to_vec(),
+                }
+            }
+            b"AP" => Subfield::Apollo(self.data.clone()),
+            _ => Subfield::Other(self.clone()),
+        })
+    }
+}
+
+/// Subfield of the FEXTRA field, decoded when its kind is documented.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Subfield {
+    /// BGZF "BC": size of the whole member minus one.
+    BgzfBlockSize(u16),
+    /// dictzip "RA": the member data is cut into chunks of `chunk_length` bytes,
+    /// compressed separately, whose compressed sizes are `chunk_sizes`.
+    RandomAccess {
+        version: u16,
+        chunk_length: u16,
+        chunk_sizes: Vec<u16>,
+    },
+    /// Apollo "AP" file type information, whose layout is not documented.
+    Apollo(Vec<u8>),
+    Other(ExtraField),
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 pub struct GzipReader<T> {
@@ -176,46 +441,52 @@ impl<T: BufRead> GzipReader<T> {
 
     pub fn read_header(&mut self) -> Option<Result<[u8; 10]>> {
         let mut header = [0_u8; 10];
-        match self.reader.read(&mut header) {
-            Ok(size) if size == 0 => None,
-            Ok(size) if size < 10 => Some(Err(anyhow!("eof error"))),
-            Ok(_) => Some(Ok(header)),
-            Err(err) => Some(Err(anyhow!(err))),
+        // The header may be split across the buffers of the reader.
+        let mut size = 0;
+        while size < header.len() {
+            match self.reader.read(&mut header[size..]) {
+                Ok(0) => break,
+                Ok(len) => size += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Some(Err(anyhow!(err))),
+            }
+        }
+        match size {
+            0 => None,
+            10 => Some(Ok(header)),
+            _ => Some(Err(anyhow!("eof error"))),
         }
     }
 
-    fn read_crc16(&mut self) -> u16 {
+    fn read_crc16(&mut self) -> Result<u16> {
         let mut crc_ = [0_u8; 2];
-        self.reader.read_exact(&mut crc_).unwrap();
-        u16::from_le_bytes(crc_)
+        self.reader.read_exact(&mut crc_).map_err(header_error)?;
+        Ok(u16::from_le_bytes(crc_))
     }
 
-    fn read_string_until_null(&mut self) -> Option<String> {
+    /// Read a nul-terminated field, see `decode_latin1`.
+    fn read_string_until_null(&mut self) -> Result<Option<String>> {
         let mut data = Vec::new();
-        self.reader.read_until(b'\0', &mut data).unwrap();
-        String::from_utf8(data).ok()
-    }
-
-    fn read_extra(&mut self) -> Option<Vec<u8>> {
-        let mut extra_data = Vec::new();
-        let mut buffer = [0_u8; 4096];
-
-        let mut sz_additional_lines = [0_u8; 2];
-        self.reader.read_exact(&mut sz_additional_lines).ok()?;
-        let len_add = u16::from_le_bytes(sz_additional_lines);
-
-        let mut mutremaining = len_add as usize;
-        while mutremaining > 0 {
-            let to_read = std::cmp::min(mutremaining, buffer.len());
-            let read = self.reader.read(&mut buffer[..to_read]).ok()?;
-            if read == 0 {
-                return None;
-            }
-            extra_data.extend_from_slice(&buffer[..read]);
-            mutremaining -= read;
+        self.reader
+            .read_until(b'\0', &mut data)
+            .map_err(header_error)?;
+        if data.pop_if(|&mut byte| byte == 0).is_none() {
+            bail!("header is truncated");
         }
+        Ok(Some(decode_latin1(&data)))
+    }
 
-        Some(extra_data)
+    /// Read the FEXTRA field, failing if it is shorter than its XLEN.
+    fn read_extra(&mut self) -> Result<Vec<u8>> {
+        let mut xlen = [0_u8; 2];
+        self.reader
+            .read_exact(&mut xlen)
+            .map_err(|_| anyhow!("extra field is truncated"))?;
+        let mut extra = vec![0_u8; u16::from_le_bytes(xlen) as usize];
+        self.reader
+            .read_exact(&mut extra)
+            .map_err(|_| anyhow!("extra field is truncated"))?;
+        Ok(extra)
     }
 
     pub fn parse_header(mut self, header_bytes: &[u8]) -> Result<(MemberHeader, MemberReader<T>)> {
@@ -232,33 +503,134 @@ impl<T: BufRead> GzipReader<T> {
         let res = MemberHeader {
             compression_method,
             modification_time: u32::from_le_bytes((&header_bytes[4..8]).try_into().unwrap()),
-            extra: flags.has_extra().then(|| self.read_extra()).flatten(),
-            name: flags
-                .has_name()
-                .then(|| self.read_string_until_null())
-                .flatten(),
-            comment: flags
-                .has_comment()
-                .then(|| self.read_string_until_null())
-                .flatten(),
+            extra: if flags.has_extra() {
+                Some(self.read_extra()?)
+            } else {
+                None
+            },
+            name: match flags.has_name() {
+                true => self.read_string_until_null()?,
+                false => None,
+            },
+            comment: match flags.has_comment() {
+                true => self.read_string_until_null()?,
+                false => None,
+            },
             extra_flags: header_bytes[8],
             os: header_bytes[9],
             has_crc: flags.has_crc(),
             is_text: flags.is_text(),
         };
 
-        let crc16 = flags
-            .has_crc()
-            .then(|| self.read_crc16())
-            .unwrap_or_default();
+        let crc16 = if flags.has_crc() {
+            self.read_crc16()?
+        } else {
+            0
+        };
 
-        if flags.has_crc() && crc16 != res.crc16() {
+        if flags.has_crc() && crc16 != res.crc16()? {
             bail!("header crc16 check failed");
         }
         Ok((res, MemberReader { inner: self.reader }))
     }
 }
 
+/// Decode an FNAME or FCOMMENT field, which RFC 1952 has in ISO 8859-1: each
+/// byte is the character of the same value, so that any field is taken and written
+/// back as is. Names stored as UTF-8 by other tools come out garbled, see
+/// `encode_latin1` to get their bytes back.
+pub fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+/// The bytes of an FNAME or FCOMMENT field, `None` if `text` has characters past
+/// U+00FF, which ISO 8859-1 lacks.
+pub fn encode_latin1(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// Error of a failed read of the optional header fields: the end of the input
+/// means the header is truncated.
+fn header_error(err: io::Error) -> anyhow::Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => anyhow!("header is truncated"),
+        _ => err.into(),
+    }
+}
+
+/// Length of the member header at the start of `data`, or `None` if `data` ends
+/// before it does.
+#[cfg(any(feature = "zlib", feature = "stream", feature = "testing"))]
+pub(crate) fn header_len(data: &[u8]) -> Option<usize> {
+    let flags = MemberFlags(*data.get(3)?);
+    let mut len = 10;
+    if flags.has_extra() {
+        let xlen = u16::from_le_bytes([*data.get(len)?, *data.get(len + 1)?]);
+        len += 2 + xlen as usize;
+    }
+    for present in [flags.has_name(), flags.has_comment()] {
+        if present {
+            len += data.get(len..)?.iter().position(|&byte| byte == 0)? + 1;
+        }
+    }
+    if flags.has_crc() {
+        len += 2;
+    }
+    (len <= data.len()).then_some(len)
+}
+
+/// Read the header of the gzip member at the start of `reader` and check it
+/// strictly: the magic bytes, the compression method, the reserved flags, the
+/// FEXTRA subfields, the terminators of FNAME and FCOMMENT and the FHCRC. Nothing
+/// past the header is read, nor any of the deflate data checked.
+pub fn validate_header<R: BufRead>(mut reader: R) -> Result<MemberHeader> {
+    let truncated = |_| anyhow!("header is truncated");
+    let mut header_bytes = [0_u8; 10];
+    reader.read_exact(&mut header_bytes).map_err(truncated)?;
+    ensure!(header_bytes[..2] == [ID1, ID2], "wrong id values");
+    ensure!(
+        header_bytes[2] == CM_DEFLATE,
+        "unsupported compression method"
+    );
+    let flags = MemberFlags(header_bytes[3]);
+    ensure!(
+        flags.0 & MemberFlags::RESERVED.0 == 0,
+        "reserved flags are set"
+    );
+
+    // The optional fields, checked here and then parsed as usual.
+    let mut fields = Vec::new();
+    if flags.has_extra() {
+        let mut xlen = [0_u8; 2];
+        reader.read_exact(&mut xlen).map_err(truncated)?;
+        let mut extra = vec![0_u8; u16::from_le_bytes(xlen) as usize];
+        reader
+            .read_exact(&mut extra)
+            .map_err(|_| anyhow!("extra field is truncated"))?;
+        check_extra_subfields(&extra)?;
+        fields.extend(xlen);
+        fields.extend(extra);
+    }
+    for (present, field) in [(flags.has_name(), "name"), (flags.has_comment(), "comment")] {
+        if present {
+            reader.read_until(0, &mut fields)?;
+            ensure!(fields.last() == Some(&0), "{} is not terminated", field);
+        }
+    }
+    if flags.has_crc() {
+        let mut crc16 = [0_u8; 2];
+        reader.read_exact(&mut crc16).map_err(truncated)?;
+        fields.extend(crc16);
+    }
+    let (header, _) = GzipReader::new(fields.as_slice()).parse_header(&header_bytes)?;
+    Ok(header)
+}
+
+/// Check that the subfields of an FEXTRA field fill it exactly.
+fn check_extra_subfields(extra: &[u8]) -> Result<()> {
+    ExtraField::decode(extra).map(drop)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 pub struct MemberReader<T> {
@@ -266,8 +638,12 @@ pub struct MemberReader<T> {
 }
 
 impl<T: BufRead> MemberReader<T> {
-    pub fn inner_mut(&mut self) -> &mut T {
-        &mut self.inner
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
     }
 
     pub fn read_footer(mut self) -> Result<(MemberFooter, GzipReader<T>)> {
@@ -283,3 +659,540 @@ impl<T: BufRead> MemberReader<T> {
         Ok((footer, reader))
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone)]
+enum MemberState {
+    Header,
+    Body,
+    Done,
+    /// The input is not gzip compressed and is handed out as is.
+    Passthrough,
+}
+
+#[derive(Clone, Debug)]
+pub struct DecompressOptions {
+    /// Keep decoding members until the end of the input. Otherwise stop after the
+    /// first member, leaving whatever follows it in the reader.
+    pub multi_member: bool,
+    /// Require the stream to end with the BGZF EOF member, to detect truncated
+    /// BGZF files.
+    pub strict_bgzf: bool,
+    /// Require the FEXTRA fields to be made of well-formed subfields. Some writers
+    /// store free-form data there, so this is off by default.
+    pub strict_extra: bool,
+    /// Number of decompressed bytes decoded at a time, see
+    /// `DeflateDecoder::set_buffer_size`. Larger buffers mean fewer and larger
+    /// writes to the output.
+    pub buffer_size: usize,
+    /// Copy the input as is if it does not start with the gzip magic bytes, as
+    /// `zcat -f` does, instead of failing.
+    pub passthrough: bool,
+    /// Maximum rate the decompressed data is handed out at, in bytes per second.
+    /// The decoder sleeps, blocking the thread, until more output is due.
+    pub rate_limit: Option<NonZeroU64>,
+}
+
+impl Default for DecompressOptions {
+    fn default() -> Self {
+        Self {
+            multi_member: true,
+            strict_bgzf: false,
+            strict_extra: false,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            passthrough: false,
+            rate_limit: None,
+        }
+    }
+}
+
+/// Callback of `GzipDecoder::set_checkpoint_callback`.
+#[derive(Clone)]
+struct CheckpointCallback {
+    spacing: u64,
+    /// Uncompressed offset of the last point reported.
+    last: Option<u64>,
+    callback: Arc<Mutex<dyn FnMut(CheckpointInfo) + Send>>,
+}
+
+/// Streaming decoder of a (possibly multi-member) gzip stream.
+#[derive(Clone)]
+pub struct GzipDecoder<R, C = Crc32> {
+    deflate: DeflateDecoder<R, C>,
+    state: MemberState,
+    options: DecompressOptions,
+    deferred_footers: Option<Vec<MemberFooter>>,
+    /// Whether the current or last member is the BGZF EOF member.
+    bgzf_eof: bool,
+    /// Whether decoding started in the middle of the current member, whose footer
+    /// can't be checked then.
+    partial_member: bool,
+    /// The members seen so far, if they are recorded.
+    members: Option<Vec<MemberInfo>>,
+    header: Option<MemberHeader>,
+    /// Input offset at which the last `resync` stopped.
+    resync_offset: Option<u64>,
+    /// Number of member headers read so far.
+    member_count: u64,
+    checkpoint_callback: Option<CheckpointCallback>,
+    /// When the first data was asked for, to enforce the rate limit from.
+    started: Option<Instant>,
+}
+
+impl<R: BufRead> GzipDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, DecompressOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: DecompressOptions) -> Self {
+        let mut deflate = DeflateDecoder::new(reader);
+        deflate.set_buffer_size(options.buffer_size);
+        Self {
+            deflate,
+            state: MemberState::Header,
+            options,
+            deferred_footers: None,
+            bgzf_eof: false,
+            partial_member: false,
+            members: None,
+            header: None,
+            resync_offset: None,
+            member_count: 0,
+            checkpoint_callback: None,
+            started: None,
+        }
+    }
+
+    /// Continue decoding from `checkpoint`.
+    pub fn restore(reader: R, checkpoint: &Checkpoint) -> Result<Self> {
+        let state = match checkpoint.framing {
+            Framing::GzipHeader => MemberState::Header,
+            Framing::GzipBody => MemberState::Body,
+            Framing::GzipDone => MemberState::Done,
+            Framing::Raw => bail!("checkpoint was taken from a different decoder"),
+        };
+        let checksum = Crc32::resume(checkpoint.checksum);
+        Ok(Self {
+            deflate: DeflateDecoder::from_checkpoint(reader, checkpoint, checksum)?,
+            state,
+            options: DecompressOptions::default(),
+            deferred_footers: None,
+            bgzf_eof: false,
+            partial_member: false,
+            members: None,
+            header: None,
+            resync_offset: None,
+            member_count: 0,
+            checkpoint_callback: None,
+            started: None,
+        })
+    }
+}
+
+impl<R: BufRead> GzipDecoder<R> {
+    /// Start decoding in the middle of a member, at a block boundary: `reader`
+    /// stands at `offset` in the input, `pending` are the bits of the previous byte
+    /// belonging to the next block, and `window` the data preceding the block in
+    /// its member.
+    #[cfg(feature = "zlib")]
+    pub(crate) fn at_block_boundary(
+        reader: R,
+        offset: u64,
+        pending: BitSequence,
+        window: &[u8],
+    ) -> Result<Self> {
+        let checkpoint = Checkpoint {
+            framing: Framing::GzipBody,
+            input_offset: offset,
+            pending_bits: pending,
+            block: BlockCheckpoint::Header,
+            byte_count: 0,
+            checksum: 0,
+            window: window[window.len().saturating_sub(WINDOW_SIZE)..].to_vec(),
+        };
+        let mut decoder = Self::restore(reader, &checkpoint)?;
+        decoder.partial_member = true;
+        Ok(decoder)
+    }
+}
+
+impl<R: BufRead, C: Checksum> GzipDecoder<R, C> {
+    /// Record the members as they are decoded, for `take_members`.
+    pub(crate) fn record_members(&mut self) {
+        self.members = Some(Vec::new());
+    }
+
+    /// Check the stream without handing out the decoded data: `fill_buffer` only
+    /// returns once the stream is over.
+    pub(crate) fn discard_output(&mut self) {
+        self.deflate.discard_output();
+    }
+
+    pub(crate) fn take_members(&mut self) -> Vec<MemberInfo> {
+        self.members
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+impl<R: BufRead> GzipDecoder<R, NoChecksum> {
+    /// Create a decoder which leaves the crc32 check to the caller: the footers of
+    /// the finished members are collected and handed out by `take_footers`.
+    pub(crate) fn with_deferred_crc(reader: R) -> Self {
+        Self {
+            deflate: DeflateDecoder::with_checksum(reader, NoChecksum),
+            state: MemberState::Header,
+            options: DecompressOptions::default(),
+            deferred_footers: Some(Vec::new()),
+            bgzf_eof: false,
+            partial_member: false,
+            members: None,
+            header: None,
+            resync_offset: None,
+            member_count: 0,
+            checkpoint_callback: None,
+            started: None,
+        }
+    }
+
+    pub(crate) fn take_footers(&mut self) -> Vec<MemberFooter> {
+        self.deferred_footers
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+impl<R: BufRead, C: Checksum> GzipDecoder<R, C> {
+    /// Whether the decoder stands between two blocks or members and all the decoded
+    /// data was read.
+    pub fn is_at_block_boundary(&self) -> bool {
+        self.deflate.is_at_block_boundary()
+    }
+
+    /// Header of the current or last member, once decoding reached it, e.g. after
+    /// a call to `fill_buf`.
+    pub fn header(&self) -> Option<&MemberHeader> {
+        self.header.as_ref()
+    }
+
+    /// The window of the current member, oldest byte first. Only the first
+    /// `window_len()` bytes are valid.
+    pub fn window_snapshot(&self) -> [u8; WINDOW_SIZE] {
+        self.deflate.window_snapshot()
+    }
+
+    pub fn window_len(&self) -> usize {
+        self.deflate.window_len()
+    }
+
+    /// Number of bytes consumed from the reader so far.
+    pub fn total_in(&self) -> u64 {
+        self.deflate.total_in()
+    }
+
+    /// Offset of the next unread bit, in bits from the start of the input.
+    pub fn bit_position(&self) -> u64 {
+        self.deflate.bit_position()
+    }
+
+    /// Number of input bytes fully decoded so far.
+    pub fn position(&self) -> u64 {
+        self.deflate.position()
+    }
+
+    /// Number of decompressed bytes read from the decoder so far.
+    pub fn total_out(&self) -> u64 {
+        self.deflate.total_out()
+    }
+
+    /// See `DeflateDecoder::set_trace`.
+    #[cfg(feature = "trace-decode")]
+    pub fn set_trace<S: TraceSink + 'static>(&mut self, sink: S, max_symbols: usize) {
+        self.deflate.set_trace(sink, max_symbols);
+    }
+
+    /// Call `callback` at the first block boundary after every `spacing` bytes of
+    /// output, starting with the first one, as `GzipIndex::build` records points.
+    /// The points are where decoding can be resumed given the window.
+    pub fn set_checkpoint_callback<F: FnMut(CheckpointInfo) + Send + 'static>(
+        &mut self,
+        spacing: u64,
+        callback: F,
+    ) {
+        self.checkpoint_callback = Some(CheckpointCallback {
+            spacing,
+            last: None,
+            callback: Arc::new(Mutex::new(callback)),
+        });
+    }
+
+    /// See `DeflateDecoder::bits_consumed`.
+    pub fn bits_consumed(&self) -> u64 {
+        self.deflate.bits_consumed()
+    }
+
+    /// See `DeflateDecoder::bytes_emitted`.
+    pub fn bytes_emitted(&self) -> u64 {
+        self.deflate.bytes_emitted()
+    }
+
+    /// Index of the current or last member, counted from the first member the
+    /// decoder read the header of.
+    pub fn current_member_index(&self) -> u64 {
+        self.member_count.saturating_sub(1)
+    }
+
+    /// Index of the block being decoded in the current member, see
+    /// `DeflateDecoder::current_block_index`.
+    pub fn current_block_index(&self) -> u64 {
+        self.deflate.current_block_index()
+    }
+
+    /// Limit the window to `size` bytes, see `DeflateDecoder::set_window_size`.
+    pub fn set_window_size(&mut self, size: usize) {
+        self.deflate.set_window_size(size);
+    }
+
+    /// Return at most `n` decompressed bytes, see `DeflateDecoder::decode_up_to`.
+    pub fn decode_up_to(&mut self, n: usize) -> Result<&[u8]> {
+        assert!(n > 0, "decode limit must be positive");
+        self.fill_buffer_up_to(n)?;
+        match self.state {
+            MemberState::Passthrough => self.deflate.take_input(n),
+            _ => Ok(self.deflate.take_buffer(n)),
+        }
+    }
+
+    /// Blocks of each type decoded so far in the current member, see
+    /// `DeflateDecoder::block_usage`.
+    pub fn block_usage(&self) -> BlockUsage {
+        self.deflate.block_usage()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.deflate.get_ref()
+    }
+
+    /// Return the underlying reader. Once the stream is over, it is positioned right
+    /// after the last member footer, so the data following the gzip stream can be read
+    /// from it (see `DecompressOptions::multi_member`).
+    pub fn into_inner(self) -> R {
+        self.deflate.into_inner()
+    }
+
+    /// Skip the input up to the next point decoding can restart from after an error,
+    /// as zlib's `inflateSync` does: the block after an empty stored block, which a
+    /// full flush writes, or the next gzip magic bytes. Returns the number of bytes
+    /// skipped, and fails if the input ends first.
+    ///
+    /// Decoding restarted in the middle of a member can't refer to the data before
+    /// the stored block, and the footer of the member is not checked. A second call
+    /// without progress in between skips at least one byte, so that false positives
+    /// are eventually passed.
+    pub fn resync(&mut self) -> Result<u64> {
+        let mut skipped = 0;
+        if self.resync_offset == Some(self.total_in()) {
+            let reader = self.deflate.borrow_reader_from_boundary();
+            if !reader.fill_buf()?.is_empty() {
+                reader.consume(1);
+                skipped += 1;
+            }
+        }
+        let found = self.deflate.skip_to_sync_point()?;
+        self.resync_offset = Some(self.total_in());
+        match found {
+            Some((SyncPoint::Block, size)) => {
+                self.state = MemberState::Body;
+                self.partial_member = true;
+                Ok(skipped + size)
+            }
+            Some((SyncPoint::Member, size)) => {
+                self.state = MemberState::Header;
+                Ok(skipped + size)
+            }
+            None => bail!("no sync point found"),
+        }
+    }
+
+    /// Capture the full decoder state. All the decoded data must be read beforehand.
+    pub fn checkpoint(&self) -> Result<Checkpoint> {
+        self.deflate.checkpoint_with(match self.state {
+            MemberState::Header => Framing::GzipHeader,
+            MemberState::Body => Framing::GzipBody,
+            MemberState::Done => Framing::GzipDone,
+            MemberState::Passthrough => bail!("input copied as is has no checkpoint"),
+        })
+    }
+
+    pub(crate) fn fill_buffer(&mut self) -> Result<&[u8]> {
+        self.fill_buffer_up_to(self.deflate.buffer_size())
+    }
+
+    fn fill_buffer_up_to(&mut self, limit: usize) -> Result<&[u8]> {
+        if let Some(rate) = self.options.rate_limit {
+            self.throttle(rate);
+        }
+        if self.checkpoint_callback.is_some() {
+            self.report_checkpoint();
+        }
+        loop {
+            match self.state {
+                MemberState::Header => {
+                    if self.options.passthrough && self.header.is_none() {
+                        let reader = self.deflate.borrow_reader_from_boundary();
+                        let data = reader.fill_buf()?;
+                        let len = std::cmp::min(data.len(), 2);
+                        if data.is_empty() || data[..len] != [ID1, ID2][..len] {
+                            self.state = MemberState::Passthrough;
+                            continue;
+                        }
+                    }
+                    let start = self.deflate.total_in();
+                    let mut gzip_reader =
+                        GzipReader::new(self.deflate.borrow_reader_from_boundary());
+                    match gzip_reader.read_header() {
+                        Some(header) => {
+                            let (header, _) = gzip_reader.parse_header(&header?)?;
+                            let extra = header.extra.as_deref();
+                            if let Some(extra) = extra.filter(|_| self.options.strict_extra) {
+                                check_extra_subfields(extra)?;
+                            }
+                            if let Some(members) = &mut self.members {
+                                members.push(MemberInfo {
+                                    name: header.name.clone(),
+                                    comment: header.comment.clone(),
+                                    modification_time: header.modification_time,
+                                    os: header.os,
+                                    crc32: 0,
+                                    size: 0,
+                                    compressed_range: start..start,
+                                    blocks: BlockUsage::default(),
+                                });
+                            }
+                            self.bgzf_eof = header.extra.as_deref() == Some(&EOF_EXTRA);
+                            self.header = Some(header);
+                            self.member_count += 1;
+                            #[cfg(feature = "trace-decode")]
+                            self.deflate
+                                .record_trace(TraceEvent::MemberStart { offset: start });
+                            self.deflate.reset();
+                            self.state = MemberState::Body;
+                        }
+                        None => {
+                            if self.options.strict_bgzf && !self.bgzf_eof {
+                                bail!("missing bgzf eof marker");
+                            }
+                            self.state = MemberState::Done;
+                        }
+                    }
+                }
+                MemberState::Body => {
+                    if !self.deflate.fill_buffer_up_to(limit)?.is_empty() {
+                        break;
+                    }
+                    let reader = MemberReader::new(self.deflate.borrow_reader_from_boundary());
+                    let (footer, _) = reader.read_footer()?;
+                    self.validate_footer(footer)?;
+                    if let Some(member) = self.members.as_mut().and_then(|m| m.last_mut()) {
+                        member.crc32 = footer.data_crc32;
+                        member.size = footer.data_size;
+                        member.compressed_range.end = self.deflate.total_in();
+                        member.blocks = self.deflate.block_usage();
+                    }
+                    self.bgzf_eof &= footer.data_size == 0;
+                    self.state = if self.options.multi_member {
+                        MemberState::Header
+                    } else {
+                        MemberState::Done
+                    };
+                }
+                MemberState::Done => break,
+                MemberState::Passthrough => return self.deflate.fill_input(),
+            }
+        }
+        self.deflate.fill_buffer_up_to(limit)
+    }
+
+    pub(crate) fn consume_buffer(&mut self, amount: usize) {
+        match self.state {
+            MemberState::Passthrough => self.deflate.consume_input(amount),
+            _ => self.deflate.consume_buffer(amount),
+        }
+    }
+
+    /// Sleep until the output read so far is due at `rate` bytes per second.
+    fn throttle(&mut self, rate: NonZeroU64) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let due = Duration::from_secs_f64(self.deflate.total_out() as f64 / rate.get() as f64);
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    fn report_checkpoint(&mut self) {
+        let Some(checkpoints) = &mut self.checkpoint_callback else {
+            return;
+        };
+        let total_out = self.deflate.total_out();
+        let due = checkpoints
+            .last
+            .is_none_or(|last| total_out - last >= checkpoints.spacing);
+        if !due
+            || !matches!(self.state, MemberState::Body)
+            || !self.deflate.is_at_block_boundary()
+            || self.deflate.is_finished()
+        {
+            return;
+        }
+        let mut hash = Crc32::default();
+        hash.update(&self.deflate.window_snapshot()[..self.deflate.window_len()]);
+        let bit_offset = self.deflate.bit_position();
+        checkpoints.last = Some(total_out);
+        (checkpoints.callback.lock().unwrap())(CheckpointInfo {
+            compressed_offset: bit_offset / 8,
+            bit_offset,
+            uncompressed_offset: total_out,
+            window_hash: hash.value(),
+        });
+    }
+
+    fn validate_footer(&mut self, footer: MemberFooter) -> Result<()> {
+        if std::mem::take(&mut self.partial_member) {
+            return Ok(());
+        }
+        if self.deflate.byte_count() as u32 != footer.data_size {
+            bail!("length check failed");
+        }
+        if let Some(footers) = &mut self.deferred_footers {
+            footers.push(footer);
+            return Ok(());
+        }
+        if self.deflate.checksum() != footer.data_crc32 {
+            bail!("crc32 check failed");
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, C: Checksum> Read for GzipDecoder<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl<R: BufRead, C: Checksum> BufRead for GzipDecoder<R, C> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill_buffer().map_err(into_io_error)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consume_buffer(amount)
+    }
+}
@@ -0,0 +1,372 @@
+#![forbid(unsafe_code)]
+
+//! Raw deflate decoder working in buffers supplied by the caller, for targets
+//! without an allocator: the input, the window and the Huffman tables are
+//! borrowed, and the output is read in chunks. Besides the tables of `deflate`
+//! it only uses `core`, so that it can be copied into `no_std` firmware, the rest
+//! of the crate needing `std`.
+
+use core::fmt;
+
+use crate::decoder::WINDOW_SIZE;
+use crate::deflate::{
+    CODE_LENGTH_ORDER, DIST_BASE, DIST_EXTRA, FIXED_DISTANCE_BITS, FIXED_LIT_LEN_LENGTHS,
+    LENGTH_BASE, LENGTH_EXTRA, MAX_CODE_LENGTH,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const MAX_BITS: usize = MAX_CODE_LENGTH as usize;
+const MAX_LITLEN_CODES: usize = FIXED_LIT_LEN_LENGTHS.len();
+const MAX_DIST_CODES: usize = DIST_BASE.len();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaplessError {
+    UnexpectedEof,
+    InvalidBlockType,
+    /// The length of a stored block doesn't match its complement.
+    StoredLengthMismatch,
+    InvalidCodeLengths,
+    InvalidSymbol,
+    DistanceTooFar,
+}
+
+impl fmt::Display for HeaplessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnexpectedEof => "unexpected end of input",
+            Self::InvalidBlockType => "invalid block type",
+            Self::StoredLengthMismatch => "stored block length mismatch",
+            Self::InvalidCodeLengths => "invalid code lengths",
+            Self::InvalidSymbol => "invalid symbol",
+            Self::DistanceTooFar => "distance too far back",
+        })
+    }
+}
+
+impl core::error::Error for HeaplessError {}
+
+type Result<T> = core::result::Result<T, HeaplessError>;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Canonical Huffman code, as the number of codes of each length and the symbols
+/// ordered by code.
+#[derive(Clone, Copy)]
+struct Code<const N: usize> {
+    counts: [u16; MAX_BITS + 1],
+    symbols: [u16; N],
+}
+
+impl<const N: usize> Code<N> {
+    const fn new() -> Self {
+        Self {
+            counts: [0; MAX_BITS + 1],
+            symbols: [0; N],
+        }
+    }
+
+    /// Build the code from the lengths of the symbols, 0 for unused ones.
+    /// Incomplete codes are allowed, as a single distance code needs one.
+    fn build(&mut self, lengths: &[u8]) -> Result<()> {
+        self.counts = [0; MAX_BITS + 1];
+        for &len in lengths {
+            self.counts[len as usize] += 1;
+        }
+        let mut left = 1i32;
+        for len in 1..=MAX_BITS {
+            left = (left << 1) - self.counts[len] as i32;
+            if left < 0 {
+                return Err(HeaplessError::InvalidCodeLengths);
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + self.counts[len];
+        }
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                self.symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= bits.take(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(HeaplessError::InvalidSymbol)
+    }
+}
+
+/// Storage of the Huffman tables of the block being decoded, about 1.5 KiB, to be
+/// placed e.g. in a static.
+#[derive(Clone)]
+pub struct HuffmanTables {
+    litlen: Code<MAX_LITLEN_CODES>,
+    dist: Code<MAX_DIST_CODES>,
+    lengths: [u8; MAX_LITLEN_CODES + MAX_DIST_CODES],
+    /// Whether the block uses the fixed codes, whose distances are read as plain
+    /// 5-bit numbers rather than with `dist`.
+    fixed: bool,
+}
+
+impl HuffmanTables {
+    pub const fn new() -> Self {
+        Self {
+            litlen: Code::new(),
+            dist: Code::new(),
+            lengths: [0; MAX_LITLEN_CODES + MAX_DIST_CODES],
+            fixed: false,
+        }
+    }
+
+    fn build_fixed(&mut self) -> Result<()> {
+        self.fixed = true;
+        self.litlen.build(&FIXED_LIT_LEN_LENGTHS)
+    }
+
+    fn build_dynamic(&mut self, bits: &mut Bits<'_>) -> Result<()> {
+        self.fixed = false;
+        let litlen_count = bits.take(5)? as usize + 257;
+        let dist_count = bits.take(5)? as usize + 1;
+        let code_length_count = bits.take(4)? as usize + 4;
+        if litlen_count > 286 || dist_count > MAX_DIST_CODES {
+            return Err(HeaplessError::InvalidCodeLengths);
+        }
+
+        // The code length code is kept in the distance table until it is built.
+        let mut code_lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = bits.take(3)? as u8;
+        }
+        self.dist.build(&code_lengths)?;
+
+        let total = litlen_count + dist_count;
+        let mut index = 0;
+        while index < total {
+            let (len, repeat) = match self.dist.decode(bits)? {
+                len @ 0..=15 => (len as u8, 1),
+                16 if index > 0 => (self.lengths[index - 1], 3 + bits.take(2)?),
+                16 => return Err(HeaplessError::InvalidCodeLengths),
+                17 => (0, 3 + bits.take(3)?),
+                _ => (0, 11 + bits.take(7)?),
+            };
+            let end = index + repeat as usize;
+            if end > total {
+                return Err(HeaplessError::InvalidCodeLengths);
+            }
+            self.lengths[index..end].fill(len);
+            index = end;
+        }
+        if self.lengths[256] == 0 {
+            return Err(HeaplessError::InvalidCodeLengths);
+        }
+        self.litlen.build(&self.lengths[..litlen_count])?;
+        self.dist.build(&self.lengths[litlen_count..total])
+    }
+}
+
+impl Default for HuffmanTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Input read a bit at a time, least significant first.
+struct Bits<'a> {
+    input: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u8,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, len: u8) -> Result<u16> {
+        while self.count < len {
+            let byte = *self
+                .input
+                .get(self.pos)
+                .ok_or(HeaplessError::UnexpectedEof)?;
+            self.pos += 1;
+            self.buf |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buf & ((1 << len) - 1);
+        self.buf >>= len;
+        self.count -= len;
+        Ok(value as u16)
+    }
+
+    /// Drop the bits left of the current byte, less than 8 being buffered.
+    fn align_to_byte(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .input
+            .get(self.pos)
+            .ok_or(HeaplessError::UnexpectedEof)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    /// Number of bytes of the stored block left.
+    Stored(u16),
+    Huffman,
+    Done,
+}
+
+/// Decoder of a raw deflate stream held in memory, without heap allocations.
+pub struct HeaplessInflater<'a> {
+    bits: Bits<'a>,
+    window: &'a mut [u8; WINDOW_SIZE],
+    tables: &'a mut HuffmanTables,
+    state: State,
+    is_final: bool,
+    /// Length and distance of the match not fully copied yet.
+    pending: (usize, usize),
+    total_out: u64,
+}
+
+impl<'a> HeaplessInflater<'a> {
+    pub fn new(
+        input: &'a [u8],
+        window: &'a mut [u8; WINDOW_SIZE],
+        tables: &'a mut HuffmanTables,
+    ) -> Self {
+        Self {
+            bits: Bits {
+                input,
+                pos: 0,
+                buf: 0,
+                count: 0,
+            },
+            window,
+            tables,
+            state: State::Header,
+            is_final: false,
+            pending: (0, 0),
+            total_out: 0,
+        }
+    }
+
+    /// Number of input bytes used so far, including a partly read last byte.
+    pub fn consumed(&self) -> usize {
+        self.bits.pos
+    }
+
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Done && self.pending.0 == 0
+    }
+
+    /// Decode into `output`, returning the number of bytes written, 0 once the
+    /// stream is over.
+    pub fn read(&mut self, output: &mut [u8]) -> Result<usize> {
+        let mut written = 0;
+        while written < output.len() {
+            let byte = match self.next_byte()? {
+                Some(byte) => byte,
+                None => break,
+            };
+            output[written] = byte;
+            self.window[self.total_out as usize % WINDOW_SIZE] = byte;
+            self.total_out += 1;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        loop {
+            let (len, dist) = self.pending;
+            if len > 0 {
+                self.pending.0 -= 1;
+                let pos = (self.total_out - dist as u64) as usize % WINDOW_SIZE;
+                return Ok(Some(self.window[pos]));
+            }
+            match self.state {
+                State::Header if self.is_final => self.state = State::Done,
+                State::Header => self.read_block_header()?,
+                State::Stored(0) => self.state = State::Header,
+                State::Stored(left) => {
+                    self.state = State::Stored(left - 1);
+                    return self.bits.byte().map(Some);
+                }
+                State::Huffman => match self.tables.litlen.decode(&mut self.bits)? {
+                    symbol @ 0..=255 => return Ok(Some(symbol as u8)),
+                    256 => self.state = State::Header,
+                    symbol => self.read_match(symbol as usize - 257)?,
+                },
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    fn read_block_header(&mut self) -> Result<()> {
+        self.is_final = self.bits.take(1)? == 1;
+        match self.bits.take(2)? {
+            0 => {
+                self.bits.align_to_byte();
+                let len = u16::from_le_bytes([self.bits.byte()?, self.bits.byte()?]);
+                let nlen = u16::from_le_bytes([self.bits.byte()?, self.bits.byte()?]);
+                if len != !nlen {
+                    return Err(HeaplessError::StoredLengthMismatch);
+                }
+                self.state = State::Stored(len);
+                return Ok(());
+            }
+            1 => self.tables.build_fixed()?,
+            2 => self.tables.build_dynamic(&mut self.bits)?,
+            _ => return Err(HeaplessError::InvalidBlockType),
+        }
+        self.state = State::Huffman;
+        Ok(())
+    }
+
+    fn read_match(&mut self, index: usize) -> Result<()> {
+        if index >= LENGTH_BASE.len() {
+            return Err(HeaplessError::InvalidSymbol);
+        }
+        let len = LENGTH_BASE[index] + self.bits.take(LENGTH_EXTRA[index])?;
+        let index = match self.tables.fixed {
+            // The codes come most significant bit first, the bits the other way round.
+            true => {
+                let code = self.bits.take(FIXED_DISTANCE_BITS)?;
+                (code.reverse_bits() >> (16 - FIXED_DISTANCE_BITS)) as usize
+            }
+            false => self.tables.dist.decode(&mut self.bits)? as usize,
+        };
+        if index >= DIST_BASE.len() {
+            return Err(HeaplessError::InvalidSymbol);
+        }
+        let dist = DIST_BASE[index] + self.bits.take(DIST_EXTRA[index])?;
+        if dist as u64 > self.total_out {
+            return Err(HeaplessError::DistanceTooFar);
+        }
+        self.pending = (len as usize, dist as usize);
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
 #![forbid(unsafe_code)]
 
-use std::{collections::HashMap, convert::TryFrom, io::BufRead};
+use std::{convert::TryFrom, io::BufRead};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 
 use crate::bit_reader::{BitReader, BitSequence};
+use crate::deflate::{
+    CODE_LENGTH_ORDER, DIST_BASE, DIST_EXTRA, FIXED_DISTANCE_BITS, FIXED_LIT_LEN_LENGTHS,
+};
 use crate::huffman_coding::LitLenToken::{EndOfBlock, Length, Literal};
 use crate::huffman_coding::TreeCodeToken::{CopyPrev, RepeatZero};
 
@@ -18,21 +21,13 @@ pub fn decode_litlen_distance_trees<T: BufRead>(
     let num_distance_tokens = bit_reader.read_bits(5)?.bits() + 1;
     let num_code_lengths = bit_reader.read_bits(4)?.bits() + 4;
 
-    for (num, val) in [
-        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
-    ]
-    .iter()
-    .enumerate()
-    {
-        if num >= num_code_lengths as usize {
-            break;
-        }
-        code_lengths[*val as usize] = bit_reader.read_bits(3)?.bits() as u8;
+    for &symbol in &CODE_LENGTH_ORDER[..num_code_lengths as usize] {
+        code_lengths[symbol] = bit_reader.read_bits(3)?.bits() as u8;
     }
 
     let encoder = HuffmanCoding::<TreeCodeToken>::from_lengths(&code_lengths)?;
 
-    let mut token_lengths = vec![
+    let mut token_lengths = [
         Vec::<u8>::with_capacity(num_litlen_tokens as usize),
         Vec::<u8>::with_capacity(num_distance_tokens as usize),
     ];
@@ -48,7 +43,7 @@ pub fn decode_litlen_distance_trees<T: BufRead>(
                 }
                 RepeatZero { base, extra_bits } => {
                     let copy_cnt = bit_reader.read_bits(extra_bits)?.bits() + base;
-                    length_vec.extend(std::iter::repeat(0).take(copy_cnt as usize));
+                    length_vec.extend(std::iter::repeat_n(0, copy_cnt as usize));
                 }
             }
         }
@@ -60,6 +55,17 @@ pub fn decode_litlen_distance_trees<T: BufRead>(
     ))
 }
 
+/// Trees of the blocks compressed with fixed codes. The literal/length lengths
+/// stop at the last valid symbol: the codes of the others are never assigned to
+/// anything. Distances are read as plain 5-bit numbers.
+pub fn fixed_litlen_distance_trees(
+) -> Result<(HuffmanCoding<LitLenToken>, HuffmanCoding<DistanceToken>)> {
+    Ok((
+        HuffmanCoding::<LitLenToken>::from_lengths(&FIXED_LIT_LEN_LENGTHS[..286])?,
+        HuffmanCoding::<DistanceToken>::fixed_width(FIXED_DISTANCE_BITS, DIST_BASE.len()),
+    ))
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Clone, Copy, Debug)]
@@ -102,7 +108,6 @@ impl TryFrom<HuffmanCodeWord> for LitLenToken {
     type Error = anyhow::Error;
 
     fn try_from(value: HuffmanCodeWord) -> Result<Self> {
-        assert!(value.0 <= 285);
         match value.0 {
             256 => Ok(EndOfBlock),
             0..=255 => Ok(Literal(value.0 as u8)),
@@ -153,10 +158,11 @@ impl TryFrom<HuffmanCodeWord> for LitLenToken {
                     extra_bits: 5,
                 })
             }
-            _ => Ok(Length {
+            285 => Ok(Length {
                 base: 258,
                 extra_bits: 0,
             }),
+            _ => bail!("invalid length code {}", value.0),
         }
     }
 }
@@ -173,43 +179,12 @@ impl TryFrom<HuffmanCodeWord> for DistanceToken {
     type Error = anyhow::Error;
 
     fn try_from(value: HuffmanCodeWord) -> Result<Self> {
-        const TABLE: [(u16, u8); 30] = [
-            (1, 0),
-            (2, 0),
-            (3, 0),
-            (4, 0),
-            (5, 1),
-            (7, 1),
-            (9, 2),
-            (13, 2),
-            (17, 3),
-            (25, 3),
-            (33, 4),
-            (49, 4),
-            (65, 5),
-            (97, 5),
-            (129, 6),
-            (193, 6),
-            (257, 7),
-            (385, 7),
-            (513, 8),
-            (769, 8),
-            (1025, 9),
-            (1537, 9),
-            (2049, 10),
-            (3073, 10),
-            (4097, 11),
-            (6145, 11),
-            (8193, 12),
-            (12289, 12),
-            (16385, 13),
-            (24577, 13),
-        ];
-
-        if let Some(&(base, extra_bits)) = TABLE.get(value.0 as usize) {
-            Ok(DistanceToken { base, extra_bits })
-        } else {
-            bail!("wrong code")
+        match DIST_BASE.get(value.0 as usize) {
+            Some(&base) => Ok(DistanceToken {
+                base,
+                extra_bits: DIST_EXTRA[value.0 as usize],
+            }),
+            None => bail!("invalid distance code {}", value.0),
         }
     }
 }
@@ -218,63 +193,266 @@ impl TryFrom<HuffmanCodeWord> for DistanceToken {
 
 const MAX_BITS: usize = 15;
 
+/// Bits looked up at once when reading a symbol. Longer codes, which are rare,
+/// are decoded from the number of codes of each length.
+const LOOKUP_BITS: u8 = 9;
+
 pub struct HuffmanCodeWord(pub u16);
 
+#[derive(Clone)]
 pub struct HuffmanCoding<T> {
-    map: HashMap<BitSequence, T>,
+    /// Number of codes of each length.
+    counts: Vec<u16>,
+    /// Symbols in the order of their codes.
+    symbols: Vec<T>,
+    /// Index into `symbols` and code length for each value of the next
+    /// `LOOKUP_BITS` bits, as read. A zero length stands for longer codes.
+    lookup: Vec<(u16, u8)>,
+    lengths: Vec<u8>,
+    /// Length of all the codes, if they are the symbols themselves.
+    fixed_width: Option<u8>,
+}
+
+impl<T> HuffmanCoding<T> {
+    /// Code lengths the coding was built from, indexed by symbol.
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
 }
 
 impl<T> HuffmanCoding<T>
 where
     T: Copy + TryFrom<HuffmanCodeWord, Error = anyhow::Error>,
 {
+    /// A code of `count` symbols whose codes are the symbols themselves, `bits`
+    /// long, as the fixed distance code. They are read as numbers, without a
+    /// lookup; those `T` doesn't take, as distances 30 and 31, fail then.
+    pub fn fixed_width(bits: u8, count: usize) -> Self {
+        Self {
+            counts: Vec::new(),
+            symbols: Vec::new(),
+            lookup: Vec::new(),
+            lengths: vec![bits; count],
+            fixed_width: Some(bits),
+        }
+    }
+
+    /// The symbol of the code `seq`, most significant bit first.
     #[allow(unused)]
     pub fn decode_symbol(&self, seq: BitSequence) -> Option<T> {
-        if let Some(symbol) = self.map.get(&seq) {
-            return Some(*symbol);
+        if let Some(bits) = self.fixed_width.filter(|&bits| bits == seq.len()) {
+            return T::try_from(HuffmanCodeWord(seq.bits())).ok();
+        }
+        let len = seq.len() as usize;
+        if len == 0 || len >= self.counts.len() {
+            return None;
+        }
+        // Codes of each length follow the shorter ones, counting up.
+        let (mut first, mut index) = (0u32, 0usize);
+        for &count in &self.counts[1..len] {
+            index += count as usize;
+            first = (first + count as u32) << 1;
+        }
+        let offset = (seq.bits() as u32).checked_sub(first)?;
+        match offset < self.counts[len] as u32 {
+            true => Some(self.symbols[index + offset as usize]),
+            false => None,
+        }
+    }
+
+    /// The symbol of the code at the start of `peeked` and its length, if whole.
+    fn match_peeked(&self, peeked: BitSequence) -> Option<(T, u8)> {
+        let (index, len) = self.lookup[(peeked.bits() & ((1 << LOOKUP_BITS) - 1)) as usize];
+        if len > 0 {
+            return (len <= peeked.len()).then(|| (self.symbols[index as usize], len));
+        }
+        // A longer code: walk down the lengths, the bits coming first the most
+        // significant.
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0usize);
+        for len in 1..=peeked.len() {
+            code |= (peeked.bits() >> (len - 1) & 1) as u32;
+            let count = self.counts[len as usize] as u32;
+            if code < first + count {
+                return Some((self.symbols[index + (code - first) as usize], len));
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
         }
         None
     }
+
     pub fn read_symbol<U: BufRead>(&self, bit_reader: &mut BitReader<U>) -> Result<T> {
+        if let Some(bits) = self.fixed_width {
+            // The bits come least significant first, the code the other way round.
+            let code = bit_reader.read_bits(bits)?.bits().reverse_bits() >> (16 - bits);
+            return T::try_from(HuffmanCodeWord(code));
+        }
+        // Match the code against peeked bits, reading it at once when found.
+        let peeked = bit_reader.peek_bits(MAX_BITS as u8)?;
+        if let Some((symbol, len)) = self.match_peeked(peeked) {
+            bit_reader.read_bits(len)?;
+            return Ok(symbol);
+        }
+        if peeked.len() == MAX_BITS as u8 {
+            bail!("invalid huffman code");
+        }
+
+        // Too few bits were buffered: go bit by bit, refilling as needed.
         let mut result_symbol = BitSequence::new(0, 0);
-        while let Ok(seq) = bit_reader.read_bits(1) {
-            result_symbol = seq.concat(result_symbol);
+        for _ in 0..MAX_BITS {
+            result_symbol = bit_reader.read_bits(1)?.concat(result_symbol);
             if let Some(val) = self.decode_symbol(result_symbol) {
                 return Ok(val);
             }
         }
-        bail!("couldn't read");
+        bail!("invalid huffman code");
     }
 
     pub fn from_lengths(code_lengths: &[u8]) -> Result<Self> {
-        let mut bl_count: HashMap<u8, u16> = HashMap::new();
-
+        let mut counts = vec![0u16; MAX_BITS + 1];
         for &length in code_lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Codes left of each length once the shorter ones are assigned, which only
+        // runs out if there are more codes than fit.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = 2 * left - count as i32;
+            ensure!(left >= 0, "over-subscribed huffman code");
+        }
+
+        // Canonical codes are assigned by length, then by symbol.
+        let mut symbols = Vec::new();
+        for (i, &length) in code_lengths.iter().enumerate() {
             if length > 0 {
-                let count = bl_count.entry(length).or_insert(0);
-                *count += 1;
+                symbols.push((length, i, T::try_from(HuffmanCodeWord(i as u16))?));
             }
         }
+        symbols.sort_by_key(|&(length, _, _)| length);
+
+        let codes = canonical_codes(code_lengths);
+        let mut lookup = vec![(0, 0); 1 << LOOKUP_BITS];
+        for (index, &(length, i, _)) in symbols.iter().enumerate() {
+            if length <= LOOKUP_BITS {
+                let first = BitSequence::new(codes[i], length).reverse().bits() as usize;
+                for entry in lookup[first..].iter_mut().step_by(1 << length) {
+                    *entry = (index as u16, length);
+                }
+            }
+        }
+
+        Ok(Self {
+            counts,
+            symbols: symbols.into_iter().map(|(_, _, symbol)| symbol).collect(),
+            lookup,
+            lengths: code_lengths.to_vec(),
+            fixed_width: None,
+        })
+    }
+}
 
-        let mut next_code = [0u16; MAX_BITS + 1];
-        for bits in 1..=MAX_BITS {
-            let count = bl_count.get(&(bits as u8 - 1)).unwrap_or(&0);
-            next_code[bits] = (next_code[bits - 1] + count) << 1;
+////////////////////////////////////////////////////////////////////////////////
+
+/// Code lengths of a Huffman code for `frequencies`, none longer than `max_len`.
+/// Symbols with a zero frequency get no code.
+///
+/// The lengths are found by package-merge, which makes the code optimal among
+/// those within the limit.
+pub fn lengths_from_frequencies(frequencies: &[u32], max_len: u8) -> Vec<u8> {
+    assert!(max_len as usize <= MAX_BITS, "max_len is bigger than 15");
+    let mut lengths = vec![0; frequencies.len()];
+    let mut leaves = (0..frequencies.len())
+        .filter(|&symbol| frequencies[symbol] > 0)
+        .map(|symbol| (frequencies[symbol] as u64, Some(symbol)))
+        .collect::<Vec<_>>();
+    assert!(leaves.len() <= 1 << max_len, "too many symbols for max_len");
+    if leaves.len() == 1 {
+        lengths[leaves[0].1.unwrap()] = 1;
+    }
+    if leaves.len() <= 1 {
+        return lengths;
+    }
+    leaves.sort();
+
+    // Items are weighted leaves, or packages of two items of the previous list.
+    // The list of each level merges the leaves with the pairs of the deeper one.
+    let mut lists = vec![leaves.clone()];
+    for _ in 1..max_len {
+        let deeper = lists.last().unwrap();
+        let mut packages = deeper
+            .chunks_exact(2)
+            .map(|pair| (pair[0].0 + pair[1].0, None))
+            .peekable();
+        let mut list = Vec::with_capacity(leaves.len() + packages.len());
+        for &leaf in &leaves {
+            while let Some(package) = packages.next_if(|package| package.0 < leaf.0) {
+                list.push(package);
+            }
+            list.push(leaf);
         }
+        list.extend(packages);
+        lists.push(list);
+    }
 
-        let mut result = HashMap::new();
-        for (i, &length) in code_lengths.iter().enumerate() {
-            let len = length as usize;
-            if len > 0 {
-                let seq = BitSequence::new(next_code[len], len as u8);
-                let elem = T::try_from(HuffmanCodeWord(i as u16))?;
-                result.insert(seq, elem);
-                next_code[len] += 1;
+    // The cheapest 2n - 2 items of the top list make the code. The packages among
+    // them are made of the cheapest items of the deeper list, and so on; a symbol
+    // is as long as the number of times its leaf is picked.
+    let mut picked = 2 * leaves.len() - 2;
+    for list in lists.iter().rev() {
+        let mut packages = 0;
+        for &(_, symbol) in &list[..picked] {
+            match symbol {
+                Some(symbol) => lengths[symbol] += 1,
+                None => packages += 1,
             }
         }
+        picked = 2 * packages;
+    }
+    lengths
+}
 
-        Ok(Self { map: result })
+/// Canonical codes for `lengths`, as in RFC 1951, most significant bit first.
+/// Symbols with a zero length get a zero code.
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut bl_count = [0u16; MAX_BITS + 1];
+    for &length in lengths {
+        bl_count[length as usize] += 1;
     }
+    bl_count[0] = 0;
+
+    let mut next_code = [0u16; MAX_BITS + 1];
+    for bits in 1..=MAX_BITS {
+        next_code[bits] = (next_code[bits - 1] + bl_count[bits - 1]) << 1;
+    }
+
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+/// Canonical codes for `lengths`, bit-reversed so that they can be written least
+/// significant bit first.
+#[cfg(feature = "gzip")]
+pub fn codes_from_lengths(lengths: &[u8]) -> Vec<u16> {
+    canonical_codes(lengths)
+        .into_iter()
+        .zip(lengths)
+        .map(|(code, &length)| match length {
+            0 => 0,
+            _ => BitSequence::new(code, length).reverse().bits(),
+        })
+        .collect()
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -419,4 +597,148 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn lengths_from_frequencies() -> Result<()> {
+        // Fibonacci frequencies make the unbounded code as deep as possible.
+        let mut frequencies = vec![1u32, 1];
+        while frequencies.len() < 30 {
+            let n = frequencies.len();
+            frequencies.push(frequencies[n - 1] + frequencies[n - 2]);
+        }
+        frequencies.push(0);
+
+        for max_len in [7, 15] {
+            let lengths = super::lengths_from_frequencies(&frequencies, max_len);
+            assert_eq!(lengths[30], 0);
+            assert!(lengths.iter().all(|&len| len <= max_len));
+            let kraft: f64 = lengths[..30]
+                .iter()
+                .map(|&len| 0.5f64.powi(len as i32))
+                .sum();
+            assert_eq!(kraft, 1.0);
+
+            let code = HuffmanCoding::<Value>::from_lengths(&lengths)?;
+            let codes = codes_from_lengths(&lengths);
+            let mut writer = crate::bit_writer::BitWriter::new();
+            for symbol in (0..30).rev() {
+                writer.write_bits(codes[symbol] as u32, lengths[symbol]);
+            }
+            writer.align_to_byte();
+            let data = writer.take_bytes();
+            let mut reader = BitReader::new(data.as_slice());
+            for symbol in (0..30).rev() {
+                assert_eq!(code.read_symbol(&mut reader)?, Value(symbol as u16));
+            }
+        }
+
+        assert_eq!(super::lengths_from_frequencies(&[0, 3, 0], 15), [0, 1, 0]);
+        assert_eq!(super::lengths_from_frequencies(&[0, 0], 15), [0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn lengths_are_optimal() {
+        let cost = |frequencies: &[u32], lengths: &[u8]| {
+            frequencies
+                .iter()
+                .zip(lengths)
+                .map(|(&freq, &len)| freq as u64 * len as u64)
+                .sum::<u64>()
+        };
+        for frequencies in [
+            [1u32, 1, 2, 3, 5, 8],
+            [10, 1, 1, 1, 1, 30],
+            [4, 4, 4, 4, 1, 9],
+        ] {
+            for max_len in [3u8, 4, 5] {
+                let lengths = super::lengths_from_frequencies(&frequencies, max_len);
+                assert!(lengths.iter().all(|&len| (1..=max_len).contains(&len)));
+
+                // Every assignment of lengths within the limit, complete or not.
+                let mut best = u64::MAX;
+                for n in 0..(max_len as u32).pow(6) {
+                    let candidate = (0..6)
+                        .map(|i| (n / (max_len as u32).pow(i) % max_len as u32) as u8 + 1)
+                        .collect::<Vec<_>>();
+                    let kraft = candidate.iter().map(|&len| 1u32 << (8 - len)).sum::<u32>();
+                    if kraft <= 1 << 8 {
+                        best = best.min(cost(&frequencies, &candidate));
+                    }
+                }
+                assert_eq!(cost(&frequencies, &lengths), best);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_codes() {
+        // The table of RFC 1951 3.2.6: first and last code of each range.
+        let codes = canonical_codes(&FIXED_LIT_LEN_LENGTHS);
+        for (symbol, code) in [
+            (0, 0b00110000),
+            (143, 0b10111111),
+            (144, 0b110010000),
+            (255, 0b111111111),
+            (256, 0b0000000),
+            (279, 0b0010111),
+            (280, 0b11000000),
+            (287, 0b11000111),
+        ] {
+            assert_eq!(codes[symbol], code, "symbol {}", symbol);
+        }
+    }
+
+    #[test]
+    fn invalid_length_codes() {
+        let token = LitLenToken::try_from(HuffmanCodeWord(285)).unwrap();
+        assert!(matches!(
+            token,
+            Length {
+                base: 258,
+                extra_bits: 0
+            }
+        ));
+        for symbol in [286, 287] {
+            let err = LitLenToken::try_from(HuffmanCodeWord(symbol)).unwrap_err();
+            assert_eq!(err.to_string(), format!("invalid length code {}", symbol));
+        }
+    }
+
+    #[test]
+    fn fixed_distances() -> Result<()> {
+        // Symbols 0 to 31, each written most significant bit first.
+        let mut data = Vec::new();
+        let mut bits = 0u64;
+        let mut len = 0;
+        for symbol in 0..32u64 {
+            bits |= (symbol.reverse_bits() >> (64 - FIXED_DISTANCE_BITS)) << len;
+            len += FIXED_DISTANCE_BITS;
+            while len >= 8 {
+                data.push(bits as u8);
+                bits >>= 8;
+                len -= 8;
+            }
+        }
+        let mut data = data.as_slice();
+        let mut reader = BitReader::new(&mut data);
+
+        let (_, dist) = fixed_litlen_distance_trees()?;
+        for symbol in 0..30 {
+            let token = dist.read_symbol(&mut reader)?;
+            assert_eq!(token.base, DIST_BASE[symbol]);
+            assert_eq!(token.extra_bits, DIST_EXTRA[symbol]);
+        }
+        for symbol in [30, 31] {
+            let err = dist.read_symbol(&mut reader).unwrap_err();
+            assert_eq!(err.to_string(), format!("invalid distance code {}", symbol));
+        }
+        assert_eq!(
+            dist.decode_symbol(BitSequence::new(0b11101, 5))
+                .map(|token| token.base),
+            Some(24577)
+        );
+        Ok(())
+    }
 }
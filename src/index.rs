@@ -0,0 +1,332 @@
+#![forbid(unsafe_code)]
+
+use anyhow::{bail, ensure, Result};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+use crate::checkpoint::{BlockCheckpoint, Framing};
+use crate::encoder::compress_zlib;
+use crate::gzip::GzipDecoder;
+use crate::zlib::ZlibDecoder;
+use crate::WINDOW_SIZE;
+
+////////////////////////////////////////////////////////////////////////////////
+
+const INDEXED_GZIP_MAGIC: &[u8; 5] = b"GZIDX";
+const INDEXED_GZIP_VERSION: u8 = 1;
+
+const GZTOOL_MAGIC: &[u8; 8] = b"gzipindx";
+
+const WINDOW_STORE_MAGIC: &[u8; 4] = b"RGZW";
+
+/// Level the windows of gztool indexes and window stores are compressed with.
+const WINDOW_LEVEL: u32 = 6;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Place in a gzip file where decoding can start: a deflate block boundary inside
+/// a member, with the window needed to decode what follows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexPoint {
+    /// Offset of the first compressed byte entirely after the point.
+    pub compressed_offset: u64,
+    /// Number of high bits of the byte before `compressed_offset` which belong to
+    /// the data after the point, from 0 to 7.
+    pub bits: u8,
+    pub uncompressed_offset: u64,
+    /// Decompressed data preceding the point in its member, oldest byte first, up
+    /// to the window size. Empty at the start of a member.
+    pub window: Vec<u8>,
+}
+
+/// Access points into a gzip file, compatible with the index files of gztool and
+/// of Python's indexed_gzip.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GzipIndex {
+    pub points: Vec<IndexPoint>,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    /// Minimum number of uncompressed bytes between two points.
+    pub spacing: u64,
+}
+
+impl GzipIndex {
+    /// Decompress `reader` and record a point at the first block boundary after
+    /// every `spacing` bytes of output.
+    pub fn build<R: BufRead>(reader: R, spacing: u64) -> Result<Self> {
+        Self::build_with(reader, spacing, Ok)
+    }
+
+    /// Build an index, passing the window of each point to `keep_window`, which
+    /// returns what to keep in the point.
+    fn build_with<R: BufRead, F: FnMut(Vec<u8>) -> Result<Vec<u8>>>(
+        reader: R,
+        spacing: u64,
+        mut keep_window: F,
+    ) -> Result<Self> {
+        let mut decoder = GzipDecoder::new(reader);
+        let mut points = Vec::<IndexPoint>::new();
+        loop {
+            let due = points
+                .last()
+                .is_none_or(|last| decoder.total_out() - last.uncompressed_offset >= spacing);
+            if due && decoder.is_at_block_boundary() {
+                let checkpoint = decoder.checkpoint()?;
+                if checkpoint.framing == Framing::GzipBody
+                    && checkpoint.block == BlockCheckpoint::Header
+                {
+                    let bit_position =
+                        checkpoint.input_offset * 8 - checkpoint.pending_bits.len() as u64;
+                    points.push(IndexPoint {
+                        compressed_offset: bit_position.div_ceil(8),
+                        bits: ((8 - bit_position % 8) % 8) as u8,
+                        uncompressed_offset: decoder.total_out(),
+                        window: keep_window(checkpoint.window)?,
+                    });
+                }
+            }
+            let size = decoder.fill_buffer()?.len();
+            if size == 0 {
+                break;
+            }
+            decoder.consume_buffer(size);
+        }
+        Ok(Self {
+            points,
+            compressed_size: decoder.total_in(),
+            uncompressed_size: decoder.total_out(),
+            spacing,
+        })
+    }
+
+    /// Read an index exported by indexed_gzip (`IndexedGzipFile.export_index`).
+    pub fn read_indexed_gzip<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 5];
+        reader.read_exact(&mut magic)?;
+        ensure!(&magic == INDEXED_GZIP_MAGIC, "not an indexed_gzip index");
+        let version = reader.read_u8()?;
+        ensure!(version <= INDEXED_GZIP_VERSION, "unsupported index version");
+        let _flags = reader.read_u8()?;
+        let compressed_size = reader.read_u64::<LittleEndian>()?;
+        let uncompressed_size = reader.read_u64::<LittleEndian>()?;
+        let spacing = reader.read_u32::<LittleEndian>()? as u64;
+        let window_size = reader.read_u32::<LittleEndian>()? as usize;
+        ensure!(window_size <= WINDOW_SIZE, "invalid index window size");
+        let count = reader.read_u32::<LittleEndian>()?;
+
+        let mut points = Vec::new();
+        let mut has_window = Vec::new();
+        for i in 0..count {
+            points.push(IndexPoint {
+                compressed_offset: reader.read_u64::<LittleEndian>()?,
+                uncompressed_offset: reader.read_u64::<LittleEndian>()?,
+                bits: reader.read_u8()?,
+                window: Vec::new(),
+            });
+            // Version 0 has a window for every point but the first.
+            has_window.push(match version {
+                0 => i > 0,
+                _ => reader.read_u8()? != 0,
+            });
+        }
+        for (point, has_window) in points.iter_mut().zip(has_window) {
+            if has_window {
+                point.window = vec![0; window_size];
+                reader.read_exact(&mut point.window)?;
+            }
+        }
+        Ok(Self {
+            points,
+            compressed_size,
+            uncompressed_size,
+            spacing,
+        })
+    }
+
+    /// Write the index in the format indexed_gzip imports. Windows are padded with
+    /// zeros in front to the full window size.
+    pub fn write_indexed_gzip<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(INDEXED_GZIP_MAGIC)?;
+        writer.write_u8(INDEXED_GZIP_VERSION)?;
+        writer.write_u8(0)?;
+        writer.write_u64::<LittleEndian>(self.compressed_size)?;
+        writer.write_u64::<LittleEndian>(self.uncompressed_size)?;
+        writer.write_u32::<LittleEndian>(u32::try_from(self.spacing)?)?;
+        writer.write_u32::<LittleEndian>(WINDOW_SIZE as u32)?;
+        writer.write_u32::<LittleEndian>(u32::try_from(self.points.len())?)?;
+        for point in &self.points {
+            writer.write_u64::<LittleEndian>(point.compressed_offset)?;
+            writer.write_u64::<LittleEndian>(point.uncompressed_offset)?;
+            writer.write_u8(point.bits)?;
+            writer.write_u8(!point.window.is_empty() as u8)?;
+        }
+        for point in self.points.iter().filter(|point| !point.window.is_empty()) {
+            ensure!(
+                point.window.len() <= WINDOW_SIZE,
+                "index window is too long"
+            );
+            writer.write_all(&[0; WINDOW_SIZE][point.window.len()..])?;
+            writer.write_all(&point.window)?;
+        }
+        Ok(())
+    }
+
+    /// Read an index created by gztool. Indexes with line numbers ("gzipindX") are
+    /// not supported.
+    pub fn read_gztool<R: Read>(mut reader: R) -> Result<Self> {
+        let mut header = [0; 16];
+        reader.read_exact(&mut header)?;
+        ensure!(
+            header[..8] == [0; 8] && &header[8..] == GZTOOL_MAGIC,
+            "not a gztool index"
+        );
+        let count = reader.read_u64::<BigEndian>()?;
+        let _size = reader.read_u64::<BigEndian>()?;
+
+        let mut points = Vec::new();
+        for _ in 0..count {
+            let uncompressed_offset = reader.read_u64::<BigEndian>()?;
+            let compressed_offset = reader.read_u64::<BigEndian>()?;
+            let bits = reader.read_u32::<BigEndian>()?;
+            ensure!(bits < 8, "invalid index point bits");
+            let compressed_len = reader.read_u32::<BigEndian>()? as u64;
+            let mut window = Vec::new();
+            if compressed_len > 0 {
+                ZlibDecoder::new(io::BufReader::new((&mut reader).take(compressed_len)))
+                    .read_to_end(&mut window)?;
+                ensure!(window.len() <= WINDOW_SIZE, "index window is too long");
+            }
+            points.push(IndexPoint {
+                compressed_offset,
+                bits: bits as u8,
+                uncompressed_offset,
+                window,
+            });
+        }
+        // An index still being built has no trailer yet.
+        let uncompressed_size = match reader.read_u64::<BigEndian>() {
+            Ok(size) => size,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => 0,
+            Err(err) => bail!(err),
+        };
+        Ok(Self {
+            points,
+            compressed_size: 0,
+            uncompressed_size,
+            spacing: 0,
+        })
+    }
+
+    /// Write the index in the format of gztool.
+    pub fn write_gztool<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&[0; 8])?;
+        writer.write_all(GZTOOL_MAGIC)?;
+        writer.write_u64::<BigEndian>(self.points.len() as u64)?;
+        writer.write_u64::<BigEndian>(self.points.len() as u64)?;
+        for point in &self.points {
+            writer.write_u64::<BigEndian>(point.uncompressed_offset)?;
+            writer.write_u64::<BigEndian>(point.compressed_offset)?;
+            writer.write_u32::<BigEndian>(point.bits as u32)?;
+            if point.window.is_empty() {
+                writer.write_u32::<BigEndian>(0)?;
+            } else {
+                let window = compress_zlib(&point.window, WINDOW_LEVEL);
+                writer.write_u32::<BigEndian>(window.len() as u32)?;
+                writer.write_all(&window)?;
+            }
+        }
+        writer.write_u64::<BigEndian>(self.uncompressed_size)?;
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Windows of the points of an index kept compressed in a file and read on
+/// demand, so that memory use does not grow with the number of points.
+///
+/// The file holds the windows, then a table of their offsets and lengths, then
+/// the number of windows and a magic number.
+pub struct WindowStore {
+    file: Box<dyn ReadSeek>,
+    /// Offset and length of the window of each point.
+    entries: Vec<(u64, u32)>,
+}
+
+impl WindowStore {
+    /// Build an index of `reader` like `GzipIndex::build`, writing the windows of
+    /// the points to `file` instead of keeping them in the index.
+    pub fn build<R: BufRead, F: Read + Write + Seek + 'static>(
+        reader: R,
+        spacing: u64,
+        mut file: F,
+    ) -> Result<(GzipIndex, Self)> {
+        let mut entries = Vec::new();
+        let mut offset = file.stream_position()?;
+        let index = GzipIndex::build_with(reader, spacing, |window| {
+            let window = compress_zlib(&window, WINDOW_LEVEL);
+            file.write_all(&window)?;
+            entries.push((offset, window.len() as u32));
+            offset += window.len() as u64;
+            Ok(Vec::new())
+        })?;
+        for &(offset, len) in &entries {
+            file.write_u64::<LittleEndian>(offset)?;
+            file.write_u32::<LittleEndian>(len)?;
+        }
+        file.write_u32::<LittleEndian>(u32::try_from(entries.len())?)?;
+        file.write_all(WINDOW_STORE_MAGIC)?;
+        file.flush()?;
+        Ok((
+            index,
+            Self {
+                file: Box::new(file),
+                entries,
+            },
+        ))
+    }
+
+    /// Open a store written by `build`.
+    pub fn open<F: Read + Seek + 'static>(mut file: F) -> Result<Self> {
+        file.seek(SeekFrom::End(-8))?;
+        let count = file.read_u32::<LittleEndian>()? as i64;
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        ensure!(&magic == WINDOW_STORE_MAGIC, "not a window store");
+        file.seek(SeekFrom::End(-8 - 12 * count))?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let offset = file.read_u64::<LittleEndian>()?;
+            let len = file.read_u32::<LittleEndian>()?;
+            entries.push((offset, len));
+        }
+        Ok(Self {
+            file: Box::new(file),
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Read the window of the point at `index`.
+    pub fn load(&mut self, index: usize) -> Result<Vec<u8>> {
+        let Some(&(offset, len)) = self.entries.get(index) else {
+            bail!("no window for point {}", index);
+        };
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut window = Vec::new();
+        ZlibDecoder::new(io::BufReader::new((&mut self.file).take(len as u64)))
+            .read_to_end(&mut window)?;
+        Ok(window)
+    }
+}
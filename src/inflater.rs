@@ -0,0 +1,532 @@
+#![forbid(unsafe_code)]
+
+//! Push-style decoder following zlib's `inflateInit2`/`inflate`/`inflateEnd`, to
+//! ease porting C code: the caller hands in input and output buffers and is told
+//! how much of each was used, like `avail_in` and `avail_out`.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, ensure, Result};
+
+use crate::bit_reader::BitSequence;
+use crate::checksum::{Adler32, Checksum, Crc32};
+use crate::core::{InflateState, InflateStatus};
+use crate::gzip::{self, GzipReader, MemberFlags, MemberHeader, ID1, ID2};
+use crate::tracking_writer::TrackingWriter;
+use crate::zlib;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Input joined to the bytes the core left unconsumed, at most, so that they are
+/// not copied along with all of the input.
+const CARRY_CHUNK_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Wrapper {
+    Raw,
+    Zlib,
+    Gzip,
+    /// Gzip or zlib, told apart by the first byte.
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Header,
+    /// The zlib header asks for the dictionary with this Adler-32.
+    NeedDictionary(u32),
+    Body,
+    Trailer,
+    Done,
+}
+
+/// Checksum of the wrapper, computed by the window writer.
+#[derive(Clone)]
+enum WrapperChecksum {
+    None,
+    Crc32(Crc32),
+    Adler32(Adler32),
+}
+
+impl Checksum for WrapperChecksum {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::None => {}
+            Self::Crc32(crc32) => crc32.update(data),
+            Self::Adler32(adler32) => adler32.update(data),
+        }
+    }
+
+    fn value(&self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Crc32(crc32) => crc32.value(),
+            Self::Adler32(adler32) => adler32.value(),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::None => {}
+            Self::Crc32(crc32) => crc32.reset(),
+            Self::Adler32(adler32) => adler32.reset(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Outcome of `Inflater::inflate`, as zlib's return codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InflaterStatus {
+    /// Progress was made (Z_OK).
+    Ok,
+    /// The stream ended and its trailer checked out (Z_STREAM_END). The input
+    /// following it is left unconsumed.
+    StreamEnd,
+    /// The zlib header asks for a preset dictionary, to be provided with
+    /// `set_dictionary` before decoding goes on (Z_NEED_DICT).
+    NeedDictionary,
+    /// No progress was possible, for lack of input or of output space (Z_BUF_ERROR).
+    BufError,
+}
+
+/// Result of an `Inflater::inflate` call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InflateProgress {
+    /// Number of input bytes used, by which `avail_in` would go down.
+    pub consumed: usize,
+    /// Number of bytes written to the output, by which `avail_out` would go down.
+    pub produced: usize,
+    pub status: InflaterStatus,
+}
+
+/// Progress of the parsing of a gzip header, as the done flag of zlib's `gz_header`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeaderProgress {
+    #[default]
+    Pending,
+    /// The fixed fields are known, but not yet the extra field, name and comment.
+    Fixed,
+    Done,
+    /// The stream turned out to be a zlib one, in automatic mode.
+    NotGzip,
+}
+
+/// Gzip header filled in by an `Inflater` as it is parsed, see
+/// `Inflater::get_header`. Clones share the same header, so a clone can be handed
+/// to the inflater and the header read from the original.
+#[derive(Clone, Default)]
+pub struct GzipHeaderOut {
+    inner: Arc<Mutex<(HeaderProgress, Option<MemberHeader>)>>,
+}
+
+impl GzipHeaderOut {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn progress(&self) -> HeaderProgress {
+        self.inner.lock().unwrap().0
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress() == HeaderProgress::Done
+    }
+
+    /// The fields parsed so far.
+    pub fn header(&self) -> Option<MemberHeader> {
+        self.inner.lock().unwrap().1.clone()
+    }
+
+    fn set(&self, progress: HeaderProgress, header: Option<MemberHeader>) {
+        *self.inner.lock().unwrap() = (progress, header);
+    }
+}
+
+/// Writer keeping a window of `2^bits` bytes and computing the checksum of `wrapper`.
+fn window_writer(wrapper: Wrapper, bits: u8) -> TrackingWriter<Vec<u8>, WrapperChecksum> {
+    let checksum = match wrapper {
+        Wrapper::Raw | Wrapper::Auto => WrapperChecksum::None,
+        Wrapper::Zlib => WrapperChecksum::Adler32(Adler32::default()),
+        Wrapper::Gzip => WrapperChecksum::Crc32(Crc32::default()),
+    };
+    let mut writer = TrackingWriter::with_checksum(Vec::new(), checksum);
+    writer.set_window_size(1 << bits);
+    writer
+}
+
+/// The header fields preceding the optional ones.
+fn fixed_fields(header: &[u8]) -> MemberHeader {
+    let flags = MemberFlags(header[3]);
+    MemberHeader {
+        compression_method: header[2].into(),
+        modification_time: u32::from_le_bytes(header[4..8].try_into().unwrap()),
+        extra: None,
+        name: None,
+        comment: None,
+        extra_flags: header[8],
+        os: header[9],
+        has_crc: flags.has_crc(),
+        is_text: flags.is_text(),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Decoder of a raw deflate, zlib or gzip stream fed by the caller, as with zlib.
+///
+/// Input is consumed even when it can't be decoded yet, e.g. part of a header,
+/// and decoded data which doesn't fit in the output is kept for the next call.
+#[derive(Clone)]
+pub struct Inflater {
+    wrapper: Wrapper,
+    /// Whether the wrapper is detected anew for each stream.
+    auto: bool,
+    /// Largest window of the stream, in bits.
+    window_bits: u8,
+    state: State,
+    core: InflateState,
+    writer: TrackingWriter<Vec<u8>, WrapperChecksum>,
+    /// Offset of the decoded data not handed out yet in the writer.
+    out_pos: usize,
+    /// Consumed input not decoded yet: the start of a header, of a trailer or of
+    /// a symbol the core needs in one piece.
+    carry: Vec<u8>,
+    total_in: u64,
+    total_out: u64,
+    header_out: Option<GzipHeaderOut>,
+    dictionary_id: Option<u32>,
+}
+
+impl Inflater {
+    /// Create a decoder as `inflateInit2` does: `window_bits` is 8 to 15 for a zlib
+    /// stream with a window of up to `2^window_bits` bytes, the same plus 16 for a
+    /// gzip stream, the same plus 32 for either of them, and -9 to -15 for a raw
+    /// deflate stream. Matches reaching further back than the window are rejected.
+    pub fn new(window_bits: i32) -> Result<Self> {
+        let (wrapper, bits) = match window_bits {
+            -15..=-9 => (Wrapper::Raw, -window_bits),
+            8..=15 => (Wrapper::Zlib, window_bits),
+            24..=31 => (Wrapper::Gzip, window_bits - 16),
+            // As with zlib, 32 alone stands for the largest window.
+            32 => (Wrapper::Auto, 15),
+            40..=47 => (Wrapper::Auto, window_bits - 32),
+            _ => bail!("invalid window bits"),
+        };
+        Ok(Self {
+            wrapper,
+            auto: wrapper == Wrapper::Auto,
+            window_bits: bits as u8,
+            state: match wrapper {
+                Wrapper::Raw => State::Body,
+                _ => State::Header,
+            },
+            core: InflateState::new(),
+            writer: window_writer(wrapper, bits as u8),
+            out_pos: 0,
+            carry: Vec::new(),
+            total_in: 0,
+            total_out: 0,
+            header_out: None,
+            dictionary_id: None,
+        })
+    }
+
+    /// Fill `header` in as the gzip header is parsed, as `inflateGetHeader` does.
+    /// Only valid for gzip streams, and until the next reset. In automatic mode, a
+    /// zlib stream is reported as `HeaderProgress::NotGzip`.
+    pub fn get_header(&mut self, header: GzipHeaderOut) -> Result<()> {
+        ensure!(
+            self.wrapper == Wrapper::Gzip
+                || self.wrapper == Wrapper::Auto && self.state == State::Header,
+            "not a gzip decoder"
+        );
+        header.set(HeaderProgress::Pending, None);
+        self.header_out = Some(header);
+        Ok(())
+    }
+
+    /// Start over with a new stream of the same format, as `inflateReset` does.
+    /// The header registered with `get_header` is no longer filled in.
+    pub fn reset(&mut self) {
+        if self.auto {
+            self.wrapper = Wrapper::Auto;
+        }
+        self.state = match self.wrapper {
+            Wrapper::Raw => State::Body,
+            _ => State::Header,
+        };
+        self.core = InflateState::new();
+        self.writer.reset();
+        self.writer.get_mut().clear();
+        self.out_pos = 0;
+        self.carry.clear();
+        self.total_in = 0;
+        self.total_out = 0;
+        self.header_out = None;
+        self.dictionary_id = None;
+    }
+
+    /// Adler-32 of the preset dictionary the zlib stream was compressed with
+    /// (DICTID), if any. Known once the header is read.
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary_id
+    }
+
+    /// Provide the dictionary, as `inflateSetDictionary` does: for a zlib stream,
+    /// once `inflate` asked for it, and for a raw deflate stream, before decoding
+    /// data compressed with it.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<()> {
+        match (self.wrapper, self.state) {
+            (Wrapper::Raw, _) => {}
+            (Wrapper::Zlib, State::NeedDictionary(id)) => {
+                let mut checksum = Adler32::default();
+                checksum.update(dictionary);
+                ensure!(checksum.value() == id, "incorrect dictionary");
+                self.state = State::Body;
+            }
+            _ => bail!("no dictionary is required"),
+        }
+        self.writer.set_dictionary(dictionary);
+        Ok(())
+    }
+
+    /// The current window, as `inflateGetDictionary` returns it: the last bytes
+    /// decoded, up to the window size, usable as the dictionary of a next stream.
+    pub fn get_dictionary(&self) -> Vec<u8> {
+        let (history, len) = self.writer.history_snapshot();
+        history[..len].to_vec()
+    }
+
+    /// Decode the `len` low bits of `value` before the next input, as `inflatePrime`
+    /// does, e.g. to resume a raw deflate stream at a bit offset recorded in an
+    /// index: the bits of its byte are primed and the input starts at the next one.
+    pub fn prime(&mut self, len: u8, value: u16) -> Result<()> {
+        ensure!(
+            self.state == State::Body && self.carry.is_empty(),
+            "bits can only be primed between symbols of the deflate stream"
+        );
+        ensure!(len <= 16, "too many bits to prime");
+        self.core.prime(BitSequence::new(value, len))
+    }
+
+    /// Independent copy of the decoder in its current state, as `inflateCopy`
+    /// makes, e.g. to try decoding ahead and go back. The copy fills in the same
+    /// header registered with `get_header`, if any.
+    pub fn duplicate(&self) -> Self {
+        self.clone()
+    }
+
+    /// Number of input bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Number of decompressed bytes written out so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Done && self.out_pos == self.writer.get_ref().len()
+    }
+
+    /// Decode as much of `input` into `output` as possible, as `inflate` does. An
+    /// error means the stream is corrupted, and the decoder can only be reset.
+    pub fn inflate(&mut self, input: &[u8], output: &mut [u8]) -> Result<InflateProgress> {
+        let mut consumed = 0;
+        let mut produced = 0;
+        loop {
+            produced += self.write_out(&mut output[produced..]);
+            if self.out_pos < self.writer.get_ref().len() {
+                break;
+            }
+            let input = &input[consumed..];
+            let (used, stuck) = match self.state {
+                State::Header => self.read_header(input)?,
+                State::NeedDictionary(_) => break,
+                State::Body if produced == output.len() => break,
+                State::Body => self.decode(input, output.len() - produced)?,
+                State::Trailer => self.read_trailer(input)?,
+                State::Done => break,
+            };
+            consumed += used;
+            if stuck {
+                break;
+            }
+        }
+        self.total_in += consumed as u64;
+        self.total_out += produced as u64;
+        let status = if self.is_finished() {
+            InflaterStatus::StreamEnd
+        } else if matches!(self.state, State::NeedDictionary(_)) {
+            InflaterStatus::NeedDictionary
+        } else if consumed == 0 && produced == 0 {
+            InflaterStatus::BufError
+        } else {
+            InflaterStatus::Ok
+        };
+        Ok(InflateProgress {
+            consumed,
+            produced,
+            status,
+        })
+    }
+
+    /// Copy the decoded data not handed out yet to `output`.
+    fn write_out(&mut self, output: &mut [u8]) -> usize {
+        let data = &self.writer.get_ref()[self.out_pos..];
+        let size = std::cmp::min(data.len(), output.len());
+        output[..size].copy_from_slice(&data[..size]);
+        self.out_pos += size;
+        if self.out_pos == self.writer.get_ref().len() {
+            self.writer.get_mut().clear();
+            self.out_pos = 0;
+        }
+        size
+    }
+
+    /// The carried input followed by `input`, joined only if need be.
+    fn joined<'a>(&self, input: &'a [u8], buf: &'a mut Vec<u8>) -> &'a [u8] {
+        if self.carry.is_empty() {
+            return input;
+        }
+        buf.extend_from_slice(&self.carry);
+        buf.extend_from_slice(input);
+        buf
+    }
+
+    /// Read the header, or carry `input` over if it ends before the header does.
+    /// Returns the number of bytes of `input` used and whether more are needed.
+    fn read_header(&mut self, input: &[u8]) -> Result<(usize, bool)> {
+        let mut buf = Vec::new();
+        let data = self.joined(input, &mut buf);
+        if self.wrapper == Wrapper::Auto && !data.is_empty() {
+            // The first byte of a zlib header can't be that of a gzip one, its
+            // compression method being invalid.
+            self.wrapper = match data[0] {
+                ID1 => Wrapper::Gzip,
+                _ => Wrapper::Zlib,
+            };
+            self.writer = window_writer(self.wrapper, self.window_bits);
+            if let Some(out) = self
+                .header_out
+                .as_ref()
+                .filter(|_| self.wrapper == Wrapper::Zlib)
+            {
+                out.set(HeaderProgress::NotGzip, None);
+            }
+        }
+        let len = match self.wrapper {
+            Wrapper::Zlib if data.len() >= 2 => {
+                let (window_size, has_dictionary) = zlib::parse_header([data[0], data[1]])?;
+                ensure!(window_size <= 1 << self.window_bits, "invalid window size");
+                match has_dictionary {
+                    false => Some(2),
+                    true if data.len() >= 6 => {
+                        self.dictionary_id =
+                            Some(u32::from_be_bytes(data[2..6].try_into().unwrap()));
+                        Some(6)
+                    }
+                    true => None,
+                }
+            }
+            Wrapper::Gzip => {
+                let magic = &data[..std::cmp::min(data.len(), 2)];
+                ensure!(*magic == [ID1, ID2][..magic.len()], "wrong id values");
+                if let Some(out) = self.header_out.as_ref().filter(|_| data.len() >= 10) {
+                    out.set(HeaderProgress::Fixed, Some(fixed_fields(data)));
+                }
+                gzip::header_len(data)
+            }
+            _ => None,
+        };
+        let Some(len) = len else {
+            self.carry.extend_from_slice(input);
+            return Ok((input.len(), true));
+        };
+        if self.wrapper == Wrapper::Gzip {
+            let (header, rest) = data[..len].split_at(10);
+            let (header, _) = GzipReader::new(rest).parse_header(header)?;
+            if let Some(out) = &self.header_out {
+                out.set(HeaderProgress::Done, Some(header));
+            }
+        }
+        let used = len - self.carry.len();
+        self.carry.clear();
+        self.state = match self.dictionary_id {
+            Some(id) => State::NeedDictionary(id),
+            None => State::Body,
+        };
+        Ok((used, false))
+    }
+
+    /// Decode `input` until about `limit` bytes are written or a block ends.
+    fn decode(&mut self, input: &[u8], limit: usize) -> Result<(usize, bool)> {
+        let carried = self.carry.len();
+        let available = input.len();
+        let input = match carried {
+            0 => input,
+            _ => &input[..std::cmp::min(available, CARRY_CHUNK_SIZE)],
+        };
+        let mut buf = Vec::new();
+        let data = self.joined(input, &mut buf);
+        let (size, status) = self.core.inflate(data, &mut self.writer, limit)?;
+        let used = if status == InflateStatus::NeedInput {
+            self.carry = data[size..].to_vec();
+            input.len()
+        } else if size >= carried {
+            self.carry.clear();
+            size - carried
+        } else {
+            self.carry.drain(..size);
+            0
+        };
+        if status == InflateStatus::StreamEnd {
+            self.core.align_to_byte();
+            self.state = match self.wrapper {
+                Wrapper::Raw => State::Done,
+                _ => State::Trailer,
+            };
+        }
+        Ok((
+            used,
+            status == InflateStatus::NeedInput && used == available,
+        ))
+    }
+
+    /// Read and check the trailer, carrying `input` over if it ends before it.
+    fn read_trailer(&mut self, input: &[u8]) -> Result<(usize, bool)> {
+        let len = match self.wrapper {
+            Wrapper::Zlib => 4,
+            _ => 8,
+        };
+        let used = std::cmp::min(len - self.carry.len(), input.len());
+        self.carry.extend_from_slice(&input[..used]);
+        if self.carry.len() < len {
+            return Ok((used, true));
+        }
+        let trailer = std::mem::take(&mut self.carry);
+        let checksum = self.writer.checksum();
+        match self.wrapper {
+            Wrapper::Zlib => ensure!(
+                u32::from_be_bytes(trailer[..4].try_into().unwrap()) == checksum,
+                "adler32 check failed"
+            ),
+            _ => {
+                ensure!(
+                    u32::from_le_bytes(trailer[..4].try_into().unwrap()) == checksum,
+                    "crc32 check failed"
+                );
+                ensure!(
+                    u32::from_le_bytes(trailer[4..].try_into().unwrap())
+                        == self.writer.byte_count() as u32,
+                    "length check failed"
+                );
+            }
+        }
+        self.state = State::Done;
+        Ok((used, false))
+    }
+}
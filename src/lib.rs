@@ -1,133 +1,280 @@
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "simd"), forbid(unsafe_code))]
+#![cfg_attr(feature = "simd", deny(unsafe_code))]
 
-use crate::bit_reader::BitReader;
-use crate::deflate::DeflateReader;
-use crate::gzip::GzipReader;
-use crate::huffman_coding::decode_litlen_distance_trees;
-use crate::tracking_writer::TrackingWriter;
-use anyhow::{bail, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
+#[cfg(any(feature = "gzip", feature = "raw"))]
+use anyhow::Result;
+#[cfg(any(feature = "gzip", feature = "raw"))]
 use std::io::{BufRead, Write};
 
-mod bit_reader;
-mod deflate;
+#[cfg(feature = "async")]
+pub use crate::async_decoder::{copy_decode_async, AsyncGzipDecoder};
+#[cfg(feature = "gzip")]
+pub use crate::batch::{decompress_many, BatchOptions, FileResult, FileStats};
+#[cfg(feature = "gzip")]
+pub use crate::bgzf::{BgzfEncoder, BGZF_EOF};
+#[cfg(feature = "stream")]
+pub use crate::body::GzipBodyDecoder;
+#[cfg(feature = "gzip")]
+pub use crate::bounded::{decompress_bounded, Bounded, DecompressStats};
+#[cfg(all(feature = "gzip", feature = "bytes"))]
+pub use crate::buf::{decompress_bytes, decompress_chunks};
+pub use crate::checkpoint::{Checkpoint, CheckpointInfo};
+#[cfg(feature = "raw")]
+pub use crate::decoder::DeflateDecoder;
+pub use crate::decoder::{PartialOutput, WINDOW_SIZE};
+#[cfg(feature = "gzip")]
+pub use crate::dump::hex_dump;
+#[cfg(feature = "gzip")]
+pub use crate::encoder::{compress, CompressOptions, GzipEncoder, Strategy};
+#[cfg(feature = "gzip")]
+pub use crate::estimate::{estimate_decompressed_size, SizeEstimate};
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+pub use crate::format::{decompress_nested, sniff_format, AnyDecoder, Format};
+#[cfg(feature = "gzip")]
+pub use crate::gzip::{
+    decode_latin1, encode_latin1, validate_header, CompressionHint, CompressionMethod,
+    DecompressOptions, ExtraField, GzipDecoder, MemberFlags, MemberFooter, MemberHeader,
+    MemberInfo, Subfield,
+};
+#[cfg(feature = "heapless")]
+pub use crate::heapless::{HeaplessError, HeaplessInflater, HuffmanTables};
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+pub use crate::index::{GzipIndex, IndexPoint, WindowStore};
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+pub use crate::inflater::{
+    GzipHeaderOut, HeaderProgress, InflateProgress, Inflater, InflaterStatus,
+};
+#[cfg(feature = "gzip")]
+pub use crate::parts::{decompress_parts, PartError, PartsReader};
+#[cfg(feature = "gzip")]
+pub use crate::pipeline::decompress_pipelined;
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+pub use crate::seekable::SeekableGzipReader;
+pub use crate::sink::{Sink, WriteSink};
+pub use crate::timeout::{ReadTimeout, SetReadTimeout, TimeoutReader};
+#[cfg(feature = "trace-defmt")]
+pub use crate::trace::DefmtSink;
+#[cfg(feature = "trace-log")]
+pub use crate::trace::LogSink;
+#[cfg(feature = "trace-decode")]
+pub use crate::trace::{TraceEvent, TraceLog, TraceSink};
+#[cfg(feature = "zlib")]
+pub use crate::zlib::ZlibDecoder;
+
+#[cfg(feature = "async")]
+mod async_decoder;
+#[cfg(feature = "gzip")]
+mod batch;
+#[cfg(feature = "gzip")]
+mod bgzf;
+pub mod bit_reader;
+#[cfg(feature = "gzip")]
+mod bit_writer;
+#[cfg(feature = "stream")]
+mod body;
+#[cfg(feature = "gzip")]
+mod bounded;
+#[cfg(all(feature = "gzip", feature = "bytes"))]
+mod buf;
+mod checkpoint;
+pub mod checksum;
+pub mod core;
+mod counting_reader;
+#[cfg_attr(not(feature = "raw"), allow(dead_code))]
+mod decoder;
+pub mod deflate;
+#[cfg(feature = "gzip")]
+mod dump;
+#[cfg(feature = "gzip")]
+mod encoder;
+#[cfg(feature = "gzip")]
+mod estimate;
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+mod format;
+#[cfg(feature = "gzip")]
 mod gzip;
+#[cfg(feature = "heapless")]
+mod heapless;
 mod huffman_coding;
-mod tracking_writer;
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+mod index;
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+mod inflater;
+#[cfg(feature = "gzip")]
+mod lz77;
+#[cfg(feature = "gzip")]
+mod parts;
+#[cfg(feature = "gzip")]
+mod pipeline;
+pub mod prelude;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "gzip")]
+pub mod read;
+#[cfg(all(feature = "gzip", feature = "zlib"))]
+mod seekable;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+mod sink;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timeout;
+#[cfg(feature = "trace-decode")]
+mod trace;
+pub mod tracking_writer;
+#[cfg(feature = "gzip")]
+pub mod write;
+#[cfg(feature = "zlib")]
+mod zlib;
 
+/// Decompress `input` to `output`.
+///
+/// If the input turns out to be broken, the data decoded before the error is
+/// written and flushed first, and the error has a `PartialOutput` context with
+/// its size.
+#[cfg(feature = "gzip")]
 pub fn decompress<R: BufRead, W: Write>(input: R, mut output: W) -> Result<()> {
-    let mut gzip_reader = GzipReader::new(input);
-    let mut track_writer = TrackingWriter::new(&mut output);
-
-    while let Some(header) = gzip_reader.read_header() {
-        let header = header?;
-        match gzip_reader.parse_header(&header) {
-            Ok(mut parsed) => {
-                track_writer.flush()?;
-                let initial_len = track_writer.byte_count();
-                let mut defl_reader = DeflateReader::new(BitReader::new(parsed.1.inner_mut()));
-                process_blocks(&mut defl_reader, &mut track_writer)?;
-                let footer = parsed.1.read_footer()?;
-                validate_footer_data(&mut track_writer, initial_len, footer.0)?;
-                gzip_reader = footer.1;
-            }
-            Err(error) => bail!(error),
+    let result = decompress_with(input, |data| Ok(output.write_all(data)?));
+    if let Err(err) = &result {
+        if err.is::<PartialOutput>() {
+            output.flush()?;
         }
     }
-
-    Ok(())
+    result
 }
 
-fn process_blocks<R: BufRead, W: Write>(
-    defl_reader: &mut DeflateReader<R>,
-    track_writer: &mut TrackingWriter<W>,
+/// Decompress `input`, handing the decompressed data to `output` in chunks of up to
+/// 64K. An error returned by `output` stops decompression and is returned as is,
+/// a decoding error gets a `PartialOutput` context as with `decompress`.
+#[cfg(feature = "gzip")]
+pub fn decompress_with<R: BufRead, F: FnMut(&[u8]) -> Result<()>>(
+    input: R,
+    mut output: F,
 ) -> Result<()> {
+    let mut decoder = GzipDecoder::new(input);
+    let mut bytes_written = 0;
     loop {
-        let block_res = match defl_reader.next_block() {
-            Some(res) => res,
-            None => break,
-        };
-        let (block_hdr, rdr) = match block_res {
-            Ok(res) => res,
-            Err(e) => return Err(e),
-        };
-        match block_hdr.compression_type {
-            deflate::CompressionType::Uncompressed => {
-                process_uncompressed_block(rdr, track_writer)?;
-            }
-            deflate::CompressionType::DynamicTree => {
-                process_dynamic_tree_block(rdr, track_writer)?;
-            }
-            _ => {
-                bail!("unsupported block type");
-            }
-        }
-        if block_hdr.is_final {
+        let data = decoder
+            .fill_buffer()
+            .map_err(|err| err.context(PartialOutput { bytes_written }))?;
+        if data.is_empty() {
             break;
         }
+        output(data)?;
+        let size = data.len();
+        bytes_written += size as u64;
+        decoder.consume_buffer(size);
     }
     Ok(())
 }
 
-fn process_uncompressed_block<R: BufRead, W: Write>(
-    rdr: &mut BitReader<R>,
-    track_writer: &mut TrackingWriter<W>,
-) -> Result<()> {
-    let rdr = rdr.borrow_reader_from_boundary();
-    let length = rdr.read_u16::<LittleEndian>()?;
-
-    if length != !rdr.read_u16::<LittleEndian>()? {
-        bail!("nlen check failed");
-    }
+/// Decompress `input` into `sink`. If the decompressed data exceeds the capacity
+/// of the sink, the sink is filled up and decompression fails.
+#[cfg(feature = "gzip")]
+pub fn decompress_to<R: BufRead, S: Sink>(input: R, mut sink: S) -> Result<()> {
+    decompress_with(input, |data| match sink.capacity() {
+        Some(capacity) if capacity < data.len() => {
+            sink.push(&data[..capacity])?;
+            anyhow::bail!("sink is full")
+        }
+        _ => sink.push(data),
+    })
+}
 
-    let mut buffer = vec![0; length as usize];
-    rdr.read_exact(&mut buffer)?;
+/// Decompress `input`, discarding the output, and return a summary of its members.
+#[cfg(feature = "gzip")]
+pub fn members<R: BufRead>(input: R) -> Result<Vec<MemberInfo>> {
+    let mut decoder = GzipDecoder::new(input);
+    decoder.record_members();
+    std::io::copy(&mut decoder, &mut std::io::sink())?;
+    Ok(decoder.take_members())
+}
 
-    track_writer.write_all(&buffer)?;
+/// Check the integrity of `input`, as `gzip -t` does, without writing out the
+/// decompressed data: it only goes through the crc32 and the window.
+#[cfg(feature = "gzip")]
+pub fn verify<R: BufRead>(input: R) -> Result<()> {
+    let mut decoder = GzipDecoder::new(input);
+    decoder.discard_output();
+    while !decoder.fill_buffer()?.is_empty() {}
     Ok(())
 }
 
-fn process_dynamic_tree_block<R: BufRead, W: Write>(
-    rdr: &mut BitReader<R>,
-    track_writer: &mut TrackingWriter<W>,
-) -> Result<()> {
-    let (lit_length, dist) = decode_litlen_distance_trees(rdr)?;
+/// Write a gzip member with `header` around the raw deflate stream at the start of
+/// `deflate`, e.g. to turn a zlib stream into gzip or to fix a wrong footer without
+/// recompressing. The stream is decoded to compute the footer, which is returned.
+/// Data following the stream is left out.
+#[cfg(feature = "gzip")]
+pub fn reframe_member<W: Write>(
+    header: &MemberHeader,
+    deflate: &[u8],
+    mut output: W,
+) -> Result<MemberFooter> {
+    let mut decoder = decoder::DeflateDecoder::with_checksum(deflate, checksum::Crc32::default());
+    let size = std::io::copy(&mut decoder, &mut std::io::sink())?;
+    let footer = MemberFooter {
+        data_crc32: decoder.checksum(),
+        data_size: size as u32,
+    };
+    header.write_to(&mut output)?;
+    output.write_all(&deflate[..decoder.total_in() as usize])?;
+    footer.write_to(&mut output)?;
+    Ok(footer)
+}
 
-    while let Ok(token) = lit_length.read_symbol(rdr) {
-        match token {
-            huffman_coding::LitLenToken::Length { base, extra_bits } => {
-                let size = base + rdr.read_bits(extra_bits)?.bits();
-                let token = dist.read_symbol(rdr)?;
-                let distance = token.base + rdr.read_bits(token.extra_bits)?.bits();
-                track_writer.write_previous(distance as usize, size as usize)?;
-            }
-            huffman_coding::LitLenToken::Literal(value) => {
-                track_writer.write_all(&[value])?;
-            }
-            huffman_coding::LitLenToken::EndOfBlock => {
-                break;
+/// Decompress a raw deflate stream, returning the checksum of the decompressed data.
+///
+/// Meant for custom containers which frame deflate data with their own checksum.
+/// Errors are reported as by `decompress`.
+#[cfg(feature = "raw")]
+pub fn inflate<R: BufRead, W: Write, C: checksum::Checksum>(
+    input: R,
+    mut output: W,
+    checksum: C,
+) -> Result<u32> {
+    let mut decoder = DeflateDecoder::with_checksum(input, checksum);
+    let mut bytes_written = 0;
+    loop {
+        let data = match decoder.fill_buffer() {
+            Ok(data) => data,
+            Err(err) => {
+                output.flush()?;
+                return Err(err.context(PartialOutput { bytes_written }));
             }
+        };
+        if data.is_empty() {
+            break;
         }
+        output.write_all(data)?;
+        let size = data.len();
+        bytes_written += size as u64;
+        decoder.consume_buffer(size);
     }
-    Ok(())
+    Ok(decoder.checksum())
 }
 
-fn validate_footer_data<W: Write>(
-    track_writer: &mut TrackingWriter<W>,
-    initial_len: usize,
-    footer_data: gzip::MemberFooter,
-) -> Result<()> {
-    let byte_count = track_writer.byte_count();
-    let expected_len = initial_len + footer_data.data_size as usize;
-    let crc32 = track_writer.crc32();
-
-    if byte_count != expected_len {
-        bail!("length check failed");
-    }
-
-    if footer_data.data_crc32 != crc32 {
-        bail!("crc32 check failed");
-    }
+/// Decompress `input` as it is read, for consumers of readers such as
+/// `serde_json::from_reader(ripgzip::buf_reader(file))` or
+/// `csv::Reader::from_reader`. The input and the output are both buffered, so a
+/// `File` is best wrapped in a `BufReader` with the default capacity, and the
+/// result needs no further buffering.
+#[cfg(feature = "gzip")]
+pub fn buf_reader<R: BufRead>(input: R) -> impl BufRead {
+    GzipDecoder::new(input)
+}
 
-    Ok(())
+/// Decompress `input` lazily, line by line. Line endings are stripped as by
+/// `BufRead::lines`, and memory stays bounded by the longest line.
+///
+/// The iterator stops after the first error.
+#[cfg(feature = "gzip")]
+pub fn lines<R: BufRead>(input: R) -> impl Iterator<Item = Result<String>> {
+    let mut failed = false;
+    GzipDecoder::new(input).lines().map_while(move |line| {
+        if failed {
+            return None;
+        }
+        failed = line.is_err();
+        Some(line.map_err(Into::into))
+    })
 }
@@ -0,0 +1,338 @@
+#![forbid(unsafe_code)]
+
+use crate::tracking_writer::HISTORY_SIZE;
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const MIN_MATCH: usize = 3;
+pub const MAX_MATCH: usize = 258;
+pub const MAX_DISTANCE: usize = HISTORY_SIZE - 1;
+
+const HASH_BITS: u32 = 15;
+const NIL: usize = usize::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// How hard the matcher looks for matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchParams {
+    /// Longest hash chain walked for a position.
+    pub max_chain: usize,
+    /// Length of a match after which only a quarter of the chain is walked.
+    pub good_length: usize,
+    /// Length of a match under which a longer one is still looked for at the next
+    /// position, the match being deferred. Zero disables lazy matching.
+    pub max_lazy: usize,
+    /// Length of a match good enough to stop looking for a longer one.
+    pub nice_length: usize,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Matcher finding the matches of the data in the last 32K with hash chains over
+/// its first 3 bytes. Positions are counted from the start of the stream.
+pub struct Matcher {
+    /// The window followed by the data being tokenized.
+    buf: Vec<u8>,
+    /// Position of `buf[0]`.
+    base: usize,
+    /// Last position of each hash.
+    head: Vec<usize>,
+    /// Previous position with the same hash, indexed by position modulo the window size.
+    prev: Vec<usize>,
+    params: MatchParams,
+}
+
+impl Matcher {
+    pub fn new(params: MatchParams) -> Self {
+        Self {
+            buf: Vec::new(),
+            base: 0,
+            head: vec![NIL; 1 << HASH_BITS],
+            prev: vec![NIL; HISTORY_SIZE],
+            params,
+        }
+    }
+
+    /// Split `data` into literals and matches, which may refer to the data given
+    /// before.
+    pub fn tokenize(&mut self, data: &[u8]) -> Vec<Token> {
+        let start = self.base + self.buf.len();
+        self.buf.extend_from_slice(data);
+        let end = self.base + self.buf.len();
+
+        let tokens = if self.params.max_lazy == 0 {
+            self.tokenize_greedy(start, end)
+        } else {
+            self.tokenize_lazy(start, end)
+        };
+
+        let excess = self.buf.len().saturating_sub(HISTORY_SIZE);
+        self.buf.drain(..excess);
+        self.base += excess;
+        tokens
+    }
+
+    /// Take the longest match at each position.
+    fn tokenize_greedy(&mut self, start: usize, end: usize) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut pos = start;
+        while pos < end {
+            match self.longest_match(pos, end, MIN_MATCH - 1) {
+                Some((length, distance)) => {
+                    tokens.push(Token::Match {
+                        length: length as u16,
+                        distance: distance as u16,
+                    });
+                    for p in pos..pos + length {
+                        self.insert(p, end);
+                    }
+                    pos += length;
+                }
+                None => {
+                    tokens.push(Token::Literal(self.buf[pos - self.base]));
+                    self.insert(pos, end);
+                    pos += 1;
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Take a match only if the next position has no longer one, as zlib does.
+    /// Otherwise the first byte is emitted as a literal and the longer match is
+    /// deferred in turn.
+    fn tokenize_lazy(&mut self, start: usize, end: usize) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut pos = start;
+        // Match found at the previous position.
+        let mut deferred: Option<(usize, usize)> = None;
+        while pos < end {
+            let prev_length = deferred.map_or(MIN_MATCH - 1, |(length, _)| length);
+            let found = match prev_length < self.params.max_lazy.max(MIN_MATCH) {
+                true => self.longest_match(pos, end, prev_length),
+                false => None,
+            };
+            self.insert(pos, end);
+            match (deferred, found) {
+                (Some((length, distance)), None) => {
+                    tokens.push(Token::Match {
+                        length: length as u16,
+                        distance: distance as u16,
+                    });
+                    for p in pos + 1..pos - 1 + length {
+                        self.insert(p, end);
+                    }
+                    pos += length - 1;
+                    deferred = None;
+                }
+                (Some(_), Some(_)) => {
+                    tokens.push(Token::Literal(self.buf[pos - 1 - self.base]));
+                    deferred = found;
+                    pos += 1;
+                }
+                (None, Some(_)) => {
+                    deferred = found;
+                    pos += 1;
+                }
+                (None, None) => {
+                    tokens.push(Token::Literal(self.buf[pos - self.base]));
+                    pos += 1;
+                }
+            }
+        }
+        if let Some((length, distance)) = deferred {
+            tokens.push(Token::Match {
+                length: length as u16,
+                distance: distance as u16,
+            });
+        }
+        tokens
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let i = pos - self.base;
+        let bytes = u32::from_le_bytes([self.buf[i], self.buf[i + 1], self.buf[i + 2], 0]);
+        (bytes.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize, end: usize) {
+        if pos + MIN_MATCH > end {
+            return;
+        }
+        let hash = self.hash(pos);
+        self.prev[pos % HISTORY_SIZE] = self.head[hash];
+        self.head[hash] = pos;
+    }
+
+    /// Longest match for the data at `pos` not going past `end`, as (length, distance),
+    /// if there is one longer than `prev_length`.
+    fn longest_match(&self, pos: usize, end: usize, prev_length: usize) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > end {
+            return None;
+        }
+        let max_len = std::cmp::min(MAX_MATCH, end - pos);
+        let nice_len = std::cmp::min(self.params.nice_length, max_len);
+        let current = &self.buf[pos - self.base..pos - self.base + max_len];
+        let mut candidate = self.head[self.hash(pos)];
+        let mut chain = self.params.max_chain;
+        if prev_length >= self.params.good_length {
+            chain /= 4;
+        }
+        let mut best = None;
+        let mut best_length = prev_length;
+        while chain > 0 {
+            if candidate == NIL || pos - candidate > MAX_DISTANCE {
+                break;
+            }
+            let length = current
+                .iter()
+                .zip(&self.buf[candidate - self.base..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best_length {
+                if best_length < self.params.good_length && length >= self.params.good_length {
+                    chain /= 4;
+                }
+                best = Some((length, pos - candidate));
+                best_length = length;
+                if length >= nice_len {
+                    break;
+                }
+            }
+            // Slots of positions out of the window are reused by newer positions.
+            let next = self.prev[candidate % HISTORY_SIZE];
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain = chain.saturating_sub(1);
+        }
+        best
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: MatchParams = MatchParams {
+        max_chain: 128,
+        good_length: 8,
+        max_lazy: 0,
+        nice_length: 128,
+    };
+
+    fn expand(tokens: &[Token], output: &mut Vec<u8>) {
+        for &token in tokens {
+            match token {
+                Token::Literal(value) => output.push(value),
+                Token::Match { length, distance } => {
+                    assert!((MIN_MATCH..=MAX_MATCH).contains(&(length as usize)));
+                    assert!((1..=MAX_DISTANCE).contains(&(distance as usize)));
+                    for _ in 0..length {
+                        output.push(output[output.len() - distance as usize]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize() {
+        let mut matcher = Matcher::new(PARAMS);
+        let tokens = matcher.tokenize(b"abcabcabcabcx");
+        assert_eq!(
+            tokens,
+            [
+                Token::Literal(b'a'),
+                Token::Literal(b'b'),
+                Token::Literal(b'c'),
+                Token::Match {
+                    length: 9,
+                    distance: 3
+                },
+                Token::Literal(b'x'),
+            ]
+        );
+        // Matches reach back into the data given before.
+        let tokens = matcher.tokenize(b"bcabcx");
+        assert_eq!(
+            tokens,
+            [Token::Match {
+                length: 6,
+                distance: 6
+            }]
+        );
+    }
+
+    #[test]
+    fn windows() {
+        let mut data = Vec::new();
+        let mut state = 1u32;
+        while data.len() < 200_000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let word = (state >> 16) % 64;
+            data.extend_from_slice(format!("w{word} ").as_bytes());
+        }
+        for (max_lazy, nice_length) in [(0, 3), (0, 16), (0, 258), (4, 16), (258, 258)] {
+            let mut matcher = Matcher::new(MatchParams {
+                max_lazy,
+                nice_length,
+                ..PARAMS
+            });
+            let mut output = Vec::new();
+            for chunk in data.chunks(50_000) {
+                expand(&matcher.tokenize(chunk), &mut output);
+            }
+            assert!(output == data);
+        }
+    }
+
+    #[test]
+    fn lazy() {
+        // Greedy matching takes "abc" and then "def"; lazy matching sees "bcdef" at
+        // the next position and takes it after a literal instead.
+        let data = b"abc-bcdef-abcdef";
+        let mut greedy = Matcher::new(PARAMS);
+        let tokens = greedy.tokenize(data);
+        assert_eq!(
+            tokens[10..],
+            [
+                Token::Match {
+                    length: 3,
+                    distance: 10
+                },
+                Token::Match {
+                    length: 3,
+                    distance: 7
+                }
+            ]
+        );
+
+        let mut lazy = Matcher::new(MatchParams {
+            max_lazy: 16,
+            ..PARAMS
+        });
+        let tokens = lazy.tokenize(data);
+        assert_eq!(
+            tokens[10..],
+            [
+                Token::Literal(b'a'),
+                Token::Match {
+                    length: 5,
+                    distance: 7
+                }
+            ]
+        );
+        let mut output = Vec::new();
+        expand(&tokens, &mut output);
+        assert_eq!(output, data);
+    }
+}
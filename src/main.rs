@@ -1,11 +1,20 @@
 #![forbid(unsafe_code)]
 
-use std::io::{stdin, stdout};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, stdin, stdout, BufRead, BufReader, BufWriter, Read};
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+use anyhow::{anyhow, bail, Result};
 use log::*;
 use structopt::StructOpt;
 
-use ripgzip::decompress;
+use ripgzip::{
+    compress, decode_latin1, decompress, decompress_pipelined, encode_latin1, hex_dump, members,
+    verify, CompressOptions, DecompressOptions, GzipDecoder, GzipEncoder, MemberInfo,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt()]
@@ -13,13 +22,59 @@ struct Opts {
     /// Decompress data
     #[structopt(short = "d", long = "decompress")]
     decompress: bool,
+    /// Check the integrity of the compressed data without writing it out
+    #[structopt(short = "t", long = "test")]
+    test: bool,
+    /// List the members of the compressed data with their sizes and names
+    #[structopt(short = "l", long = "list")]
+    list: bool,
+    /// Print --list and --test results as JSON, one record per line: a member
+    /// for --list, a file for --test
+    #[structopt(long = "json")]
+    json: bool,
+    /// Print an annotated hex dump of the compressed data: the header fields, the
+    /// blocks with their bit offsets and the footer
+    #[structopt(long = "hex-dump")]
+    hex_dump: bool,
+    /// Copy input which is not gzip compressed as is when decompressing, and
+    /// overwrite existing output files
+    #[structopt(short = "f", long = "force")]
+    force: bool,
+    /// Write to standard output, keeping the input files
+    #[structopt(short = "c", long = "stdout")]
+    stdout: bool,
+    /// Keep the input files when compressing. Decompressing always keeps them
+    #[structopt(short = "k", long = "keep")]
+    keep: bool,
+    /// Compression level, from 1 (fastest) to 9 (best), also given as -1 to -9
+    #[structopt(long = "level", default_value = "6")]
+    level: u32,
+    /// Read, inflate and write on separate threads, when decompressing standard
+    /// input. Not available with -f or --rate-limit
+    #[structopt(long = "pipeline", conflicts_with_all = &["force", "rate-limit"])]
+    pipeline: bool,
+    /// Write the decompressed data at most at this rate, in bytes per second
+    #[structopt(long = "rate-limit")]
+    rate_limit: Option<NonZeroU64>,
+    /// Restore the original name and timestamp from the header when decompressing.
+    /// They are always saved when compressing, unless -n is given
+    #[structopt(short = "N", long = "name")]
+    name: bool,
+    /// Do not save or restore the original name and timestamp, overriding -N
+    #[structopt(short = "n", long = "no-name")]
+    no_name: bool,
     /// Verbose mode (-v, -vv, -vvv, etc)
     #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
     verbose: usize,
+    /// Files to compress, each into a file with the ".gz" suffix, to decompress,
+    /// each into a file without it, or to test. Standard input is compressed or
+    /// decompressed to standard output if none are given
+    #[structopt(parse(from_os_str))]
+    files: Vec<PathBuf>,
 }
 
 fn main() {
-    let opts = Opts::from_args();
+    let opts = Opts::from_iter(std::env::args_os().map(level_flag));
 
     stderrlog::new()
         .verbosity(1 + opts.verbose)
@@ -27,10 +82,405 @@ fn main() {
         .init()
         .expect("failed to initialize logging");
 
-    if opts.decompress {
-        if let Err(err) = decompress(stdin().lock(), stdout().lock()) {
-            error!("{:#}", err);
+    if opts.hex_dump {
+        let mut failed = false;
+        if opts.files.is_empty() {
+            let mut data = Vec::new();
+            let result = stdin()
+                .lock()
+                .read_to_end(&mut data)
+                .map_err(Into::into)
+                .and_then(|_| hex_dump(&data, BufWriter::new(stdout().lock())));
+            if let Err(err) = result {
+                error!("{:#}", err);
+                failed = true;
+            }
+        }
+        for file in &opts.files {
+            let result = fs::read(file)
+                .map_err(Into::into)
+                .and_then(|data| hex_dump(&data, BufWriter::new(stdout().lock())));
+            if let Err(err) = result {
+                error!("{}: {:#}", file.display(), err);
+                failed = true;
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    } else if opts.list {
+        let mut failed = false;
+        if !opts.json {
+            println!("{:>19} {:>19}  ratio name", "compressed", "uncompressed");
+        }
+        if opts.files.is_empty() {
+            if let Err(err) = list(stdin().lock(), "-", opts.json) {
+                error!("{:#}", err);
+                failed = true;
+            }
+        }
+        for file in &opts.files {
+            let name = file.display().to_string();
+            let result = File::open(file)
+                .map_err(Into::into)
+                .and_then(|file| list(BufReader::new(file), &name, opts.json));
+            if let Err(err) = result {
+                error!("{}: {:#}", name, err);
+                failed = true;
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    } else if opts.test {
+        let mut failed = false;
+        if opts.files.is_empty() {
+            let result = verify(stdin().lock());
+            failed |= report_test("-", result, opts.json);
+        }
+        for file in &opts.files {
+            let result = File::open(file)
+                .map_err(Into::into)
+                .and_then(|file| verify(BufReader::new(file)));
+            failed |= report_test(&file.display().to_string(), result, opts.json);
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    } else if opts.decompress {
+        let mut failed = false;
+        if opts.files.is_empty() {
+            let result = if opts.force || opts.rate_limit.is_some() {
+                let mut decoder =
+                    GzipDecoder::with_options(stdin().lock(), decompress_options(&opts));
+                std::io::copy(&mut decoder, &mut stdout().lock())
+                    .map(drop)
+                    .map_err(Into::into)
+            } else if opts.pipeline {
+                decompress_pipelined(stdin(), stdout().lock())
+            } else {
+                decompress(stdin().lock(), stdout().lock())
+            };
+            if let Err(err) = result {
+                error!("{:#}", err);
+                failed = true;
+            }
+        }
+        for file in &opts.files {
+            if let Err(err) = decompress_file(file, &opts) {
+                error!("{}: {:#}", file.display(), err);
+                failed = true;
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    } else {
+        let mut failed = false;
+        if opts.files.is_empty() {
+            let options = compress_options(&opts);
+            if let Err(err) = compress(stdin().lock(), stdout().lock(), options) {
+                error!("{:#}", err);
+                failed = true;
+            }
+        }
+        for file in &opts.files {
+            if let Err(err) = compress_file(file, &opts) {
+                error!("{}: {:#}", file.display(), err);
+                failed = true;
+            }
+        }
+        if failed {
             std::process::exit(1);
         }
     }
 }
+
+/// Print the members of `input`, read from `file`, as a table row or a JSON
+/// record each.
+fn list<R: BufRead>(input: R, file: &str, json: bool) -> Result<()> {
+    for (index, member) in members(input)?.iter().enumerate() {
+        if json {
+            println!("{}", member_json(file, index, member));
+            continue;
+        }
+        let compressed = member.compressed_range.end - member.compressed_range.start;
+        let ratio = match member.size {
+            0 => 0.0,
+            size => 100.0 * (1.0 - compressed as f64 / size as f64),
+        };
+        println!(
+            "{:>19} {:>19} {:5.1}% {}",
+            compressed,
+            member.size,
+            ratio,
+            member
+                .name
+                .as_deref()
+                .map_or(file.to_string(), display_text)
+        );
+    }
+    Ok(())
+}
+
+fn member_json(file: &str, index: usize, member: &MemberInfo) -> String {
+    let blocks = &member.blocks;
+    format!(
+        concat!(
+            r#"{{"file":{},"member":{},"name":{},"comment":{},"modification_time":{},"os":{},"#,
+            r#""crc32":{},"size":{},"compressed_start":{},"compressed_end":{},"#,
+            r#""stored_blocks":{},"fixed_blocks":{},"dynamic_blocks":{}}}"#
+        ),
+        json_string(file),
+        index,
+        member
+            .name
+            .as_deref()
+            .map_or("null".to_string(), |name| json_string(&display_text(name))),
+        member
+            .comment
+            .as_deref()
+            .map_or("null".to_string(), |comment| json_string(&display_text(
+                comment
+            ))),
+        member.modification_time,
+        member.os,
+        member.crc32,
+        member.size,
+        member.compressed_range.start,
+        member.compressed_range.end,
+        blocks.stored_blocks,
+        blocks.fixed_blocks,
+        blocks.dynamic_blocks,
+    )
+}
+
+/// Report the result of testing `file`, returning whether it failed.
+fn report_test(file: &str, result: Result<()>, json: bool) -> bool {
+    match (result, json) {
+        (Ok(()), false) => false,
+        (Ok(()), true) => {
+            println!(r#"{{"file":{},"ok":true}}"#, json_string(file));
+            false
+        }
+        (Err(err), false) => {
+            error!("{}: {:#}", file, err);
+            true
+        }
+        (Err(err), true) => {
+            println!(
+                r#"{{"file":{},"ok":false,"error":{}}}"#,
+                json_string(file),
+                json_string(&format!("{:#}", err))
+            );
+            true
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Turn the -1 to -9 flags of gzip into --level.
+fn level_flag(arg: OsString) -> OsString {
+    match arg.to_str().and_then(|arg| arg.strip_prefix('-')) {
+        Some(level) if level.len() == 1 && level.as_bytes()[0].is_ascii_digit() => {
+            format!("--level={}", level).into()
+        }
+        _ => arg,
+    }
+}
+
+fn compress_file(input: &Path, opts: &Opts) -> Result<()> {
+    if input.extension().is_some_and(|extension| extension == "gz") {
+        bail!("already has .gz suffix");
+    }
+    let mut input_file = File::open(input)?;
+    let metadata = input_file.metadata()?;
+    let mut options = compress_options(opts);
+    if !opts.no_name {
+        options.name = header_name(input);
+        options.modification_time = metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |mtime| mtime.as_secs() as u32);
+    }
+    if opts.stdout {
+        return compress(input_file, stdout().lock(), options);
+    }
+
+    let mut output = input.as_os_str().to_owned();
+    output.push(".gz");
+    let output = PathBuf::from(output);
+    let file = create_output(input, &output, opts.force)?;
+    let result = GzipEncoder::new(BufWriter::new(file), options).and_then(|mut encoder| {
+        std::io::copy(&mut input_file, &mut encoder)?;
+        let file = encoder
+            .finish()?
+            .into_inner()
+            .map_err(|err| err.into_error())?;
+        copy_owner(&file, &metadata);
+        file.set_permissions(metadata.permissions())?;
+        file.set_modified(metadata.modified()?)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&output);
+    } else if !opts.keep {
+        fs::remove_file(input)?;
+    }
+    result
+}
+
+fn decompress_file(input: &Path, opts: &Opts) -> Result<()> {
+    let input_file = File::open(input)?;
+    let metadata = input_file.metadata()?;
+    let mut decoder =
+        GzipDecoder::with_options(BufReader::new(input_file), decompress_options(opts));
+    if opts.stdout {
+        std::io::copy(&mut decoder, &mut stdout().lock())?;
+        return Ok(());
+    }
+    // Decode up to the first data, so that the header is known.
+    decoder.fill_buf()?;
+    let header = decoder.header();
+    let restore = opts.name && !opts.no_name;
+
+    // Only the last component of the stored name is used, not to write anywhere else.
+    let stored_name = header
+        .and_then(|header| header.name.as_deref())
+        .filter(|_| restore)
+        .map(name_path);
+    let output = match stored_name.as_deref().and_then(Path::file_name) {
+        Some(name) => input.with_file_name(name),
+        None => strip_suffix(input)?,
+    };
+    let mtime = header
+        .map(|header| header.modification_time)
+        .filter(|&mtime| restore && mtime != 0)
+        .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64));
+
+    let file = create_output(input, &output, opts.force)?;
+    let mut writer = BufWriter::new(file);
+    let result = std::io::copy(&mut decoder, &mut writer)
+        .map_err(Into::into)
+        .and_then(|_| {
+            let file = writer.into_inner().map_err(|err| err.into_error())?;
+            copy_owner(&file, &metadata);
+            file.set_permissions(metadata.permissions())?;
+            if let Some(mtime) = mtime {
+                file.set_modified(mtime)?;
+            }
+            Ok(())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&output);
+    }
+    result
+}
+
+/// The FNAME stored for `path`: the bytes of its file name as is, as gzip does.
+fn header_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?;
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(name);
+    #[cfg(not(unix))]
+    let bytes = name.to_str()?.as_bytes();
+    Some(decode_latin1(bytes))
+}
+
+/// The path of a stored FNAME, from its bytes as is where paths are bytes.
+fn name_path(name: &str) -> PathBuf {
+    #[cfg(unix)]
+    if let Some(bytes) = encode_latin1(name) {
+        let name: OsString = std::os::unix::ffi::OsStringExt::from_vec(bytes);
+        return name.into();
+    }
+    PathBuf::from(display_text(name))
+}
+
+/// FNAME or FCOMMENT text as shown: stored bytes which are UTF-8, as most tools
+/// write them nowadays, are shown as such, others as ISO 8859-1.
+fn display_text(text: &str) -> String {
+    encode_latin1(text)
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_else(|| text.to_string())
+}
+
+/// Create the file the compressed or decompressed `input` goes to. A stored name
+/// may point at the input itself, which is never written over, or at another
+/// file, which is only written over if `force`.
+fn create_output(input: &Path, output: &Path, force: bool) -> Result<File> {
+    if let (Ok(resolved_input), Ok(resolved)) = (fs::canonicalize(input), fs::canonicalize(output))
+    {
+        if resolved_input == resolved {
+            bail!("{} is the input itself", output.display());
+        }
+    }
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(output)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => {
+                anyhow!(
+                    "{} already exists, use -f to overwrite it",
+                    output.display()
+                )
+            }
+            _ => anyhow!(err).context(format!("failed to create {}", output.display())),
+        })
+}
+
+fn decompress_options(opts: &Opts) -> DecompressOptions {
+    DecompressOptions {
+        passthrough: opts.force,
+        rate_limit: opts.rate_limit,
+        ..Default::default()
+    }
+}
+
+fn compress_options(opts: &Opts) -> CompressOptions {
+    CompressOptions {
+        level: opts.level,
+        ..Default::default()
+    }
+}
+
+/// Give `file` the owner and group of the input, as far as permitted.
+#[cfg(unix)]
+fn copy_owner(file: &File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        // Unprivileged users may still change the group to one of theirs.
+        let _ = fchown(file, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_file: &File, _metadata: &fs::Metadata) {}
+
+fn strip_suffix(input: &Path) -> Result<PathBuf> {
+    input
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".gz"))
+        .filter(|name| !name.is_empty())
+        .map(|name| input.with_file_name(name))
+        .ok_or_else(|| anyhow!("unknown suffix"))
+}
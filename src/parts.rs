@@ -0,0 +1,152 @@
+#![forbid(unsafe_code)]
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::decoder::PartialOutput;
+use crate::gzip::GzipDecoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Part a stream split across files failed in, see `decompress_parts`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartError {
+    pub index: usize,
+    pub path: PathBuf,
+    /// Offset in the part of the input being decoded.
+    pub offset: u64,
+}
+
+impl fmt::Display for PartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "in part {} ({}) at offset {}",
+            self.index,
+            self.path.display(),
+            self.offset
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reader of files one after the other, as a single stream. Each file is opened
+/// once the previous one is read to the end.
+pub struct PartsReader {
+    paths: Vec<PathBuf>,
+    /// Index of the part being read, or of the next one to open.
+    index: usize,
+    current: Option<BufReader<File>>,
+    /// Offset in the stream of the start of each part opened so far.
+    starts: Vec<u64>,
+    offset: u64,
+}
+
+impl PartsReader {
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> Self {
+        Self {
+            paths: paths
+                .iter()
+                .map(|path| path.as_ref().to_path_buf())
+                .collect(),
+            index: 0,
+            current: None,
+            starts: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// The part being read, and the offset in it of the next byte.
+    pub fn position(&self) -> Option<(usize, &Path, u64)> {
+        let index = self.index.min(self.starts.len().checked_sub(1)?);
+        let offset = self.offset - self.starts[index];
+        Some((index, &self.paths[index], offset))
+    }
+
+    fn open_next(&mut self) -> io::Result<()> {
+        let path = &self.paths[self.index];
+        let file = File::open(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        self.current = Some(BufReader::new(file));
+        self.starts.push(self.offset);
+        Ok(())
+    }
+}
+
+impl Read for PartsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl BufRead for PartsReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.index < self.paths.len() {
+            let Some(reader) = &mut self.current else {
+                self.open_next()?;
+                continue;
+            };
+            if !reader.fill_buf()?.is_empty() {
+                break;
+            }
+            self.current = None;
+            self.index += 1;
+        }
+        match &mut self.current {
+            Some(reader) => reader.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Some(reader) = &mut self.current {
+            reader.consume(amount);
+            self.offset += amount as u64;
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Decompress a gzip stream split across the files `parts`, e.g. `log.gz.000`,
+/// `log.gz.001` and so on, members possibly spanning two parts. Errors get a
+/// `PartError` context telling the part which was being decoded, besides the
+/// `PartialOutput` one of `decompress`.
+pub fn decompress_parts<P: AsRef<Path>, W: Write>(parts: &[P], mut output: W) -> Result<()> {
+    let mut decoder = GzipDecoder::new(PartsReader::new(parts));
+    let mut bytes_written = 0;
+    loop {
+        let data = match decoder.fill_buffer() {
+            Ok(data) => data,
+            Err(err) => {
+                output.flush()?;
+                let err = err.context(PartialOutput { bytes_written });
+                return Err(match decoder.get_ref().position() {
+                    Some((index, path, offset)) => err.context(PartError {
+                        index,
+                        path: path.to_path_buf(),
+                        offset,
+                    }),
+                    None => err,
+                });
+            }
+        };
+        if data.is_empty() {
+            break;
+        }
+        output.write_all(data)?;
+        let size = data.len();
+        bytes_written += size as u64;
+        decoder.consume_buffer(size);
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, BufRead, Read, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::checksum::{Checksum, Crc32};
+use crate::gzip::{GzipDecoder, MemberFooter};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const READ_CHUNK_SIZE: usize = 1 << 16;
+
+/// Number of chunks which may be in flight between two stages.
+const CHANNEL_CAPACITY: usize = 4;
+
+enum Output {
+    Data(Vec<u8>),
+    MemberEnd(MemberFooter),
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// `BufRead` over the chunks sent by the reading stage.
+struct ChannelReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl BufRead for ChannelReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.chunk.len() {
+            // A closed channel means the input is over.
+            if let Ok(chunk) = self.receiver.recv() {
+                self.chunk = chunk?;
+                self.pos = 0;
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = std::cmp::min(self.pos + amount, self.chunk.len());
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Decompress `input` into `output` with three threads: one reads the input, one
+/// inflates, and the calling thread writes the output and computes the crc32.
+///
+/// Useful for big single-member streams where I/O and decoding can overlap.
+pub fn decompress_pipelined<R: Read + Send, W: Write>(input: R, mut output: W) -> Result<()> {
+    let (input_sender, input_receiver) = sync_channel(CHANNEL_CAPACITY);
+    let (output_sender, output_receiver) = sync_channel(CHANNEL_CAPACITY);
+
+    thread::scope(|scope| {
+        let reading = scope.spawn(move || read_stage(input, input_sender));
+        let inflating = scope.spawn(move || inflate_stage(input_receiver, output_sender));
+        let writing = write_stage(output_receiver, &mut output);
+
+        // The inflating error explains a failed write best, as the writing stage only
+        // sees the channel closing early.
+        inflating
+            .join()
+            .map_err(|_| anyhow!("inflating thread panicked"))??;
+        writing?;
+        reading
+            .join()
+            .map_err(|_| anyhow!("reading thread panicked"))?;
+        Ok(())
+    })
+}
+
+fn read_stage<R: Read>(mut input: R, sender: SyncSender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        let result = match input.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(size) => {
+                chunk.truncate(size);
+                Ok(chunk)
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => Err(err),
+        };
+        let failed = result.is_err();
+        if sender.send(result).is_err() || failed {
+            break;
+        }
+    }
+}
+
+fn inflate_stage(
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    sender: SyncSender<Output>,
+) -> Result<()> {
+    let mut decoder = GzipDecoder::with_deferred_crc(ChannelReader {
+        receiver,
+        chunk: Vec::new(),
+        pos: 0,
+    });
+    loop {
+        let data = decoder.fill_buffer()?.to_vec();
+        decoder.consume_buffer(data.len());
+        for footer in decoder.take_footers() {
+            if sender.send(Output::MemberEnd(footer)).is_err() {
+                return Ok(());
+            }
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        if sender.send(Output::Data(data)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn write_stage<W: Write>(receiver: Receiver<Output>, output: &mut W) -> Result<()> {
+    let mut crc32 = Crc32::default();
+    for message in receiver {
+        match message {
+            Output::Data(data) => {
+                output.write_all(&data)?;
+                crc32.update(&data);
+            }
+            Output::MemberEnd(footer) => {
+                if crc32.value() != footer.data_crc32 {
+                    bail!("crc32 check failed");
+                }
+                crc32.reset();
+            }
+        }
+    }
+    Ok(())
+}
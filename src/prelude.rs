@@ -0,0 +1,25 @@
+#![forbid(unsafe_code)]
+
+//! The commonly used types, for a glob import:
+//!
+//! ```
+//! use ripgzip::prelude::*;
+//! ```
+
+#[cfg(feature = "raw")]
+pub use crate::decoder::DeflateDecoder;
+pub use crate::decoder::PartialOutput;
+pub use crate::deflate::BlockUsage;
+pub use crate::timeout::ReadTimeout;
+#[cfg(feature = "zlib")]
+pub use crate::zlib::ZlibDecoder;
+
+#[cfg(feature = "gzip")]
+pub use crate::{
+    batch::FileStats,
+    bounded::{Bounded, DecompressStats},
+    decompress,
+    encoder::{compress, CompressOptions, GzipEncoder},
+    gzip::{DecompressOptions, GzipDecoder, MemberFlags, MemberFooter, MemberHeader, MemberInfo},
+    parts::PartError,
+};
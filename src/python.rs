@@ -0,0 +1,125 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, BufRead, BufReader, Read};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use crate::gzip::GzipDecoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn into_py_error(err: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(format!("{:#}", err))
+}
+
+/// Decompress a whole gzip stream.
+#[pyfunction]
+fn decompress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let mut output = Vec::new();
+    crate::decompress(data, &mut output).map_err(into_py_error)?;
+    Ok(PyBytes::new(py, &output))
+}
+
+/// Decompress a gzip stream, returning a dict per member.
+#[pyfunction]
+fn members<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let members = crate::members(data).map_err(into_py_error)?;
+    members
+        .into_iter()
+        .map(|member| {
+            let dict = PyDict::new(py);
+            dict.set_item("name", member.name)?;
+            dict.set_item("comment", member.comment)?;
+            dict.set_item("mtime", member.modification_time)?;
+            dict.set_item("os", member.os)?;
+            dict.set_item("crc32", member.crc32)?;
+            dict.set_item("size", member.size)?;
+            Ok(dict)
+        })
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reader calling `read` on a Python file object.
+struct PyFileReader {
+    file: Py<PyAny>,
+}
+
+impl Read for PyFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Python::attach(|py| {
+            let data = self
+                .file
+                .call_method1(py, "read", (buf.len(),))
+                .map_err(io::Error::other)?;
+            let data = data
+                .extract::<&[u8]>(py)
+                .map_err(|err| io::Error::other(PyErr::from(err)))?;
+            let size = std::cmp::min(data.len(), buf.len());
+            buf[..size].copy_from_slice(&data[..size]);
+            Ok(size)
+        })
+    }
+}
+
+/// Read-only file object decompressing a binary file object lazily.
+#[pyclass(unsendable)]
+struct GzipReader {
+    decoder: GzipDecoder<BufReader<PyFileReader>>,
+}
+
+#[pymethods]
+impl GzipReader {
+    #[new]
+    fn new(file: Py<PyAny>) -> Self {
+        Self {
+            decoder: GzipDecoder::new(BufReader::new(PyFileReader { file })),
+        }
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    /// Read up to `size` bytes, or everything left if `size` is negative.
+    #[pyo3(signature = (size = -1))]
+    fn read<'py>(&mut self, py: Python<'py>, size: i64) -> PyResult<Bound<'py, PyBytes>> {
+        let mut data = Vec::new();
+        match u64::try_from(size) {
+            Ok(size) => (&mut self.decoder).take(size).read_to_end(&mut data),
+            Err(_) => self.decoder.read_to_end(&mut data),
+        }
+        .map_err(into_py_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn readline<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut line = Vec::new();
+        self.decoder
+            .read_until(b'\n', &mut line)
+            .map_err(into_py_error)?;
+        Ok(PyBytes::new(py, &line))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let line = self.readline(py)?;
+        Ok((!line.as_bytes().is_empty()).then_some(line))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[pymodule]
+fn ripgzip(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(decompress, module)?)?;
+    module.add_function(wrap_pyfunction!(members, module)?)?;
+    module.add_class::<GzipReader>()?;
+    Ok(())
+}
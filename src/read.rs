@@ -0,0 +1,81 @@
+#![forbid(unsafe_code)]
+
+//! Wrappers which are read from, as in `flate2::read`: they read the input from
+//! the inner reader and hand out the output.
+
+use anyhow::Result;
+use std::io::{self, Read};
+
+use crate::decoder::into_io_error;
+use crate::encoder::{self, CompressOptions};
+
+pub use crate::gzip::GzipDecoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Size of the chunks of input read and compressed at a time.
+const CHUNK_SIZE: usize = 1 << 14;
+
+/// Gzip encoder compressing the data read from the inner reader into a single
+/// member, for pipelines which pull compressed data, e.g. an upload body.
+pub struct GzipEncoder<R> {
+    input: R,
+    /// `None` once the input ended and the member was completed.
+    encoder: Option<encoder::GzipEncoder<Vec<u8>>>,
+    /// Compressed data not read yet, from `position` on.
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> GzipEncoder<R> {
+    pub fn new(input: R, options: CompressOptions) -> Result<Self> {
+        let encoder = encoder::GzipEncoder::new(Vec::new(), options)?;
+        Ok(Self {
+            input,
+            encoder: Some(encoder),
+            output: Vec::new(),
+            position: 0,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.input
+    }
+
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Compress the next chunk of input, or complete the member at its end.
+    fn fill(&mut self) -> io::Result<()> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Ok(());
+        };
+        let mut chunk = [0; CHUNK_SIZE];
+        let len = self.input.read(&mut chunk)?;
+        self.output.clear();
+        self.position = 0;
+        if len == 0 {
+            let encoder = self.encoder.take().unwrap();
+            self.output = encoder.finish().map_err(into_io_error)?;
+        } else {
+            io::Write::write_all(encoder, &chunk[..len])?;
+            std::mem::swap(&mut self.output, encoder.get_mut());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzipEncoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Compressing a chunk may give no output yet, until a block is complete.
+        while self.position == self.output.len() && self.encoder.is_some() {
+            self.fill()?;
+        }
+        let data = &self.output[self.position..];
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.position += size;
+        Ok(size)
+    }
+}
@@ -0,0 +1,156 @@
+#![forbid(unsafe_code)]
+
+use anyhow::{ensure, Result};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use crate::bit_reader::BitSequence;
+use crate::decoder::into_io_error;
+use crate::gzip::GzipDecoder;
+use crate::index::{GzipIndex, WindowStore};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reader of the decompressed data of a gzip file, seeking with the points of an
+/// index: a seek decodes from the last point before the target, or from the
+/// current position if it is closer.
+pub struct SeekableGzipReader<R: Read + Seek> {
+    /// Always set, except while repositioning.
+    decoder: Option<GzipDecoder<BufReader<R>>>,
+    index: GzipIndex,
+    /// Where the windows of the points are, if not in the index.
+    windows: Option<WindowStore>,
+    position: u64,
+    size: u64,
+}
+
+impl<R: Read + Seek> SeekableGzipReader<R> {
+    /// Create a reader using `index`, which must have been built for `inner`.
+    ///
+    /// If the index does not know the size of the decompressed data, it is taken
+    /// from the ISIZE field of the last footer, which is only right for a single
+    /// member smaller than 4G.
+    pub fn new(mut inner: R, index: GzipIndex) -> Result<Self> {
+        let size = if index.uncompressed_size > 0 {
+            index.uncompressed_size
+        } else {
+            inner.seek(SeekFrom::End(-4))?;
+            let mut size = [0; 4];
+            inner.read_exact(&mut size)?;
+            u32::from_le_bytes(size) as u64
+        };
+        inner.rewind()?;
+        Ok(Self {
+            decoder: Some(GzipDecoder::new(BufReader::new(inner))),
+            index,
+            windows: None,
+            position: 0,
+            size,
+        })
+    }
+
+    /// Create a reader using `index` whose windows are in `windows`.
+    pub fn with_window_store(inner: R, index: GzipIndex, windows: WindowStore) -> Result<Self> {
+        ensure!(
+            windows.len() == index.points.len(),
+            "window store does not match the index"
+        );
+        let mut reader = Self::new(inner, index)?;
+        reader.windows = Some(windows);
+        Ok(reader)
+    }
+
+    /// Scan `inner` to build an index with a point every `spacing` bytes.
+    pub fn build(mut inner: R, spacing: u64) -> Result<Self> {
+        inner.rewind()?;
+        let index = GzipIndex::build(BufReader::new(&mut inner), spacing)?;
+        Self::new(inner, index)
+    }
+
+    pub fn index(&self) -> &GzipIndex {
+        &self.index
+    }
+
+    /// Size of the decompressed data.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.decoder.unwrap().into_inner().into_inner()
+    }
+
+    fn seek_to(&mut self, target: u64) -> Result<()> {
+        let position = self
+            .index
+            .points
+            .iter()
+            .rposition(|point| point.uncompressed_offset <= target);
+        let point = position.map(|i| &self.index.points[i]);
+        let start = point.map_or(0, |point| point.uncompressed_offset);
+        if target < self.position || start > self.position {
+            let mut inner = self.decoder.take().unwrap().into_inner().into_inner();
+            let decoder = match point {
+                Some(point) => {
+                    let window = match &mut self.windows {
+                        Some(windows) => windows.load(position.unwrap())?,
+                        None => point.window.clone(),
+                    };
+                    let mut pending = BitSequence::new(0, 0);
+                    if point.bits > 0 {
+                        inner.seek(SeekFrom::Start(point.compressed_offset - 1))?;
+                        let mut byte = [0];
+                        inner.read_exact(&mut byte)?;
+                        pending =
+                            BitSequence::new((byte[0] >> (8 - point.bits)) as u16, point.bits);
+                    } else {
+                        inner.seek(SeekFrom::Start(point.compressed_offset))?;
+                    }
+                    GzipDecoder::at_block_boundary(
+                        BufReader::new(inner),
+                        point.compressed_offset,
+                        pending,
+                        &window,
+                    )?
+                }
+                None => {
+                    inner.rewind()?;
+                    GzipDecoder::new(BufReader::new(inner))
+                }
+            };
+            self.decoder = Some(decoder);
+            self.position = start;
+        }
+        let decoder = self.decoder.as_mut().unwrap();
+        io::copy(&mut decoder.take(target - self.position), &mut io::sink())?;
+        self.position = target;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableGzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.decoder.as_mut().unwrap().read(buf)?;
+        self.position += size as u64;
+        Ok(size)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableGzipReader<R> {
+    /// Seeking past the end is allowed, and reads return nothing from there.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative offset")
+        })?;
+        self.seek_to(target).map_err(into_io_error)?;
+        Ok(self.position)
+    }
+}
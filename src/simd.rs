@@ -0,0 +1,191 @@
+#![allow(unsafe_code)]
+
+//! Explicit SIMD paths, checked for at runtime. Every function returns `None`
+//! when the CPU lacks the instructions, and the caller falls back to safe code.
+
+use std::arch::x86_64::*;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "crc32")]
+pub(crate) use fold::crc32;
+
+#[cfg(feature = "crc32")]
+mod fold {
+    use super::*;
+
+    /// Folding constants for the gzip CRC-32 polynomial, bit-reflected: x^(4*128+32)
+    /// and x^(4*128-32) mod P, then the same for 128, x^64 mod P, P and mu.
+    const K1: i64 = 0x154442bd4;
+    const K2: i64 = 0x1c6e41596;
+    const K3: i64 = 0x1751997d0;
+    const K4: i64 = 0x0ccaa009e;
+    const K5: i64 = 0x163cd6124;
+    const P_X: i64 = 0x1db710641;
+    const U_PRIME: i64 = 0x1f7011641;
+
+    /// Inputs shorter than this are left to the table-driven implementation.
+    const MIN_FOLD_LEN: usize = 128;
+
+    /// Continue the CRC-32 `crc` over a prefix of `data` with carry-less
+    /// multiplication. Returns the new CRC and the length of the prefix.
+    pub(crate) fn crc32(crc: u32, data: &[u8]) -> Option<(u32, usize)> {
+        if data.len() < MIN_FOLD_LEN
+            || !is_x86_feature_detected!("pclmulqdq")
+            || !is_x86_feature_detected!("sse4.1")
+        {
+            return None;
+        }
+        let len = data.len() & !15;
+        // SAFETY: the required CPU features were detected above.
+        Some((unsafe { crc32_fold(crc, &data[..len]) }, len))
+    }
+
+    /// `data.len()` must be a multiple of 16, and at least 64.
+    #[target_feature(enable = "pclmulqdq,sse2,sse4.1")]
+    fn crc32_fold(crc: u32, mut data: &[u8]) -> u32 {
+        let mut x3 = load(&mut data);
+        let mut x2 = load(&mut data);
+        let mut x1 = load(&mut data);
+        let mut x0 = load(&mut data);
+        x3 = _mm_xor_si128(x3, _mm_cvtsi32_si128(!crc as i32));
+
+        let k1k2 = _mm_set_epi64x(K2, K1);
+        while data.len() >= 64 {
+            x3 = fold(x3, load(&mut data), k1k2);
+            x2 = fold(x2, load(&mut data), k1k2);
+            x1 = fold(x1, load(&mut data), k1k2);
+            x0 = fold(x0, load(&mut data), k1k2);
+        }
+
+        let k3k4 = _mm_set_epi64x(K4, K3);
+        let mut x = fold(x3, x2, k3k4);
+        x = fold(x, x1, k3k4);
+        x = fold(x, x0, k3k4);
+        while !data.is_empty() {
+            x = fold(x, load(&mut data), k3k4);
+        }
+
+        // From 128 to 64 bits.
+        let low32 = _mm_set_epi32(0, 0, 0, !0);
+        let x = _mm_xor_si128(_mm_clmulepi64_si128(x, k3k4, 0x10), _mm_srli_si128(x, 8));
+        let x = _mm_xor_si128(
+            _mm_clmulepi64_si128(_mm_and_si128(x, low32), _mm_set_epi64x(0, K5), 0x00),
+            _mm_srli_si128(x, 4),
+        );
+
+        // Barrett reduction from 64 to 32 bits.
+        let pu = _mm_set_epi64x(U_PRIME, P_X);
+        let t1 = _mm_clmulepi64_si128(_mm_and_si128(x, low32), pu, 0x10);
+        let t2 = _mm_clmulepi64_si128(_mm_and_si128(t1, low32), pu, 0x00);
+        !(_mm_extract_epi32(_mm_xor_si128(x, t2), 1) as u32)
+    }
+
+    #[target_feature(enable = "pclmulqdq,sse2")]
+    fn fold(a: __m128i, b: __m128i, keys: __m128i) -> __m128i {
+        let t1 = _mm_clmulepi64_si128(a, keys, 0x00);
+        let t2 = _mm_clmulepi64_si128(a, keys, 0x11);
+        _mm_xor_si128(_mm_xor_si128(b, t1), t2)
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn load(data: &mut &[u8]) -> __m128i {
+        let (chunk, rest) = data.split_first_chunk::<16>().unwrap();
+        *data = rest;
+        // SAFETY: `chunk` is 16 bytes long, and the load is unaligned.
+        unsafe { _mm_loadu_si128(chunk.as_ptr() as *const __m128i) }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Extend `output`, which holds a pattern, to `len` bytes repeating it, copied 16
+/// bytes at a time. Returns `false`, leaving `output` as is, if SSE2 is missing.
+pub(crate) fn repeat(output: &mut Vec<u8>, len: usize) -> bool {
+    if output.is_empty() || !is_x86_feature_detected!("sse2") {
+        return false;
+    }
+    // Chunks never overlap their source once the period is at least 16 bytes.
+    let pattern_len = output.len();
+    while output.len() < 16 {
+        output.extend_from_within(..pattern_len);
+    }
+    let period = output.len();
+    if period >= len {
+        output.truncate(len);
+        return true;
+    }
+    output.reserve(len + 16 - output.len());
+    // SAFETY: sse2 was detected above.
+    unsafe { copy_chunks(output, period, len) };
+    true
+}
+
+/// Extend `output` to `len` bytes, each byte repeating the one `period` bytes
+/// before. `output` must hold `period` bytes and have room for `len + 16`.
+#[target_feature(enable = "sse2")]
+unsafe fn copy_chunks(output: &mut Vec<u8>, period: usize, len: usize) {
+    assert!(period >= 16 && output.len() == period && output.capacity() >= len + 16);
+    let mut pos = output.len();
+    let base = output.as_mut_ptr();
+    while pos < len {
+        // SAFETY: the source chunk was written already since `period >= 16`,
+        // and the destination chunk ends within the capacity.
+        unsafe {
+            let chunk = _mm_loadu_si128(base.add(pos - period) as *const __m128i);
+            _mm_storeu_si128(base.add(pos) as *mut __m128i, chunk);
+        }
+        pos += 16;
+    }
+    // SAFETY: the first `len` bytes are initialized, the last chunk possibly
+    // writing past them.
+    unsafe { output.set_len(len) };
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "crc32")]
+    #[test]
+    fn crc32_matches_table() {
+        let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let data = (0..5000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        for start in [0, 1, 7] {
+            for len in [128, 129, 200, 1000, 4000] {
+                let data = &data[start..start + len];
+                let Some((value, folded)) = crc32(0x12345678, data) else {
+                    return;
+                };
+                assert_eq!(folded, len & !15);
+                let mut digest = crc.digest_with_initial((0x12345678u32 ^ !0).reverse_bits());
+                digest.update(&data[..folded]);
+                assert_eq!(value, digest.finalize());
+            }
+        }
+    }
+
+    #[test]
+    fn repeat_pattern() {
+        for period in [1, 3, 15, 16, 17, 40] {
+            let pattern = (0..period as u8).collect::<Vec<_>>();
+            for len in [0, 1, 15, 16, 17, 100, 258] {
+                let mut output = pattern.clone();
+                if !repeat(&mut output, len) {
+                    return;
+                }
+                let expected = pattern
+                    .iter()
+                    .copied()
+                    .cycle()
+                    .take(len)
+                    .collect::<Vec<_>>();
+                assert_eq!(output, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+#![forbid(unsafe_code)]
+
+use std::io::Write;
+
+use anyhow::{ensure, Result};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Destination of decompressed data, for consumers which are not `io::Write`.
+pub trait Sink {
+    /// Append `data`, all of it or nothing.
+    fn push(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Number of bytes which can still be pushed, if limited.
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl Sink for Vec<u8> {
+    fn push(&mut self, data: &[u8]) -> Result<()> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// The slice is filled from the start and shrinks to its unfilled part, as with
+/// `io::Write`.
+impl Sink for &mut [u8] {
+    fn push(&mut self, data: &[u8]) -> Result<()> {
+        ensure!(data.len() <= self.len(), "sink is full");
+        let (filled, rest) = std::mem::take(self).split_at_mut(data.len());
+        filled.copy_from_slice(data);
+        *self = rest;
+        Ok(())
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Sink for bytes::BytesMut {
+    fn push(&mut self, data: &[u8]) -> Result<()> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn push(&mut self, data: &[u8]) -> Result<()> {
+        (**self).push(data)
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        (**self).capacity()
+    }
+}
+
+/// Sink writing to an `io::Write`.
+pub struct WriteSink<W>(pub W);
+
+impl<W: Write> Sink for WriteSink<W> {
+    fn push(&mut self, data: &[u8]) -> Result<()> {
+        Ok(self.0.write_all(data)?)
+    }
+}
@@ -0,0 +1,471 @@
+#![forbid(unsafe_code)]
+
+//! Helpers to check the encoder and the decoder against each other, corpora to
+//! feed them with, and valid streams to feed other tools with.
+
+use std::io::{self, Write};
+
+use anyhow::{ensure, Result};
+
+use crate::bit_writer::BitWriter;
+use crate::gzip;
+use crate::{
+    compress, decompress, CompressOptions, CompressionMethod, GzipEncoder, MemberHeader, Strategy,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Compress `data` with `options`, check that it decompresses back to `data` and
+/// return the compressed member.
+pub fn round_trip(data: &[u8], options: CompressOptions) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    compress(data, &mut compressed, options)?;
+    let mut output = Vec::new();
+    decompress(compressed.as_slice(), &mut output)?;
+    ensure!(output == data, "decompressed data differs from the input");
+    Ok(compressed)
+}
+
+/// Round trip `data` through all the compression levels.
+pub fn round_trip_levels(data: &[u8]) -> Result<()> {
+    for level in 0..=9 {
+        round_trip(
+            data,
+            CompressOptions {
+                level,
+                ..Default::default()
+            },
+        )?;
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Deterministic pseudo-random generator, so that corpora are reproducible from
+/// their seed.
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        self.0 >> 16
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n
+    }
+}
+
+/// Uniformly random bytes, which do not compress.
+pub fn random(len: usize, seed: u32) -> Vec<u8> {
+    let mut rng = Lcg(seed);
+    (0..len).map(|_| rng.next() as u8).collect()
+}
+
+/// Words from a small vocabulary separated by spaces and newlines, with many
+/// short matches like natural text.
+pub fn text(len: usize, seed: u32) -> Vec<u8> {
+    const WORDS: [&str; 16] = [
+        "the", "of", "and", "to", "in", "gzip", "member", "block", "deflate", "window", "is", "a",
+        "huffman", "code", "length", "distance",
+    ];
+    let mut rng = Lcg(seed);
+    let mut data = Vec::with_capacity(len + 16);
+    while data.len() < len {
+        data.extend_from_slice(WORDS[rng.below(WORDS.len())].as_bytes());
+        data.push(if rng.below(12) == 0 { b'\n' } else { b' ' });
+    }
+    data.truncate(len);
+    data
+}
+
+/// Long runs of repeated bytes, with matches up to the maximum length.
+pub fn rle(len: usize, seed: u32) -> Vec<u8> {
+    let mut rng = Lcg(seed);
+    let mut data = Vec::with_capacity(len + 1024);
+    while data.len() < len {
+        let value = rng.next() as u8;
+        let run = 1 + rng.below(1024);
+        data.resize(data.len() + run, value);
+    }
+    data.truncate(len);
+    data
+}
+
+/// Bytes whose frequencies follow the Fibonacci sequence, in a random order, so
+/// that their Huffman code is as deep as allowed.
+pub fn skewed(len: usize, seed: u32) -> Vec<u8> {
+    let mut rng = Lcg(seed);
+    let mut pattern = Vec::new();
+    let (mut count, mut next) = (1, 1);
+    for symbol in 0..20 {
+        pattern.resize(pattern.len() + count, symbol);
+        (count, next) = (next, count + next);
+    }
+    for i in (1..pattern.len()).rev() {
+        pattern.swap(i, rng.below(i + 1));
+    }
+    pattern.iter().copied().cycle().take(len).collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Stream made by `valid_stream`, and the data it decompresses to.
+#[derive(Clone, Debug)]
+pub struct GeneratedStream {
+    pub gzip: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Stored block of no data which is not the last one.
+const EMPTY_BLOCK: [u8; 5] = [0, 0, 0, 0xff, 0xff];
+
+/// Valid gzip stream, reproducible from `seed`, to check other tools with. It is
+/// made of up to 8 members with random header fields, including the comment,
+/// FTEXT and FHCRC, compressed at random levels and strategies. Their data is
+/// empty or one of the corpora above, giving matches of the maximal length and
+/// codes of the maximal depth, and some start with an empty stored block.
+pub fn valid_stream(seed: u32) -> Result<GeneratedStream> {
+    let mut rng = Lcg(seed);
+    let mut stream = GeneratedStream {
+        gzip: Vec::new(),
+        data: Vec::new(),
+    };
+    for _ in 0..1 + rng.below(8) {
+        let (len, data_seed) = (rng.below(100_000), rng.next());
+        let data = match rng.below(5) {
+            0 => Vec::new(),
+            1 => random(len, data_seed),
+            2 => text(len, data_seed),
+            3 => rle(len, data_seed),
+            _ => skewed(len, data_seed),
+        };
+        let options = CompressOptions {
+            level: rng.below(10) as u32,
+            strategy: match rng.below(4) {
+                0 => Strategy::HuffmanOnly,
+                _ => Strategy::Default,
+            },
+            ..Default::default()
+        };
+        let string = |rng: &mut Lcg| {
+            let len = 1 + rng.below(40);
+            (rng.below(2) == 0).then(|| String::from_utf8(text(len, rng.next())).unwrap())
+        };
+        let header = MemberHeader {
+            compression_method: CompressionMethod::Deflate,
+            modification_time: rng.next() << 16 | rng.next(),
+            extra: (rng.below(3) == 0).then(|| random(rng.below(64), rng.next())),
+            name: string(&mut rng),
+            comment: string(&mut rng),
+            extra_flags: rng.next() as u8,
+            os: rng.next() as u8,
+            has_crc: rng.below(2) == 0,
+            is_text: rng.below(2) == 0,
+        };
+        let mut member = Vec::new();
+        let mut encoder = GzipEncoder::with_header(&mut member, header, options)?;
+        encoder.write_all(&data)?;
+        encoder.finish()?;
+        if rng.below(3) == 0 {
+            let start = gzip::header_len(&member).unwrap();
+            member.splice(start..start, EMPTY_BLOCK);
+        }
+        stream.gzip.extend_from_slice(&member);
+        stream.data.extend_from_slice(&data);
+    }
+    Ok(stream)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Token of an LZ77 stream made by `token_stream`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestToken {
+    Literal(u8),
+    /// Copy of `length` bytes from `distance` bytes back, possibly overlapping.
+    Match {
+        distance: usize,
+        length: usize,
+    },
+}
+
+/// Data a token stream stands for, following `history`.
+pub fn expand(history: &[u8], tokens: &[TestToken]) -> Vec<u8> {
+    let mut data = history.to_vec();
+    for &token in tokens {
+        match token {
+            TestToken::Literal(value) => data.push(value),
+            TestToken::Match { distance, length } => {
+                for _ in 0..length {
+                    data.push(data[data.len() - distance]);
+                }
+            }
+        }
+    }
+    data.split_off(history.len())
+}
+
+/// Strategy of streams of up to `max_tokens` tokens following `history` bytes,
+/// whose matches reach at most `window` bytes back, from 3 to 258 bytes long and
+/// overlapping their source or not.
+#[cfg(feature = "proptest")]
+pub fn token_stream(
+    window: usize,
+    history: usize,
+    max_tokens: usize,
+) -> impl proptest::strategy::Strategy<Value = Vec<TestToken>> {
+    use proptest::prelude::*;
+
+    let token = prop_oneof![
+        any::<u8>().prop_map(|value| (value, None)),
+        (any::<u16>(), 3..=258usize).prop_map(|(seed, length)| (0, Some((seed, length)))),
+        // Matches of the maximal length, and right behind, e.g. runs.
+        (0..4u16, Just(258usize)).prop_map(|(seed, length)| (0, Some((seed, length)))),
+    ];
+    proptest::collection::vec(token, 0..=max_tokens).prop_map(move |tokens| {
+        let mut position = history;
+        tokens
+            .into_iter()
+            .map(|(value, copy)| {
+                let available = std::cmp::min(position, window);
+                let token = match copy {
+                    Some((seed, length)) if available > 0 => TestToken::Match {
+                        distance: 1 + seed as usize % available,
+                        length,
+                    },
+                    _ => TestToken::Literal(value),
+                };
+                position += match token {
+                    TestToken::Literal(_) => 1,
+                    TestToken::Match { length, .. } => length,
+                };
+                token
+            })
+            .collect()
+    })
+}
+
+/// Strategy of window states: a window size, from 1 byte to 32K, and the bytes
+/// written before, up to a little more than the window.
+#[cfg(feature = "proptest")]
+pub fn window_state() -> impl proptest::strategy::Strategy<Value = (usize, Vec<u8>)> {
+    use proptest::prelude::*;
+
+    prop_oneof![
+        1..=crate::WINDOW_SIZE,
+        Just(crate::WINDOW_SIZE),
+        1..=300usize
+    ]
+    .prop_flat_map(|window| {
+        let history = proptest::collection::vec(any::<u8>(), 0..=window + 100);
+        (Just(window), history)
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Class of the errors decoding a corrupted stream fails with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The magic bytes or the compression method are wrong.
+    Header,
+    /// A CRC or the length doesn't match.
+    Checksum,
+    /// The stream ends too soon.
+    Truncated,
+    /// The deflate stream is malformed.
+    Deflate,
+}
+
+impl ErrorClass {
+    /// Class of `err`, going by the messages of its chain.
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.chain().find_map(|err| {
+            let message = err.to_string();
+            let class = if message.contains("wrong id values")
+                || message.contains("unsupported compression method")
+            {
+                Self::Header
+            } else if message.contains("check failed") {
+                Self::Checksum
+            } else if message.contains("eof")
+                || message.contains("truncated")
+                || err.downcast_ref::<io::Error>().map(io::Error::kind)
+                    == Some(io::ErrorKind::UnexpectedEof)
+            {
+                Self::Truncated
+            } else if message.contains("block type")
+                || message.contains("huffman code")
+                || message.contains("length code")
+                || message.contains("distance code")
+            {
+                Self::Deflate
+            } else {
+                return None;
+            };
+            Some(class)
+        })
+    }
+}
+
+/// Targeted corruption of the first member of a valid stream, see `Mutation::apply`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    WrongId,
+    WrongMethod,
+    /// Cut the stream in the middle of the fixed header fields.
+    TruncateHeader,
+    /// Make the first block of the reserved type 3.
+    ReservedBlockType,
+    /// Replace the deflate stream with a block whose code length code has more
+    /// codes than fit.
+    OversubscribedTree,
+    /// Replace the deflate stream with a dynamic block coding the length symbol
+    /// 286, which has no meaning.
+    InvalidLengthCode,
+    FlipCrc32,
+    FlipSize,
+    /// Cut the stream in the middle of the footer.
+    TruncateFooter,
+}
+
+impl Mutation {
+    pub const ALL: [Self; 9] = [
+        Self::WrongId,
+        Self::WrongMethod,
+        Self::TruncateHeader,
+        Self::ReservedBlockType,
+        Self::OversubscribedTree,
+        Self::InvalidLengthCode,
+        Self::FlipCrc32,
+        Self::FlipSize,
+        Self::TruncateFooter,
+    ];
+
+    /// Class of the error the stream must fail with once mutated.
+    pub fn expected_error(self) -> ErrorClass {
+        match self {
+            Self::WrongId | Self::WrongMethod => ErrorClass::Header,
+            Self::TruncateHeader | Self::TruncateFooter => ErrorClass::Truncated,
+            Self::ReservedBlockType | Self::OversubscribedTree | Self::InvalidLengthCode => {
+                ErrorClass::Deflate
+            }
+            Self::FlipCrc32 | Self::FlipSize => ErrorClass::Checksum,
+        }
+    }
+
+    /// Corrupt the first member of the valid stream `gzip`.
+    pub fn apply(self, gzip: &[u8]) -> Result<Vec<u8>> {
+        let member = crate::members(gzip)?.swap_remove(0).compressed_range;
+        let (start, end) = (member.start as usize, member.end as usize);
+        let body = start + gzip::header_len(&gzip[start..]).unwrap();
+        let mut output = gzip.to_vec();
+        match self {
+            Self::WrongId => output[start + 1] ^= 0x01,
+            Self::WrongMethod => output[start + 2] = 7,
+            Self::TruncateHeader => output.truncate(start + 5),
+            Self::ReservedBlockType => output[body] |= 0b110,
+            Self::OversubscribedTree => {
+                let mut writer = BitWriter::new();
+                // Final dynamic block with the 19 code length codes all of length 1.
+                writer.write_bits(0b101, 3);
+                writer.write_bits(0, 10);
+                writer.write_bits(15, 4);
+                for _ in 0..19 {
+                    writer.write_bits(1, 3);
+                }
+                writer.align_to_byte();
+                output.splice(body..end - 8, writer.take_bytes());
+            }
+            Self::InvalidLengthCode => {
+                let mut writer = BitWriter::new();
+                // Final dynamic block with 287 literal/length codes, 1 distance code
+                // and the 18 code length codes up to symbol 1.
+                writer.write_bits(0b101, 3);
+                writer.write_bits(30, 5);
+                writer.write_bits(0, 5);
+                writer.write_bits(14, 4);
+                // Code length codes 1 and 18 of length 1, in the order 16, 17, 18,
+                // 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1.
+                for index in 0..18 {
+                    writer.write_bits(u32::from(index == 2 || index == 17), 3);
+                }
+                // Code length 1 for the end of block, symbol 286 and the distance
+                // symbol 0, and runs of zeros (symbol 18 with 7 extra bits) between.
+                let (one, zeros) = (0, 1);
+                for run in [138, 118] {
+                    writer.write_bits(zeros, 1);
+                    writer.write_bits(run - 11, 7);
+                }
+                writer.write_bits(one, 1);
+                writer.write_bits(zeros, 1);
+                writer.write_bits(29 - 11, 7);
+                writer.write_bits(one, 1);
+                writer.write_bits(one, 1);
+                // Symbol 286, coded 1 as it comes after the end of block.
+                writer.write_bits(1, 1);
+                writer.align_to_byte();
+                output.splice(body..end - 8, writer.take_bytes());
+            }
+            Self::FlipCrc32 => output[end - 8] ^= 0x01,
+            Self::FlipSize => output[end - 4] ^= 0x01,
+            Self::TruncateFooter => output.truncate(end - 4),
+        }
+        Ok(output)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpora() -> Result<()> {
+        for generate in [random, text, rle, skewed] {
+            let data = generate(100_000, 7);
+            assert_eq!(data.len(), 100_000);
+            assert_eq!(data, generate(100_000, 7));
+            round_trip_levels(&data)?;
+        }
+        assert!(round_trip(&rle(100_000, 7), CompressOptions::default())?.len() < 2000);
+        Ok(())
+    }
+
+    #[test]
+    fn valid_streams() -> Result<()> {
+        let mut member_count = 0;
+        for seed in 0..20 {
+            let stream = valid_stream(seed)?;
+            let mut output = Vec::new();
+            decompress(stream.gzip.as_slice(), &mut output)?;
+            assert_eq!(output, stream.data);
+            assert_eq!(stream.gzip, valid_stream(seed)?.gzip);
+            member_count += crate::members(stream.gzip.as_slice())?.len();
+        }
+        assert!(member_count > 40);
+        Ok(())
+    }
+
+    #[test]
+    fn mutations() -> Result<()> {
+        for seed in 0..5 {
+            let stream = valid_stream(seed)?;
+            for mutation in Mutation::ALL {
+                let corrupted = mutation.apply(&stream.gzip)?;
+                let err = decompress(corrupted.as_slice(), std::io::sink()).unwrap_err();
+                assert_eq!(
+                    ErrorClass::of(&err),
+                    Some(mutation.expected_error()),
+                    "{:?}: {:#}",
+                    mutation,
+                    err
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+#![forbid(unsafe_code)]
+
+use std::fmt;
+use std::io::{self, Read};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::Result;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Read of a `TimeoutReader` which got no data in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadTimeout {
+    /// Number of bytes read before the stall.
+    pub position: u64,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for ReadTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "read timed out after {:?} at offset {}",
+            self.timeout, self.position
+        )
+    }
+}
+
+impl std::error::Error for ReadTimeout {}
+
+impl ReadTimeout {
+    /// Find the timeout a decoding error was caused by, if any. It is carried by
+    /// an `io::Error`, as readers can only return these.
+    pub fn find(err: &anyhow::Error) -> Option<&ReadTimeout> {
+        err.chain()
+            .filter_map(|err| err.downcast_ref::<io::Error>())
+            .find_map(|err| err.get_ref()?.downcast_ref::<ReadTimeout>())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Source whose reads can time out, such as a socket.
+pub trait SetReadTimeout: Read {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl SetReadTimeout for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl SetReadTimeout for std::os::unix::net::UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Reader failing with a `ReadTimeout` when a read of the source gets no data
+/// within `timeout`, so that a decoder fed by a half-open connection doesn't hang.
+/// Wrap it in a `BufReader` to decode from it.
+pub struct TimeoutReader<R> {
+    inner: R,
+    timeout: Duration,
+    position: u64,
+}
+
+impl<R: SetReadTimeout> TimeoutReader<R> {
+    pub fn new(inner: R, timeout: Duration) -> Result<Self> {
+        inner.set_read_timeout(Some(timeout))?;
+        Ok(Self {
+            inner,
+            timeout,
+            position: 0,
+        })
+    }
+
+    /// Number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: SetReadTimeout> Read for TimeoutReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(size) => {
+                self.position += size as u64;
+                Ok(size)
+            }
+            // Unix reports a timeout as `WouldBlock`, Windows as `TimedOut`.
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                let timeout = ReadTimeout {
+                    position: self.position,
+                    timeout: self.timeout,
+                };
+                Err(io::Error::new(io::ErrorKind::TimedOut, timeout))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
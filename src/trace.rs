@@ -0,0 +1,316 @@
+#![forbid(unsafe_code)]
+
+//! Structured trace of the decoding of a deflate stream, to find out where and
+//! how a malformed stream goes wrong.
+
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+
+use crate::deflate::CompressionType;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Event of a decode trace. Bit offsets are counted from the start of the input
+/// of the decoder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// Start of a gzip member, at a byte offset of the input.
+    MemberStart {
+        offset: u64,
+    },
+    BlockHeader {
+        bit_offset: u64,
+        is_final: bool,
+        block_type: CompressionType,
+    },
+    StoredLength(usize),
+    /// Number of codes used by the literal/length and distance trees and their
+    /// longest code.
+    Trees {
+        lit_len_codes: usize,
+        lit_len_max_length: u8,
+        dist_codes: usize,
+        dist_max_length: u8,
+    },
+    Literal(u8),
+    Match {
+        length: usize,
+        distance: usize,
+    },
+    EndOfBlock,
+    Error {
+        bit_offset: u64,
+        message: String,
+    },
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemberStart { offset } => write!(f, "member at byte {}", offset),
+            Self::BlockHeader {
+                bit_offset,
+                is_final,
+                block_type,
+            } => write!(
+                f,
+                "block at bit {}: {:?}{}",
+                bit_offset,
+                block_type,
+                if *is_final { ", final" } else { "" }
+            ),
+            Self::StoredLength(length) => write!(f, "  stored {} bytes", length),
+            Self::Trees {
+                lit_len_codes,
+                lit_len_max_length,
+                dist_codes,
+                dist_max_length,
+            } => write!(
+                f,
+                "  trees: {} lit/len codes up to {} bits, {} distance codes up to {} bits",
+                lit_len_codes, lit_len_max_length, dist_codes, dist_max_length
+            ),
+            Self::Literal(value) => write!(f, "  literal {:#04x}", value),
+            Self::Match { length, distance } => {
+                write!(f, "  match length {} distance {}", length, distance)
+            }
+            Self::EndOfBlock => write!(f, "  end of block"),
+            Self::Error {
+                bit_offset,
+                message,
+            } => write!(f, "error at bit {}: {}", bit_offset, message),
+        }
+    }
+}
+
+impl TraceEvent {
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::MemberStart { offset } => {
+                format!(r#"{{"event":"member_start","offset":{}}}"#, offset)
+            }
+            Self::BlockHeader {
+                bit_offset,
+                is_final,
+                block_type,
+            } => format!(
+                r#"{{"event":"block_header","bit_offset":{},"is_final":{},"block_type":"{:?}"}}"#,
+                bit_offset, is_final, block_type
+            ),
+            Self::StoredLength(length) => {
+                format!(r#"{{"event":"stored_length","length":{}}}"#, length)
+            }
+            Self::Trees {
+                lit_len_codes,
+                lit_len_max_length,
+                dist_codes,
+                dist_max_length,
+            } => format!(
+                r#"{{"event":"trees","lit_len_codes":{},"lit_len_max_length":{},"dist_codes":{},"dist_max_length":{}}}"#,
+                lit_len_codes, lit_len_max_length, dist_codes, dist_max_length
+            ),
+            Self::Literal(value) => format!(r#"{{"event":"literal","value":{}}}"#, value),
+            Self::Match { length, distance } => format!(
+                r#"{{"event":"match","length":{},"distance":{}}}"#,
+                length, distance
+            ),
+            Self::EndOfBlock => r#"{"event":"end_of_block"}"#.to_string(),
+            Self::Error {
+                bit_offset,
+                message,
+            } => format!(
+                r#"{{"event":"error","bit_offset":{},"message":"{}"}}"#,
+                bit_offset,
+                escape_json(message)
+            ),
+        }
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Receiver of the events of a decode trace.
+pub trait TraceSink: Send {
+    fn record(&mut self, event: TraceEvent);
+}
+
+/// Sink keeping the events in memory. Clones share the same events, so a clone
+/// can be handed to the decoder and the trace read from the original.
+#[derive(Clone, Default)]
+pub struct TraceLog {
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl TraceLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// The events one per line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for event in self.events.lock().unwrap().iter() {
+            writeln!(text, "{}", event).unwrap();
+        }
+        text
+    }
+
+    /// The events as a JSON array.
+    pub fn to_json(&self) -> String {
+        let events = self.events.lock().unwrap();
+        let events = events.iter().map(TraceEvent::to_json).collect::<Vec<_>>();
+        format!("[{}]", events.join(","))
+    }
+}
+
+impl TraceSink for TraceLog {
+    fn record(&mut self, event: TraceEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+/// Sink logging the events with the `log` crate, under the `ripgzip` target:
+/// errors at error level, symbols at trace level and the rest at debug level.
+#[cfg(feature = "trace-log")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+#[cfg(feature = "trace-log")]
+impl TraceSink for LogSink {
+    fn record(&mut self, event: TraceEvent) {
+        let level = match event {
+            TraceEvent::Error { .. } => log::Level::Error,
+            TraceEvent::Literal(_) | TraceEvent::Match { .. } | TraceEvent::EndOfBlock => {
+                log::Level::Trace
+            }
+            _ => log::Level::Debug,
+        };
+        log::log!(target: "ripgzip", level, "{}", event.to_string().trim_start());
+    }
+}
+
+/// Sink logging the events with `defmt`, e.g. to an RTT console, at the same
+/// levels as `LogSink`.
+#[cfg(feature = "trace-defmt")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefmtSink;
+
+#[cfg(feature = "trace-defmt")]
+impl TraceSink for DefmtSink {
+    fn record(&mut self, event: TraceEvent) {
+        match event {
+            TraceEvent::MemberStart { offset } => defmt::debug!("member at byte {=u64}", offset),
+            TraceEvent::BlockHeader {
+                bit_offset,
+                is_final,
+                block_type,
+            } => defmt::debug!(
+                "block at bit {=u64}: {=str}, final {=bool}",
+                bit_offset,
+                match block_type {
+                    CompressionType::Uncompressed => "stored",
+                    CompressionType::FixedTree => "fixed",
+                    CompressionType::DynamicTree => "dynamic",
+                    CompressionType::Reserved => "reserved",
+                },
+                is_final
+            ),
+            TraceEvent::StoredLength(length) => defmt::debug!("stored {=usize} bytes", length),
+            TraceEvent::Trees {
+                lit_len_codes,
+                lit_len_max_length,
+                dist_codes,
+                dist_max_length,
+            } => defmt::debug!(
+                "trees: {=usize} lit/len codes up to {=u8} bits, {=usize} distance codes up to {=u8} bits",
+                lit_len_codes,
+                lit_len_max_length,
+                dist_codes,
+                dist_max_length
+            ),
+            TraceEvent::Literal(value) => defmt::trace!("literal {=u8:#04x}", value),
+            TraceEvent::Match { length, distance } => {
+                defmt::trace!("match length {=usize} distance {=usize}", length, distance)
+            }
+            TraceEvent::EndOfBlock => defmt::trace!("end of block"),
+            TraceEvent::Error {
+                bit_offset,
+                message,
+            } => defmt::error!("error at bit {=u64}: {=str}", bit_offset, message.as_str()),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Tracing state of an `InflateState`.
+#[derive(Clone)]
+pub(crate) struct Tracer {
+    sink: Arc<Mutex<dyn TraceSink>>,
+    /// Number of symbols recorded at the start of each block.
+    max_symbols: usize,
+    symbols_left: usize,
+    /// Bit offset of the next unread bit at the start of the current `inflate` call.
+    pub position: u64,
+    /// Length of the input and number of pending bits at the start of the call.
+    input: (usize, u8),
+}
+
+impl Tracer {
+    pub fn new<S: TraceSink + 'static>(sink: S, max_symbols: usize) -> Self {
+        Self {
+            sink: Arc::new(Mutex::new(sink)),
+            max_symbols,
+            symbols_left: 0,
+            position: 0,
+            input: (0, 0),
+        }
+    }
+
+    pub fn start_input(&mut self, len: usize, pending_bits: u8) {
+        self.input = (len, pending_bits);
+    }
+
+    /// Bit offset of the reader of the current call, given what is left of its
+    /// input and its pending bits.
+    pub fn bit_offset(&self, remaining: usize, pending_bits: u8) -> u64 {
+        let (len, start_pending_bits) = self.input;
+        self.position + ((len - remaining) * 8) as u64 + start_pending_bits as u64
+            - pending_bits as u64
+    }
+
+    pub fn record(&mut self, event: TraceEvent) {
+        match event {
+            TraceEvent::BlockHeader { .. } => self.symbols_left = self.max_symbols,
+            TraceEvent::Literal(_) | TraceEvent::Match { .. } | TraceEvent::EndOfBlock => {
+                if self.symbols_left == 0 {
+                    return;
+                }
+                self.symbols_left -= 1;
+            }
+            _ => {}
+        }
+        self.sink.lock().unwrap().record(event);
+    }
+
+    pub fn wants_symbols(&self) -> bool {
+        self.symbols_left > 0
+    }
+}
@@ -1,76 +1,232 @@
 #![forbid(unsafe_code)]
 
 use std::collections::VecDeque;
-use std::io::{self, Write};
+use std::io::{self, IoSlice, Seek, SeekFrom, Write};
 
 use anyhow::{ensure, Result};
-use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+
+use crate::checksum::{Checksum, DefaultChecksum};
 
 ////////////////////////////////////////////////////////////////////////////////
 
-const HISTORY_SIZE: usize = 32768;
-const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+pub const HISTORY_SIZE: usize = 32768;
+
+/// Matches repeating their source more times than this are materialized instead
+/// of being written as one slice per repetition.
+const MAX_MATCH_REPEATS: usize = 4;
+
+/// Most slices of a vectored write: the literals, then each repetition of the
+/// source of a match, in up to three slices.
+const MAX_PARTS: usize = 1 + 3 * MAX_MATCH_REPEATS;
 
-pub struct TrackingWriter<'a, T> {
+#[derive(Clone)]
+pub struct TrackingWriter<T, C = DefaultChecksum> {
     inner: T,
     history: VecDeque<u8>,
+    /// Size of the history, and largest distance of a match.
+    window_size: usize,
     byte_count: usize,
-    crc32: Digest<'a, u32>,
+    checksum: C,
+    /// Buffer of the bytes of a match, kept from one to the next.
+    scratch: Vec<u8>,
 }
 
-impl<'a, T: Write> Write for TrackingWriter<'a, T> {
+impl<T: Write, C: Checksum> Write for TrackingWriter<T, C> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let written = self.inner.write(buf)?;
-        self.crc32.update(&buf[..written]);
-        for &byte in buf[..written].iter() {
-            if self.history.len() >= HISTORY_SIZE {
-                self.history.pop_front();
-            }
-            self.history.push_back(byte);
+        self.track(&buf[..written]);
+        Ok(written)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let written = self.inner.write_vectored(bufs)?;
+        let mut remaining = written;
+        for buf in bufs {
+            let size = std::cmp::min(buf.len(), remaining);
+            self.track(&buf[..size]);
+            remaining -= size;
         }
-        self.byte_count += written;
         Ok(written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush().unwrap();
-        self.byte_count = 0;
-        self.history = VecDeque::with_capacity(HISTORY_SIZE);
-        self.crc32 = CRC.digest();
-        Ok(())
+        self.inner.flush()
+    }
+}
+
+/// Seeking to another position starts a new stream there, as `reset` does: the
+/// matches written after it can't refer to the data written before.
+impl<T: Write + Seek, C: Checksum> Seek for TrackingWriter<T, C> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let old = self.inner.stream_position()?;
+        let new = self.inner.seek(pos)?;
+        if new != old {
+            self.reset();
+        }
+        Ok(new)
     }
 }
 
-impl<'a, T: Write> TrackingWriter<'a, T> {
+impl<T: Write> TrackingWriter<T> {
     pub fn new(inner: T) -> Self {
+        Self::with_checksum(inner, DefaultChecksum::default())
+    }
+}
+
+impl<T: Write, C: Checksum> TrackingWriter<T, C> {
+    pub fn with_checksum(inner: T, checksum: C) -> Self {
         Self {
             byte_count: 0,
             history: VecDeque::with_capacity(HISTORY_SIZE),
-            crc32: CRC.digest(),
+            window_size: HISTORY_SIZE,
+            checksum,
+            inner,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Create a writer continuing a stream of `byte_count` bytes which ended with `history`.
+    pub fn resume(inner: T, checksum: C, history: &[u8], byte_count: usize) -> Self {
+        let history = &history[history.len().saturating_sub(HISTORY_SIZE)..];
+        let mut buf = VecDeque::with_capacity(HISTORY_SIZE);
+        buf.extend(history);
+        Self {
+            byte_count,
+            history: buf,
+            window_size: HISTORY_SIZE,
+            checksum,
             inner,
+            scratch: Vec::new(),
         }
     }
 
+    /// Forget everything written so far: the byte count, the history and the checksum
+    /// start over, as for a new stream. The inner writer is left untouched.
+    pub fn reset(&mut self) {
+        self.byte_count = 0;
+        self.history.clear();
+        self.checksum.reset();
+    }
+
+    /// Keep only the last `size` bytes written, at most `HISTORY_SIZE`, so that
+    /// matches can't reach further back. The memory of the rest of the history is
+    /// released.
+    pub fn set_window_size(&mut self, size: usize) {
+        assert!(
+            size > 0 && size <= HISTORY_SIZE,
+            "window size must be between 1 and {}",
+            HISTORY_SIZE
+        );
+        self.window_size = size;
+        let overflow = self.history.len().saturating_sub(size);
+        self.history.drain(..overflow);
+        self.history.shrink_to(size);
+    }
+
+    /// Fill the history with `dictionary`, as if it had been written just before,
+    /// without writing it or counting it in the byte count and the checksum.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        let dictionary = &dictionary[dictionary.len().saturating_sub(self.window_size)..];
+        self.history.clear();
+        self.history.extend(dictionary);
+    }
+
     /// Write a sequence of `len` bytes written `dist` bytes ago.
     pub fn write_previous(&mut self, dist: usize, len: usize) -> Result<()> {
-        ensure!(dist <= self.history.len(), "dist is out of border");
-        ensure!(dist < HISTORY_SIZE, "dist must be less {}", HISTORY_SIZE);
-        let mut result = Vec::with_capacity(len);
-
-        self.history.make_contiguous();
-        let start = self.history.len() - dist;
-        let data = self.history.as_slices().0;
-
-        let mut ind = start;
-        for _ in 0..len {
-            result.push(data[ind]);
-            ind = if ind == std::cmp::min(data.len(), start + len) - 1 {
-                start
-            } else {
-                ind + 1
+        self.write_literals_and_previous(&[], dist, len)
+    }
+
+    /// Write `literals` followed by a sequence of `len` bytes written `dist` bytes
+    /// before its start, with a single vectored write. The match is taken straight
+    /// from the history, even when it wraps around the history buffer.
+    pub fn write_literals_and_previous(
+        &mut self,
+        literals: &[u8],
+        dist: usize,
+        len: usize,
+    ) -> Result<()> {
+        ensure!(dist <= self.window_size, "dist is beyond the window");
+        ensure!(
+            dist > 0 && dist <= self.history.len() + literals.len(),
+            "dist is out of border"
+        );
+
+        // The `dist` bytes the match repeats.
+        let mut source: [&[u8]; 3] = [&[]; 3];
+        if dist > literals.len() {
+            let start = self.history.len() - (dist - literals.len());
+            let (front, back) = self.history.as_slices();
+            source = match start.checked_sub(front.len()) {
+                None => [&front[start..], back, literals],
+                Some(start) => [&back[start..], literals, &[]],
+            };
+        } else {
+            source[0] = &literals[literals.len() - dist..];
+        }
+
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        let materialized = len > dist * MAX_MATCH_REPEATS;
+        let mut parts: [&[u8]; MAX_PARTS] = [&[]; MAX_PARTS];
+        parts[0] = literals;
+        let mut count = 1;
+        if materialized {
+            materialize(&source, len, &mut scratch);
+            parts[1] = &scratch;
+            count = 2;
+        } else {
+            for part in repeat_slices(&source, len) {
+                parts[count] = part;
+                count += 1;
+            }
+        }
+        let parts = &parts[..count];
+
+        let total = literals.len() + len;
+        let mut slices = [IoSlice::new(&[]); MAX_PARTS];
+        for (slice, part) in slices.iter_mut().zip(parts) {
+            *slice = IoSlice::new(part);
+        }
+        let mut unwritten = &mut slices[..count];
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < total {
+            match self.inner.write_vectored(unwritten) {
+                Ok(0) => break,
+                Ok(size) => {
+                    written += size;
+                    IoSlice::advance_slices(&mut unwritten, size);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        let mut remaining = written;
+        for part in parts {
+            let size = std::cmp::min(part.len(), remaining);
+            self.checksum.update(&part[..size]);
+            remaining -= size;
+        }
+
+        // `source` borrows the history, so the written match is copied to `scratch` first.
+        let written_literals = std::cmp::min(written, literals.len());
+        let written_match = written - written_literals;
+        if !materialized {
+            for part in repeat_slices(&source, written_match) {
+                scratch.extend_from_slice(part);
             }
         }
-        ensure!(self.write(&result)? == len, "could not write fully");
+        self.push_history(&literals[..written_literals]);
+        self.push_history(&scratch[..written_match]);
+        self.scratch = scratch;
+        self.byte_count += written;
+
+        result?;
+        ensure!(written == total, "could not write fully");
         Ok(())
     }
 
@@ -78,16 +234,91 @@ impl<'a, T: Write> TrackingWriter<'a, T> {
         self.byte_count
     }
 
-    pub fn crc32(&mut self) -> u32 {
-        self.crc32.clone().finalize()
+    pub fn get_ref(&self) -> &T {
+        &self.inner
     }
+
+    /// Mutable access to the inner writer. Data written directly to it is not tracked.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Copy the history into a fixed-size buffer, oldest byte first.
+    /// Returns the buffer and the number of valid bytes at its beginning.
+    pub fn history_snapshot(&self) -> ([u8; HISTORY_SIZE], usize) {
+        let mut snapshot = [0; HISTORY_SIZE];
+        let (front, back) = self.history.as_slices();
+        snapshot[..front.len()].copy_from_slice(front);
+        snapshot[front.len()..self.history.len()].copy_from_slice(back);
+        (snapshot, self.history.len())
+    }
+
+    pub fn checksum(&self) -> u32 {
+        self.checksum.value()
+    }
+
+    fn track(&mut self, data: &[u8]) {
+        self.checksum.update(data);
+        self.push_history(data);
+        self.byte_count += data.len();
+    }
+
+    fn push_history(&mut self, data: &[u8]) {
+        let data = &data[data.len().saturating_sub(self.window_size)..];
+        let overflow = (self.history.len() + data.len()).saturating_sub(self.window_size);
+        self.history.drain(..overflow);
+        self.history.extend(data);
+    }
+}
+
+/// Slices of `len` bytes repeating the concatenation of `source`.
+fn repeat_slices<'a, 's>(
+    source: &'s [&'a [u8]],
+    mut len: usize,
+) -> impl Iterator<Item = &'a [u8]> + 's {
+    source
+        .iter()
+        .filter(|part| !part.is_empty())
+        .cycle()
+        .map_while(move |part| {
+            let size = std::cmp::min(part.len(), len);
+            len -= size;
+            (size > 0).then(|| &part[..size])
+        })
+}
+
+/// Append `len` bytes repeating the concatenation of `source` to the empty
+/// `output`.
+fn materialize(source: &[&[u8]], len: usize, output: &mut Vec<u8>) {
+    for part in source {
+        output.extend_from_slice(part);
+    }
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if crate::simd::repeat(output, len) {
+        return;
+    }
+    // Each copy doubles the repetitions.
+    while output.len() < len {
+        let size = std::cmp::min(output.len(), len - output.len());
+        output.extend_from_within(..size);
+    }
+    output.truncate(len);
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
-#[cfg(test)]
+#[cfg(all(test, feature = "crc32"))]
 mod tests {
     use super::*;
+    use crate::checksum::Crc32;
     use byteorder::WriteBytesExt;
 
     #[test]
@@ -106,7 +337,7 @@ mod tests {
 
         assert_eq!(writer.write(&[42, 124, 234, 27])?, 0);
         assert_eq!(writer.byte_count(), 10);
-        assert_eq!(writer.crc32(), 2992191065);
+        assert_eq!(writer.checksum(), 2992191065);
 
         Ok(())
     }
@@ -131,8 +362,101 @@ mod tests {
 
         assert!(writer.write_previous(1, 1).is_err());
         assert_eq!(writer.byte_count(), 512);
-        assert_eq!(writer.crc32(), 2733545866);
+        assert_eq!(writer.checksum(), 2733545866);
 
         Ok(())
     }
+
+    #[test]
+    fn write_literals_and_previous() -> Result<()> {
+        let mut expected = Vec::new();
+        let mut writer = TrackingWriter::new(Vec::new());
+        let mut seed = 12345u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as usize
+        };
+
+        for _ in 0..2000 {
+            let literals = (0..next() % 20).map(|_| next() as u8).collect::<Vec<_>>();
+            expected.extend_from_slice(&literals);
+            let dist = 1 + next() % std::cmp::min(expected.len(), HISTORY_SIZE - 1);
+            let len = 3 + next() % 256;
+            for _ in 0..len {
+                expected.push(expected[expected.len() - dist]);
+            }
+            writer.write_literals_and_previous(&literals, dist, len)?;
+        }
+
+        assert!(expected.len() > HISTORY_SIZE);
+        assert_eq!(writer.byte_count(), expected.len());
+        assert_eq!(writer.get_ref(), &expected);
+        let mut crc = Crc32::default();
+        crc.update(&expected);
+        assert_eq!(writer.checksum(), crc.value());
+        Ok(())
+    }
+
+    #[test]
+    fn accessors() -> Result<()> {
+        let mut writer = TrackingWriter::new(Vec::new());
+        writer.write_all(b"abc")?;
+        writer.get_mut().flush()?;
+        assert_eq!(writer.get_ref(), b"abc");
+
+        writer.reset();
+        assert_eq!(writer.byte_count(), 0);
+        assert_eq!(writer.history_len(), 0);
+        assert_eq!(writer.checksum(), 0);
+        assert!(writer.write_previous(1, 1).is_err());
+
+        writer.write_all(b"de")?;
+        writer.write_previous(2, 3)?;
+        assert_eq!(writer.byte_count(), 5);
+        assert_eq!(writer.into_inner(), b"abcdeded");
+        Ok(())
+    }
+
+    #[test]
+    fn seek() -> Result<()> {
+        let mut writer = TrackingWriter::new(io::Cursor::new(Vec::new()));
+        writer.write_all(b"abc")?;
+        assert_eq!(writer.stream_position()?, 3);
+        writer.write_previous(3, 3)?;
+        assert_eq!(writer.byte_count(), 6);
+
+        writer.seek(SeekFrom::Start(8))?;
+        assert_eq!(writer.byte_count(), 0);
+        assert!(writer.write_previous(1, 1).is_err());
+        writer.write_all(b"de")?;
+        writer.write_previous(2, 2)?;
+        let mut crc = Crc32::default();
+        crc.update(b"dede");
+        assert_eq!(writer.checksum(), crc.value());
+        assert_eq!(writer.into_inner().into_inner(), b"abcabc\0\0dede");
+        Ok(())
+    }
+
+    #[test]
+    fn window_size() -> Result<()> {
+        let mut writer = TrackingWriter::new(Vec::new());
+        writer.set_window_size(4);
+        writer.write_all(b"abcdef")?;
+        assert_eq!(writer.history_len(), 4);
+        writer.write_previous(4, 2)?;
+        assert!(writer.write_previous(5, 1).is_err());
+        assert_eq!(writer.into_inner(), b"abcdefcd");
+        Ok(())
+    }
+
+    #[test]
+    fn longest_distance() -> Result<()> {
+        let mut writer = TrackingWriter::new(Vec::new());
+        let data = (0..HISTORY_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        writer.write_all(&data)?;
+        writer.write_previous(HISTORY_SIZE, 3)?;
+        assert_eq!(&writer.get_ref()[HISTORY_SIZE..], &[0, 1, 2]);
+        assert!(writer.write_previous(HISTORY_SIZE + 1, 3).is_err());
+        Ok(())
+    }
 }
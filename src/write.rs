@@ -0,0 +1,86 @@
+#![forbid(unsafe_code)]
+
+//! Wrappers which are written to, as in `flate2::write`: they take the input
+//! through `Write` and write the output to the inner writer.
+
+#[cfg(feature = "zlib")]
+use anyhow::Result;
+#[cfg(feature = "zlib")]
+use std::io::{self, Write};
+
+#[cfg(feature = "zlib")]
+use crate::decoder::{into_io_error, DEFAULT_BUFFER_SIZE};
+#[cfg(feature = "zlib")]
+use crate::inflater::Inflater;
+
+pub use crate::encoder::GzipEncoder;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Gzip decoder taking compressed data through `Write` and writing the
+/// decompressed data to the inner writer, for pipelines which push compressed
+/// data, e.g. a download callback. Members follow one another as when reading.
+#[cfg(feature = "zlib")]
+pub struct GzipDecoder<W: Write> {
+    output: W,
+    inflater: Inflater,
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "zlib")]
+impl<W: Write> GzipDecoder<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            inflater: Inflater::new(31).unwrap(),
+            buffer: vec![0; DEFAULT_BUFFER_SIZE],
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Check that the last member is complete and return the output. Empty input
+    /// is accepted, as when reading.
+    pub fn finish(mut self) -> Result<W> {
+        if self.inflater.total_in() != 0 && !self.inflater.is_finished() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(feature = "zlib")]
+impl<W: Write> Write for GzipDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut consumed = 0;
+        loop {
+            if consumed < buf.len() && self.inflater.is_finished() {
+                self.inflater.reset();
+            }
+            let progress = self
+                .inflater
+                .inflate(&buf[consumed..], &mut self.buffer)
+                .map_err(into_io_error)?;
+            consumed += progress.consumed;
+            self.output.write_all(&self.buffer[..progress.produced])?;
+            // A full buffer may leave decoded data behind, and the end of a member
+            // may be followed by another one.
+            let more_output = progress.produced == self.buffer.len();
+            let stuck = progress.consumed == 0 && !self.inflater.is_finished();
+            if !more_output && (consumed == buf.len() || stuck) {
+                return Ok(consumed);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
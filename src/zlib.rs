@@ -0,0 +1,174 @@
+#![forbid(unsafe_code)]
+
+use anyhow::{bail, ensure, Result};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{self, BufRead, Read};
+
+use crate::checksum::{Adler32, Checksum};
+use crate::decoder::{into_io_error, DeflateDecoder};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const CM_DEFLATE: u8 = 8;
+
+/// Largest CINFO, for a 32K window.
+const MAX_CINFO: u8 = 7;
+
+const FDICT_OFFSET: u8 = 5;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy)]
+enum StreamState {
+    Header,
+    /// The header asks for the dictionary with this Adler-32.
+    NeedDictionary(u32),
+    Body,
+    Done,
+}
+
+/// Streaming decoder of a zlib stream.
+///
+/// If the stream was compressed with a preset dictionary, decoding fails with a
+/// "preset dictionary required" error until `set_dictionary` is called with it.
+#[derive(Clone)]
+pub struct ZlibDecoder<R> {
+    deflate: DeflateDecoder<R, Adler32>,
+    state: StreamState,
+    dictionary_id: Option<u32>,
+    window_size: Option<usize>,
+    strict_window: bool,
+}
+
+impl<R: BufRead> ZlibDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            deflate: DeflateDecoder::with_checksum(reader, Adler32::default()),
+            state: StreamState::Header,
+            dictionary_id: None,
+            window_size: None,
+            strict_window: false,
+        }
+    }
+
+    /// Reject matches reaching further back than the window size declared in the
+    /// header (CINFO), as zlib does, instead of allowing the full 32K. The window
+    /// is then only as large as declared.
+    pub fn set_strict_window(&mut self, strict: bool) {
+        self.strict_window = strict;
+    }
+
+    /// Window size declared in the header. Known once the header is read.
+    pub fn window_size(&self) -> Option<usize> {
+        self.window_size
+    }
+
+    /// Adler-32 of the preset dictionary the stream was compressed with (DICTID), if
+    /// any. Known once the header is read.
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary_id
+    }
+
+    /// Provide the preset dictionary the header asks for.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<()> {
+        let StreamState::NeedDictionary(id) = self.state else {
+            bail!("no dictionary is required");
+        };
+        let mut checksum = Adler32::default();
+        checksum.update(dictionary);
+        ensure!(checksum.value() == id, "incorrect dictionary");
+        self.deflate.set_dictionary(dictionary);
+        self.state = StreamState::Body;
+        Ok(())
+    }
+
+    /// Number of bytes consumed from the reader so far.
+    pub fn total_in(&self) -> u64 {
+        self.deflate.total_in()
+    }
+
+    /// Number of decompressed bytes read from the decoder so far.
+    pub fn total_out(&self) -> u64 {
+        self.deflate.total_out()
+    }
+
+    /// Return the underlying reader. Once the stream is over, it is positioned right
+    /// after the trailer.
+    pub fn into_inner(self) -> R {
+        self.deflate.into_inner()
+    }
+
+    fn fill_buffer(&mut self) -> Result<&[u8]> {
+        loop {
+            match self.state {
+                StreamState::Header => self.read_header()?,
+                StreamState::NeedDictionary(_) => bail!("preset dictionary required"),
+                StreamState::Body => {
+                    if !self.deflate.fill_buffer()?.is_empty() {
+                        break;
+                    }
+                    let checksum = self
+                        .deflate
+                        .borrow_reader_from_boundary()
+                        .read_u32::<BigEndian>()?;
+                    ensure!(checksum == self.deflate.checksum(), "adler32 check failed");
+                    self.state = StreamState::Done;
+                }
+                StreamState::Done => break,
+            }
+        }
+        self.deflate.fill_buffer()
+    }
+
+    fn read_header(&mut self) -> Result<()> {
+        let reader = self.deflate.borrow_reader_from_boundary();
+        let (window_size, has_dictionary) = parse_header([reader.read_u8()?, reader.read_u8()?])?;
+        self.window_size = Some(window_size);
+        self.state = if has_dictionary {
+            let id = reader.read_u32::<BigEndian>()?;
+            self.dictionary_id = Some(id);
+            StreamState::NeedDictionary(id)
+        } else {
+            StreamState::Body
+        };
+        if self.strict_window {
+            self.deflate.set_window_size(window_size);
+        }
+        Ok(())
+    }
+}
+
+/// Check the two bytes of a zlib header, CMF and FLG. Returns the window size and
+/// whether a dictionary id follows.
+pub(crate) fn parse_header(header: [u8; 2]) -> Result<(usize, bool)> {
+    let [cmf, flg] = header;
+    ensure!(
+        u16::from_be_bytes(header).is_multiple_of(31),
+        "header check failed"
+    );
+    ensure!(cmf & 0x0f == CM_DEFLATE, "unsupported compression method");
+    ensure!(cmf >> 4 <= MAX_CINFO, "invalid window size");
+    Ok((1 << ((cmf >> 4) + 8), (flg >> FDICT_OFFSET) & 1 != 0))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl<R: BufRead> Read for ZlibDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = std::cmp::min(data.len(), buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+        Ok(size)
+    }
+}
+
+impl<R: BufRead> BufRead for ZlibDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill_buffer().map_err(into_io_error)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.deflate.consume_buffer(amount)
+    }
+}
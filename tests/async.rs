@@ -0,0 +1,65 @@
+#![cfg(feature = "async")]
+
+use tokio::io::AsyncReadExt;
+
+use ripgzip::{copy_decode_async, decompress, AsyncGzipDecoder};
+
+fn expected(compressed: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    decompress(compressed, &mut output).unwrap();
+    output
+}
+
+#[tokio::test]
+async fn read() {
+    let compressed = include_bytes!("../data/ok/09-concat.gz");
+    let mut decoder = AsyncGzipDecoder::new(&compressed[..]);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).await.unwrap();
+    assert_eq!(output, expected(compressed));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn copy_into_writer() {
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    let (writer, mut reader) = tokio::io::duplex(1000);
+    let reading = tokio::spawn(async move {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).await.unwrap();
+        output
+    });
+    let size = copy_decode_async(&compressed[..], writer).await.unwrap();
+    let output = reading.await.unwrap();
+    assert_eq!(size, output.len() as u64);
+    assert_eq!(output, expected(compressed));
+}
+
+#[tokio::test]
+async fn errors() {
+    for compressed in [
+        &include_bytes!("../data/corrupted/01-bad-crc32.gz")[..],
+        &include_bytes!("../data/corrupted/02-unexpected-eof.gz")[..],
+    ] {
+        assert!(copy_decode_async(compressed, tokio::io::sink())
+            .await
+            .is_err());
+    }
+}
+
+#[tokio::test]
+async fn stream() {
+    use futures_core::Stream;
+
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    let mut decoder = AsyncGzipDecoder::new(&compressed[..]);
+    let mut output = vec![0; 10];
+    decoder.read_exact(&mut output).await.unwrap();
+
+    let mut stream = std::pin::pin!(decoder.into_stream());
+    while let Some(chunk) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+        let chunk = chunk.unwrap();
+        assert!(!chunk.is_empty());
+        output.extend_from_slice(&chunk);
+    }
+    assert_eq!(output, expected(compressed));
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "gzip")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use ripgzip::{decompress, decompress_many, BatchOptions};
+
+#[test]
+fn decompress_many_files() {
+    let dir = std::env::temp_dir().join(format!("ripgzip-batch-{}", std::process::id()));
+    let output_dir = dir.join("out");
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let mut inputs = Vec::new();
+    for name in ["00-Cargo.toml.gz", "01-page.gz", "09-concat.gz"] {
+        let path = dir.join(name);
+        fs::copy(PathBuf::from("data/ok").join(name), &path).unwrap();
+        inputs.push(path);
+    }
+    let corrupted = dir.join("01-bad-crc32.gz");
+    fs::copy("data/corrupted/01-bad-crc32.gz", &corrupted).unwrap();
+    inputs.insert(1, corrupted);
+    inputs.push(dir.join("missing.gz"));
+    inputs.push(PathBuf::from("data/ok/01-page"));
+
+    let options = BatchOptions {
+        jobs: 2,
+        output_dir: Some(output_dir.clone()),
+        ..Default::default()
+    };
+    let results = decompress_many(inputs.clone(), &options);
+    assert_eq!(results.len(), inputs.len());
+    for (result, input) in results.iter().zip(&inputs) {
+        assert_eq!(&result.input, input);
+    }
+
+    for i in [0, 2, 3] {
+        let stats = results[i].result.as_ref().unwrap();
+        let compressed = fs::read(&inputs[i]).unwrap();
+        let mut expected = Vec::new();
+        decompress(compressed.as_slice(), &mut expected).unwrap();
+        assert_eq!(stats.compressed_size, compressed.len() as u64);
+        assert_eq!(stats.decompressed_size, expected.len() as u64);
+        assert_eq!(stats.output.parent(), Some(output_dir.as_path()));
+        assert_eq!(fs::read(&stats.output).unwrap(), expected);
+    }
+    for i in [1, 4, 5] {
+        assert!(results[i].result.is_err());
+    }
+    assert!(!output_dir.join("01-bad-crc32").exists());
+    assert!(format!("{:#}", results[5].result.as_ref().unwrap_err()).contains("unknown suffix"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
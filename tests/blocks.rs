@@ -0,0 +1,154 @@
+#![cfg(feature = "gzip")]
+
+use ripgzip::deflate::{BlockHeaders, CompressionType, SymbolCode, MAX_CODE_LENGTH};
+use ripgzip::{compress, decompress, CompressOptions};
+
+fn decompressed_len(compressed: &[u8]) -> u64 {
+    let mut output = Vec::new();
+    decompress(compressed, &mut output).unwrap();
+    output.len() as u64
+}
+
+#[test]
+fn block_headers() {
+    let compressed = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+    // No optional header fields: the deflate stream starts right after the 10 bytes.
+    let mut blocks = BlockHeaders::new(&compressed[10..]);
+    let mut position = 0;
+    let mut size = 0;
+    let mut count = 0;
+    for block in &mut blocks {
+        let block = block.unwrap();
+        assert_eq!(block.start_bit, position);
+        assert!(block.stats.is_none());
+        assert_eq!(block.header.compression_type, CompressionType::DynamicTree);
+        position = block.end_bit;
+        size += block.size;
+        count += 1;
+    }
+    assert!(count > 10);
+    assert_eq!(size, decompressed_len(compressed));
+    assert_eq!(position.div_ceil(8), blocks.bit_position().div_ceil(8));
+    assert_eq!(blocks.into_inner().len(), 8);
+}
+
+#[test]
+fn stored_and_truncated() {
+    let data = vec![7; 100_000];
+    let options = CompressOptions {
+        level: 0,
+        ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    compress(data.as_slice(), &mut compressed, options).unwrap();
+    let blocks = BlockHeaders::new(&compressed[10..])
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    assert!(blocks
+        .iter()
+        .all(|block| block.header.compression_type == CompressionType::Uncompressed));
+    assert!(blocks.last().unwrap().header.is_final);
+    assert_eq!(blocks.iter().map(|block| block.size).sum::<u64>(), 100_000);
+
+    let truncated = &compressed[10..compressed.len() - 1000];
+    let mut blocks = BlockHeaders::new(truncated);
+    assert!(blocks.by_ref().any(|block| block.is_err()));
+    assert!(blocks.next().is_none());
+}
+
+#[test]
+fn stats() {
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    let mut blocks = BlockHeaders::new(&compressed[10..]);
+    blocks.record_stats();
+    let mut size = 0;
+    for block in blocks {
+        let block = block.unwrap();
+        let stats = block.stats.unwrap();
+        assert_eq!(stats.literals + stats.match_bytes, block.size);
+        assert!(stats.matches > 0 && stats.literals > 0);
+        assert!((3..=258).contains(&stats.max_match_length));
+        assert!((3.0..=stats.max_match_length as f64).contains(&stats.average_match_length()));
+        assert!((257..=286).contains(&stats.lit_len_codes));
+        assert!((1..=30).contains(&stats.distance_codes));
+        assert_eq!(stats.distance_histogram.iter().sum::<u64>(), stats.matches);
+        assert!(
+            stats
+                .distance_histogram
+                .iter()
+                .filter(|&&count| count > 0)
+                .count()
+                > 5
+        );
+        size += block.size;
+    }
+    assert_eq!(size, decompressed_len(compressed));
+}
+
+#[test]
+fn trees() {
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    let mut blocks = BlockHeaders::new(&compressed[10..]);
+    blocks.record_trees();
+    for block in blocks {
+        let trees = block.unwrap().trees.unwrap();
+        assert!(trees.lit_len.iter().any(|code| code.symbol == 256));
+        for codes in [&trees.lit_len, &trees.distance] {
+            // Complete codes: the Kraft sum is one.
+            let kraft = codes
+                .iter()
+                .map(|code| 1u64 << (15 - code.length))
+                .sum::<u64>();
+            assert_eq!(kraft, 1 << 15);
+            for pair in codes.windows(2) {
+                if pair[0].length == pair[1].length {
+                    assert_eq!(pair[0].code + 1, pair[1].code);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn codes_from_frequencies() {
+    let mut frequencies = vec![0u32; 286];
+    for (symbol, freq) in frequencies.iter_mut().enumerate().step_by(3) {
+        *freq = 1 + (symbol as u32 * 7919) % 1000;
+    }
+    // Fibonacci frequencies would make the unbounded code deeper than allowed.
+    let (mut a, mut b) = (1, 1);
+    for freq in frequencies.iter_mut().skip(1).step_by(3).take(30) {
+        *freq = a;
+        (a, b) = (b, a + b);
+    }
+
+    let codes = SymbolCode::from_frequencies(&frequencies);
+    let used = frequencies.iter().filter(|&&freq| freq > 0).count();
+    assert_eq!(codes.len(), used);
+    assert!(codes
+        .iter()
+        .all(|code| frequencies[code.symbol as usize] > 0));
+    assert!(codes.iter().all(|code| code.length <= MAX_CODE_LENGTH));
+    let kraft = codes
+        .iter()
+        .map(|code| 1u64 << (15 - code.length))
+        .sum::<u64>();
+    assert_eq!(kraft, 1 << 15);
+    // No code is a prefix of another.
+    for x in &codes {
+        for y in codes.iter().filter(|y| y.length > x.length) {
+            assert_ne!(y.code >> (y.length - x.length), x.code);
+        }
+    }
+
+    assert!(SymbolCode::from_frequencies(&[0, 0]).is_empty());
+    let single = SymbolCode::from_frequencies(&[0, 5]);
+    assert_eq!(
+        single,
+        [SymbolCode {
+            symbol: 1,
+            length: 1,
+            code: 0
+        }]
+    );
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "stream")]
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use bytes::Bytes;
+use futures_core::Stream;
+
+use ripgzip::{decompress, GzipBodyDecoder};
+
+struct Chunks(VecDeque<io::Result<Bytes>>);
+
+impl Stream for Chunks {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.0.pop_front())
+    }
+}
+
+/// Split `data` byte by byte at first, through the header, then into chunks of `size`.
+fn chunks(data: &[u8], size: usize) -> Chunks {
+    let (head, tail) = data.split_at(std::cmp::min(data.len(), 64));
+    Chunks(
+        head.chunks(1)
+            .chain(tail.chunks(size))
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect(),
+    )
+}
+
+fn decode<S: Stream<Item = io::Result<Bytes>> + Unpin>(body: S) -> io::Result<Vec<u8>> {
+    let mut decoder = GzipBodyDecoder::new(body);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut output = Vec::new();
+    loop {
+        match Pin::new(&mut decoder).poll_next(&mut cx) {
+            Poll::Ready(Some(chunk)) => output.extend_from_slice(&chunk?),
+            Poll::Ready(None) => return Ok(output),
+            Poll::Pending => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn chunk_boundaries() {
+    for compressed in [
+        &include_bytes!("../data/ok/00-Cargo.toml.gz")[..],
+        include_bytes!("../data/ok/09-concat.gz"),
+        include_bytes!("../data/ok/10-header-crc16.gz"),
+    ] {
+        let mut expected = Vec::new();
+        decompress(compressed, &mut expected).unwrap();
+        for size in [1000, 1 << 16] {
+            assert_eq!(decode(chunks(compressed, size)).unwrap(), expected);
+        }
+    }
+    assert!(decode(chunks(b"", 1)).unwrap().is_empty());
+}
+
+#[test]
+fn chunk_size() {
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    let mut expected = Vec::new();
+    decompress(&compressed[..], &mut expected).unwrap();
+
+    let mut decoder = GzipBodyDecoder::new(chunks(compressed, 1 << 16));
+    decoder.set_chunk_size(4096);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut output = Vec::new();
+    while let Poll::Ready(Some(chunk)) = Pin::new(&mut decoder).poll_next(&mut cx) {
+        let chunk = chunk.unwrap();
+        assert!(chunk.len() < 4096 + 258);
+        output.extend_from_slice(&chunk);
+    }
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn errors() {
+    for (compressed, message) in [
+        (
+            &include_bytes!("../data/corrupted/01-bad-crc32.gz")[..],
+            "crc32 check failed",
+        ),
+        (
+            include_bytes!("../data/corrupted/00-bad-length.gz"),
+            "length check failed",
+        ),
+        (
+            include_bytes!("../data/corrupted/03-wrong-id.gz"),
+            "wrong id values",
+        ),
+    ] {
+        let err = decode(chunks(compressed, 100)).unwrap_err();
+        assert!(err.to_string().contains(message), "{}", err);
+    }
+
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    let err = decode(chunks(&compressed[..compressed.len() - 4], 100)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    let mut body = chunks(compressed, 100);
+    body.0.insert(3, Err(io::Error::other("connection reset")));
+    let err = decode(body).unwrap_err();
+    assert_eq!(err.to_string(), "connection reset");
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "gzip")]
+
+use ripgzip::{decompress_bounded, Bounded, DecompressStats};
+
+#[test]
+fn bounded() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut output = Vec::new();
+    let result = decompress_bounded(data, &mut output, 1 << 16).unwrap();
+    assert_eq!(result, Bounded::Truncated { written: 1 << 16 });
+    assert_eq!(output, expected[..1 << 16]);
+
+    // A cap of exactly the size is not reached.
+    for max_out in [expected.len() as u64, u64::MAX] {
+        let mut output = Vec::new();
+        let result = decompress_bounded(data, &mut output, max_out).unwrap();
+        let stats = DecompressStats {
+            compressed_size: data.len() as u64,
+            decompressed_size: expected.len() as u64,
+        };
+        assert_eq!(result, Bounded::Complete(stats));
+        assert_eq!(output, expected);
+    }
+
+    let corrupted: &[u8] = include_bytes!("../data/corrupted/01-bad-crc32.gz");
+    assert!(decompress_bounded(corrupted, &mut Vec::new(), u64::MAX).is_err());
+}
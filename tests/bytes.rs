@@ -0,0 +1,32 @@
+#![cfg(all(feature = "gzip", feature = "bytes"))]
+
+use bytes::{Buf, Bytes};
+
+use ripgzip::{decompress_bytes, decompress_chunks};
+
+#[test]
+fn decompress() {
+    let data = Bytes::from_static(include_bytes!("../data/ok/09-concat.gz"));
+    let mut expected = Vec::new();
+    ripgzip::decompress(data.as_ref(), &mut expected).unwrap();
+
+    assert_eq!(decompress_bytes(data.clone()).unwrap(), expected);
+
+    // Inputs split into several buffers are read through.
+    let (first, second) = data.split_at(1000);
+    let chained = Bytes::copy_from_slice(first).chain(Bytes::copy_from_slice(second));
+    let chunks = decompress_chunks(chained)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| chunk.len() < (1 << 16) + 258));
+    assert_eq!(chunks.concat(), expected);
+}
+
+#[test]
+fn chunk_errors() {
+    let data = Bytes::from_static(include_bytes!("../data/corrupted/01-bad-crc32.gz"));
+    let results = decompress_chunks(data).collect::<Vec<_>>();
+    assert!(results.last().unwrap().is_err());
+    assert!(results[..results.len() - 1].iter().all(Result::is_ok));
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "gzip")]
+
+use anyhow::bail;
+use ripgzip::{decompress, decompress_with};
+
+#[test]
+fn same_output() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut expected = Vec::new();
+    decompress(data, &mut expected).unwrap();
+
+    let mut output = Vec::new();
+    decompress_with(data, |chunk| {
+        assert!(!chunk.is_empty());
+        output.extend_from_slice(chunk);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn callback_error() {
+    let data: &[u8] = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+    let mut calls = 0;
+    let err = decompress_with(data, |_| {
+        calls += 1;
+        bail!("receiver is gone")
+    })
+    .unwrap_err();
+    assert_eq!(err.to_string(), "receiver is gone");
+    assert_eq!(calls, 1);
+}
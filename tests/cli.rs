@@ -0,0 +1,313 @@
+#![cfg(feature = "gzip")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ripgzip-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn ripgzip(args: &[&str], dir: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn page() -> Vec<u8> {
+    let mut data = Vec::new();
+    ripgzip::decompress(&include_bytes!("../data/ok/01-page.gz")[..], &mut data).unwrap();
+    data
+}
+
+#[test]
+fn restore_name() {
+    let dir = temp_dir("name");
+    // The page member, with a name and a modification time added to its header.
+    let mut compressed = include_bytes!("../data/ok/01-page.gz").to_vec();
+    compressed[3] |= 8;
+    compressed[4..8].copy_from_slice(&1_000_000_000u32.to_le_bytes());
+    compressed.splice(10..10, b"../page.html\0".iter().copied());
+    fs::write(dir.join("x.gz"), &compressed).unwrap();
+
+    ripgzip(&["-d", "x.gz"], &dir);
+    assert_eq!(fs::read(dir.join("x")).unwrap(), page());
+    fs::remove_file(dir.join("x")).unwrap();
+
+    ripgzip(&["-d", "-N", "-n", "x.gz"], &dir);
+    assert!(dir.join("x").exists());
+
+    ripgzip(&["-d", "-N", "x.gz"], &dir);
+    let output = dir.join("page.html");
+    assert_eq!(fs::read(&output).unwrap(), page());
+    let mtime = fs::metadata(&output).unwrap().modified().unwrap();
+    assert_eq!(
+        mtime,
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+    );
+
+    // A name in ISO 8859-1 is restored byte for byte, as gzip does.
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        compressed.splice(10..23, b"caf\xe9\0".iter().copied());
+        fs::write(dir.join("y.gz"), &compressed).unwrap();
+        ripgzip(&["-d", "-N", "y.gz"], &dir);
+        let output = dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+        assert_eq!(fs::read(output).unwrap(), page());
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuse_overwrite() {
+    let dir = temp_dir("overwrite");
+    let fails = |args: &[&str]| {
+        let status = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+            .args(args)
+            .current_dir(&dir)
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        !status.success()
+    };
+    // The page member, with its own file name stored in the header.
+    let mut compressed = include_bytes!("../data/ok/01-page.gz").to_vec();
+    compressed[3] |= 8;
+    compressed.splice(10..10, b"page.gz\0".iter().copied());
+    fs::write(dir.join("page.gz"), &compressed).unwrap();
+
+    // The stored name is the input, which is kept even with -f.
+    assert!(fails(&["-d", "-N", "page.gz"]));
+    assert!(fails(&["-d", "-N", "-f", "page.gz"]));
+    assert_eq!(fs::read(dir.join("page.gz")).unwrap(), compressed);
+
+    // Other files are only written over with -f.
+    fs::write(dir.join("page"), b"old").unwrap();
+    assert!(fails(&["-d", "page.gz"]));
+    assert_eq!(fs::read(dir.join("page")).unwrap(), b"old");
+    ripgzip(&["-d", "-f", "page.gz"], &dir);
+    assert_eq!(fs::read(dir.join("page")).unwrap(), page());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("mode");
+    let input = dir.join("page.gz");
+    fs::write(&input, include_bytes!("../data/ok/01-page.gz")).unwrap();
+    fs::set_permissions(&input, fs::Permissions::from_mode(0o640)).unwrap();
+
+    ripgzip(&["-d", "page.gz"], &dir);
+    let mode = fs::metadata(dir.join("page")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_mode() {
+    let dir = temp_dir("test");
+    fs::write(dir.join("ok.gz"), include_bytes!("../data/ok/09-concat.gz")).unwrap();
+    fs::write(
+        dir.join("bad.gz"),
+        include_bytes!("../data/corrupted/01-bad-crc32.gz"),
+    )
+    .unwrap();
+
+    ripgzip(&["-t", "ok.gz"], &dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+        .args(["-t", "ok.gz", "bad.gz"])
+        .current_dir(&dir)
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    // Nothing is written out.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn force_passthrough() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let decompress = |input: &[u8], args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        output.status.success().then_some(output.stdout)
+    };
+
+    let plain = b"plain text\n";
+    assert_eq!(decompress(plain, &["-d", "-f"]).unwrap(), plain);
+    assert!(decompress(plain, &["-d"]).is_none());
+    let compressed = include_bytes!("../data/ok/01-page.gz");
+    assert_eq!(decompress(compressed, &["-d", "-f"]).unwrap(), page());
+}
+
+#[test]
+fn zero_rate_limit() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+        .args(["-d", "--rate-limit", "0"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    // Rejected as an invalid value, not a panic.
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--rate-limit"));
+}
+
+#[test]
+fn pipeline_conflicts() {
+    // The pipeline neither copies plain input nor throttles, so it is not silently
+    // left out.
+    for args in [
+        ["-d", "--pipeline", "-f"],
+        ["-d", "--pipeline", "--rate-limit=5"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    }
+}
+
+#[test]
+fn compress() {
+    let dir = temp_dir("compress");
+    fs::write(dir.join("page.html"), page()).unwrap();
+
+    ripgzip(&["-9", "page.html"], &dir);
+    assert!(!dir.join("page.html").exists());
+    let compressed = fs::read(dir.join("page.html.gz")).unwrap();
+    let mut decoder = ripgzip::GzipDecoder::new(compressed.as_slice());
+    let mut output = Vec::new();
+    std::io::Read::read_to_end(&mut decoder, &mut output).unwrap();
+    assert_eq!(output, page());
+    assert_eq!(decoder.header().unwrap().name.as_deref(), Some("page.html"));
+    assert_eq!(compressed[8], 2);
+
+    // An existing output is only written over with -f.
+    fs::write(dir.join("page.html"), page()).unwrap();
+    fs::write(dir.join("page.html.gz"), b"old").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+        .args(["-k", "page.html"])
+        .current_dir(&dir)
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    assert_eq!(fs::read(dir.join("page.html.gz")).unwrap(), b"old");
+    ripgzip(&["-f", "page.html"], &dir);
+    let mut output = Vec::new();
+    let compressed = fs::read(dir.join("page.html.gz")).unwrap();
+    ripgzip::decompress(compressed.as_slice(), &mut output).unwrap();
+    assert_eq!(output, page());
+
+    // Compressed files are left alone, and -k keeps the input.
+    ripgzip(&["-d", "-N", "page.html.gz"], &dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+        .args(["page.html.gz"])
+        .current_dir(&dir)
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    fs::remove_file(dir.join("page.html.gz")).unwrap();
+    ripgzip(&["-k", "-n", "-1", "page.html"], &dir);
+    assert!(dir.join("page.html").exists());
+    let compressed = fs::read(dir.join("page.html.gz")).unwrap();
+    assert_eq!(compressed[3], 0);
+    assert_eq!(compressed[8], 4);
+
+    // The system gzip reads the output, if there is one.
+    if let Ok(output) = Command::new("gzip")
+        .args(["-dc", "page.html.gz"])
+        .current_dir(&dir)
+        .output()
+    {
+        assert!(output.status.success());
+        assert_eq!(output.stdout, page());
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json() {
+    let dir = temp_dir("json");
+    let concat = [
+        &include_bytes!("../data/ok/00-Cargo.toml.gz")[..],
+        include_bytes!("../data/ok/01-page.gz"),
+    ]
+    .concat();
+    fs::write(dir.join("ok.gz"), &concat).unwrap();
+    fs::write(
+        dir.join("bad.gz"),
+        include_bytes!("../data/corrupted/01-bad-crc32.gz"),
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_ripgzip"))
+            .args(args)
+            .current_dir(&dir)
+            .stderr(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8(output.stdout).unwrap(),
+        )
+    };
+
+    let members = ripgzip::members(concat.as_slice()).unwrap();
+    let (success, listing) = run(&["-l", "--json", "ok.gz"]);
+    assert!(success);
+    let records = listing.lines().collect::<Vec<_>>();
+    assert_eq!(records.len(), members.len());
+    for (index, (record, member)) in records.iter().zip(&members).enumerate() {
+        assert!(record.starts_with(&format!(r#"{{"file":"ok.gz","member":{},"#, index)));
+        assert!(record.contains(&format!(
+            r#""crc32":{},"size":{},"#,
+            member.crc32, member.size
+        )));
+        assert!(record.ends_with('}'));
+    }
+
+    let (success, listing) = run(&["-l", "ok.gz"]);
+    assert!(success);
+    assert_eq!(listing.lines().count(), members.len() + 1);
+
+    let (success, results) = run(&["-t", "--json", "ok.gz", "bad.gz"]);
+    assert!(!success);
+    let results = results.lines().collect::<Vec<_>>();
+    assert_eq!(results[0], r#"{"file":"ok.gz","ok":true}"#);
+    assert!(results[1].starts_with(r#"{"file":"bad.gz","ok":false,"error":""#));
+    assert_eq!(results.len(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,432 @@
+#![cfg(feature = "gzip")]
+
+use std::io::{BufRead, BufReader, Read};
+use std::num::NonZeroU64;
+
+use ripgzip::{Checkpoint, DecompressOptions, GzipDecoder, BGZF_EOF, WINDOW_SIZE};
+
+#[test]
+fn window_snapshot() {
+    let data: &[u8] = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+    let mut decoder = GzipDecoder::new(data);
+    let mut output = Vec::new();
+    let mut buf = [0; 4096];
+    let mut boundaries = 0;
+
+    loop {
+        let size = decoder.read(&mut buf).unwrap();
+        if size == 0 {
+            break;
+        }
+        output.extend_from_slice(&buf[..size]);
+        if decoder.is_at_block_boundary() {
+            boundaries += 1;
+            let len = decoder.window_len();
+            assert_eq!(len, output.len().min(WINDOW_SIZE));
+            assert_eq!(
+                &decoder.window_snapshot()[..len],
+                &output[output.len() - len..]
+            );
+        }
+    }
+
+    assert!(boundaries > 1);
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn checkpoint_restore() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut input = data;
+    let mut output = Vec::new();
+    let mut buf = vec![0; 1 << 17];
+    let mut restores = 0;
+    let mut checkpoint = GzipDecoder::new(&mut input).checkpoint().unwrap();
+
+    loop {
+        assert_eq!(checkpoint.input_offset(), (data.len() - input.len()) as u64);
+        let mut decoder = GzipDecoder::restore(&mut input, &checkpoint).unwrap();
+        let size = decoder.read(&mut buf).unwrap();
+        if size == 0 {
+            break;
+        }
+        output.extend_from_slice(&buf[..size]);
+
+        let mut serialized = Vec::new();
+        decoder
+            .checkpoint()
+            .unwrap()
+            .write_to(&mut serialized)
+            .unwrap();
+        checkpoint = Checkpoint::read_from(serialized.as_slice()).unwrap();
+        restores += 1;
+    }
+
+    assert!(restores > 10);
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn clone_mid_stream() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut decoder = GzipDecoder::new(data);
+    let mut buf = [0; 50000];
+    decoder.read_exact(&mut buf).unwrap();
+
+    let mut speculative = decoder.clone();
+    let mut first = Vec::new();
+    speculative.read_to_end(&mut first).unwrap();
+    drop(speculative);
+
+    let mut second = Vec::new();
+    decoder.read_to_end(&mut second).unwrap();
+    assert!(!first.is_empty());
+    assert_eq!(first, second);
+}
+
+#[test]
+fn into_inner_after_member() {
+    let member: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut data = member.to_vec();
+    data.extend_from_slice(b"NEXT MESSAGE");
+
+    let options = DecompressOptions {
+        multi_member: false,
+        ..Default::default()
+    };
+    let mut decoder = GzipDecoder::with_options(data.as_slice(), options);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+
+    assert_eq!(decoder.total_in(), member.len() as u64);
+    assert_eq!(decoder.total_out(), output.len() as u64);
+    assert_eq!(decoder.into_inner(), b"NEXT MESSAGE");
+
+    let mut expected = Vec::new();
+    ripgzip::decompress(member, &mut expected).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn position() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut decoder = GzipDecoder::new(data);
+    let mut buf = [0; 10000];
+    let mut last = 0;
+
+    while decoder.read(&mut buf).unwrap() > 0 {
+        assert!(decoder.bit_position() >= last);
+        assert!(decoder.position() <= decoder.total_in());
+        assert!(decoder.total_in() * 8 - decoder.bit_position() < 16);
+        last = decoder.bit_position();
+    }
+
+    assert_eq!(decoder.position(), data.len() as u64);
+    assert_eq!(decoder.bit_position(), data.len() as u64 * 8);
+}
+
+#[test]
+fn buf_read() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut decoder = GzipDecoder::new(data);
+    let mut line = Vec::new();
+    let mut lines = 0;
+    let mut total = 0;
+    while decoder.read_until(b'\n', &mut line).unwrap() > 0 {
+        assert!(line.ends_with(b"\n") || total + line.len() == expected.len());
+        assert_eq!(line, &expected[total..total + line.len()]);
+        total += line.len();
+        lines += 1;
+        line.clear();
+    }
+
+    assert_eq!(total, expected.len());
+    assert_eq!(lines, expected.split_inclusive(|&b| b == b'\n').count());
+}
+
+#[test]
+fn small_reads() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut output = Vec::new();
+    let mut decoder = GzipDecoder::new(BufReader::with_capacity(16, data));
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, expected);
+    assert_eq!(decoder.total_in(), data.len() as u64);
+}
+
+#[test]
+fn buffer_size() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let options = DecompressOptions {
+        buffer_size: 1000,
+        ..Default::default()
+    };
+    let mut decoder = GzipDecoder::with_options(data, options);
+    let mut output = Vec::new();
+    loop {
+        let chunk = decoder.fill_buf().unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        assert!(chunk.len() < 1000 + 258);
+        output.extend_from_slice(chunk);
+        let len = chunk.len();
+        decoder.consume(len);
+    }
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn passthrough() {
+    let options = DecompressOptions {
+        passthrough: true,
+        ..Default::default()
+    };
+    let decode = |data: &[u8]| {
+        let mut decoder = GzipDecoder::with_options(data, options.clone());
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).map(|_| output)
+    };
+
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+    assert_eq!(decode(data).unwrap(), expected);
+
+    let plain = b"not compressed\n".repeat(10_000);
+    let mut decoder = GzipDecoder::with_options(plain.as_slice(), options.clone());
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, plain);
+    assert_eq!(decoder.total_out(), plain.len() as u64);
+    assert!(decoder.checkpoint().is_err());
+
+    assert!(decode(b"").unwrap().is_empty());
+    assert!(decode(b"\x1f").is_err());
+    // Only the start of the input is looked at.
+    let mut trailing = data.to_vec();
+    trailing.extend_from_slice(b"plain");
+    assert!(decode(&trailing).is_err());
+}
+
+#[test]
+fn strict_bgzf() {
+    let member: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(member, &mut expected).unwrap();
+    let options = DecompressOptions {
+        strict_bgzf: true,
+        ..Default::default()
+    };
+
+    let mut data = member.to_vec();
+    data.extend_from_slice(&BGZF_EOF);
+    let mut output = Vec::new();
+    GzipDecoder::with_options(data.as_slice(), options.clone())
+        .read_to_end(&mut output)
+        .unwrap();
+    assert_eq!(output, expected);
+
+    let err = GzipDecoder::with_options(member, options)
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "missing bgzf eof marker");
+}
+
+#[test]
+fn members() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let members = ripgzip::members(data).unwrap();
+    let summary = members
+        .iter()
+        .map(|member| {
+            (
+                member.modification_time,
+                member.os,
+                member.crc32,
+                member.size,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (1617639922, 3, 0x822c7eb3, 88194),
+            (1617639609, 3, 0xa188320a, 153333),
+            (1617639068, 3, 0xb03952f5, 1543130),
+        ]
+    );
+    assert!(members.iter().all(|member| member.name.is_none()));
+
+    // The members can be copied out by their range.
+    assert_eq!(members[0].compressed_range.start, 0);
+    assert_eq!(members[2].compressed_range.end, data.len() as u64);
+    for (i, member) in members.iter().enumerate() {
+        if i > 0 {
+            assert_eq!(
+                member.compressed_range.start,
+                members[i - 1].compressed_range.end
+            );
+        }
+        let range = member.compressed_range.start as usize..member.compressed_range.end as usize;
+        let mut output = Vec::new();
+        ripgzip::decompress(&data[range], &mut output).unwrap();
+        assert_eq!(output.len(), member.size as usize);
+    }
+}
+
+#[test]
+fn introspection() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut decoder = GzipDecoder::new(data);
+    assert_eq!(decoder.current_member_index(), 0);
+    assert_eq!(decoder.bits_consumed(), 0);
+
+    let mut members = Vec::new();
+    let mut blocks = 0;
+    loop {
+        let chunk = decoder.fill_buf().unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        let len = chunk.len() as u64;
+        assert_eq!(decoder.bytes_emitted(), decoder.total_out() + len);
+        if members.last() != Some(&decoder.current_member_index()) {
+            members.push(decoder.current_member_index());
+            blocks = 0;
+        }
+        assert!(decoder.current_block_index() >= blocks);
+        blocks = decoder.current_block_index();
+        decoder.consume(len as usize);
+    }
+    assert_eq!(members, [0, 1, 2]);
+    assert!(blocks > 1);
+    assert_eq!(decoder.bytes_emitted(), 88194 + 153333 + 1543130);
+    assert_eq!(decoder.bits_consumed(), data.len() as u64 * 8);
+}
+
+#[test]
+fn block_usage() {
+    let data = (0..100_000u32)
+        .map(|i| (i * 7 % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut input = Vec::new();
+    let options = ripgzip::CompressOptions {
+        level: 0,
+        ..Default::default()
+    };
+    ripgzip::compress(&data[..], &mut input, options).unwrap();
+    input.extend_from_slice(include_bytes!("../data/ok/01-page.gz"));
+
+    let members = ripgzip::members(&input[..]).unwrap();
+    let stored = members[0].blocks;
+    assert_eq!(stored.blocks(), stored.stored_blocks);
+    assert!(stored.stored_blocks >= 2);
+    assert_eq!(stored.stored_bytes, 100_000);
+
+    let page = members[1].blocks;
+    assert_eq!(page.stored_blocks, 0);
+    assert!(page.dynamic_blocks > 0);
+    assert_eq!(page.bytes(), 153333);
+
+    let mut decoder = GzipDecoder::new(&input[..]);
+    std::io::copy(&mut decoder, &mut std::io::sink()).unwrap();
+    assert_eq!(decoder.block_usage(), page);
+}
+
+#[test]
+fn decode_up_to() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut decoder = GzipDecoder::new(data);
+    let mut output = Vec::new();
+    loop {
+        let chunk = decoder.decode_up_to(1000).unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        assert!(chunk.len() <= 1000);
+        output.extend_from_slice(chunk);
+        // Little more than what was asked for is decoded ahead.
+        assert!(decoder.bytes_emitted() - decoder.total_out() < 258);
+    }
+    assert_eq!(output, expected);
+
+    // Data which is not gzip is handed out as is, in chunks as well.
+    let options = DecompressOptions {
+        passthrough: true,
+        ..Default::default()
+    };
+    let mut decoder = GzipDecoder::with_options(&b"plain text"[..], options);
+    assert_eq!(decoder.decode_up_to(5).unwrap(), b"plain");
+    assert_eq!(decoder.decode_up_to(100).unwrap(), b" text");
+    assert!(decoder.decode_up_to(100).unwrap().is_empty());
+}
+
+#[test]
+fn rate_limit() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let options = DecompressOptions {
+        rate_limit: NonZeroU64::new(2_000_000),
+        ..Default::default()
+    };
+    let mut decoder = GzipDecoder::with_options(data, options);
+    let start = std::time::Instant::now();
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output.len(), 153333);
+    // 153333 bytes at 2MB/s take about 77ms.
+    assert!(start.elapsed() >= std::time::Duration::from_millis(70));
+}
+
+#[test]
+fn small_window() {
+    let data = (0..100_000u32).map(|i| (i % 100) as u8).collect::<Vec<_>>();
+    let mut input = Vec::new();
+    ripgzip::compress(&data[..], &mut input, Default::default()).unwrap();
+    let mut decoder = GzipDecoder::new(&input[..]);
+    decoder.set_window_size(256);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, data);
+
+    // Matches reaching further than the window fail.
+    let mut decoder = GzipDecoder::new(&include_bytes!("../data/ok/01-page.gz")[..]);
+    decoder.set_window_size(256);
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    assert!(err.to_string().contains("beyond the window"), "{}", err);
+}
+
+#[test]
+fn send_static() {
+    fn spawn<T: Read + Send + 'static>(mut decoder: T) -> std::thread::JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            output
+        })
+    }
+
+    let data: &'static [u8] = include_bytes!("../data/ok/01-page.gz");
+    let output = spawn(GzipDecoder::new(data)).join().unwrap();
+    assert_eq!(output.len(), 153333);
+    let reader = BufReader::new(std::io::Cursor::new(data.to_vec()));
+    let output = spawn(GzipDecoder::new(reader)).join().unwrap();
+    assert_eq!(output.len(), 153333);
+}
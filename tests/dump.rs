@@ -0,0 +1,62 @@
+#![cfg(feature = "gzip")]
+
+use ripgzip::{compress, hex_dump, CompressOptions, ExtraField};
+
+fn dump(data: &[u8]) -> (String, bool) {
+    let mut output = Vec::new();
+    let result = hex_dump(data, &mut output);
+    (String::from_utf8(output).unwrap(), result.is_ok())
+}
+
+#[test]
+fn fields() {
+    let options = CompressOptions {
+        name: Some("a \"name\"".to_string()),
+        header_crc: true,
+        extra: vec![ExtraField::new(*b"ab", vec![1, 2])],
+        modification_time: 1_000_000_000,
+        os: 3,
+        ..Default::default()
+    };
+    let mut data = Vec::new();
+    compress(&[0u8; 100][..], &mut data, options).unwrap();
+    compress(&b"second"[..], &mut data, CompressOptions::default()).unwrap();
+
+    let (text, ok) = dump(&data);
+    assert!(ok);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "00000000  -- member 0");
+    assert!(lines[1].starts_with("00000000  1f 8b") && lines[1].ends_with("ID1 ID2"));
+    assert!(lines[3].ends_with("FLG = FHCRC | FEXTRA | FNAME"));
+    assert!(lines[4].ends_with("MTIME = 1000000000"));
+    assert!(lines[7].ends_with("XLEN = 6"));
+    assert!(lines[8].starts_with("0000000c  61 62 02 00 01 02") && lines[8].ends_with("FEXTRA"));
+    assert!(lines[9].ends_with(r#"FNAME = "a \"name\"""#));
+    assert!(lines[10].ends_with("(ok)") && lines[10].contains("HCRC = 0x"));
+    assert!(lines[11].contains("-- block 0 at deflate bit 0 (bit 0 of the byte): "));
+    assert!(lines[11].ends_with(", final, 100 bytes"));
+    assert!(text.contains("ISIZE = 100 (ok)\n"));
+    assert!(text.contains("-- member 1\n"));
+    assert!(text.ends_with("ISIZE = 6 (ok)\n"));
+    assert_eq!(text.matches("CRC32 = 0x").count(), 2);
+}
+
+#[test]
+fn broken() {
+    // Wrong checksums are pointed out, without failing the dump.
+    let (text, ok) = dump(include_bytes!("../data/corrupted/01-bad-crc32.gz"));
+    assert!(ok);
+    assert!(text.contains("(computed 0x"));
+
+    let (text, ok) = dump(include_bytes!("../data/corrupted/06-invalid-btype.gz"));
+    assert!(!ok);
+    assert!(text.contains(": broken\n"));
+    assert!(text.contains("-- error: unsupported block type\n"));
+
+    let data = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let (text, ok) = dump(&data[..data.len() - 3]);
+    assert!(!ok);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert!(lines[lines.len() - 2].ends_with("-- error: footer is truncated"));
+    assert!(lines[lines.len() - 1].ends_with("unparsed"));
+}
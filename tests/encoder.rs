@@ -0,0 +1,418 @@
+#![cfg(feature = "gzip")]
+
+use std::io::{BufRead, Read, Write};
+
+use ripgzip::{
+    compress, decompress, BgzfEncoder, CompressOptions, CompressionHint, DecompressOptions,
+    ExtraField, GzipDecoder, GzipEncoder, MemberFlags, Strategy, Subfield, BGZF_EOF,
+};
+
+fn round_trip(data: &[u8], level: u32) -> Vec<u8> {
+    round_trip_with(
+        data,
+        CompressOptions {
+            level,
+            ..Default::default()
+        },
+    )
+}
+
+fn round_trip_with(data: &[u8], options: CompressOptions) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    compress(data, &mut compressed, options).unwrap();
+    let mut output = Vec::new();
+    decompress(compressed.as_slice(), &mut output).unwrap();
+    assert_eq!(output, data);
+    compressed
+}
+
+fn incompressible_data(len: usize) -> Vec<u8> {
+    let mut seed = 12345u32;
+    (0..len)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect()
+}
+
+fn page() -> Vec<u8> {
+    let mut data = Vec::new();
+    decompress(&include_bytes!("../data/ok/01-page.gz")[..], &mut data).unwrap();
+    data
+}
+
+#[test]
+fn levels() {
+    let data = page();
+    let mut sizes = Vec::new();
+    for level in [0, 1, 6, 9] {
+        sizes.push(round_trip(&data, level).len());
+    }
+    assert!(sizes[0] > data.len());
+    assert!(sizes[1] < data.len() / 3);
+    assert!(sizes[3] <= sizes[1]);
+}
+
+#[test]
+fn huffman_only() {
+    let data = page();
+    let options = CompressOptions {
+        strategy: Strategy::HuffmanOnly,
+        ..Default::default()
+    };
+    let size = round_trip_with(&data, options.clone()).len();
+    assert!(size < data.len() * 3 / 4);
+    assert!(size > round_trip(&data, 1).len());
+
+    round_trip_with(b"", options.clone());
+    round_trip_with(b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", options);
+}
+
+#[test]
+fn header_bytes() {
+    for (options, extra_flags, os) in [
+        (CompressOptions::default(), 0, None),
+        (
+            CompressOptions {
+                level: 9,
+                ..Default::default()
+            },
+            2,
+            None,
+        ),
+        (
+            CompressOptions {
+                level: 1,
+                os: 255,
+                ..Default::default()
+            },
+            4,
+            Some(255),
+        ),
+        (
+            CompressOptions {
+                extra_flags: Some(0x42),
+                os: 0,
+                ..Default::default()
+            },
+            0x42,
+            Some(0),
+        ),
+    ] {
+        let compressed = round_trip_with(b"header", options);
+        assert_eq!(compressed[8], extra_flags);
+        if let Some(os) = os {
+            assert_eq!(compressed[9], os);
+        }
+        let mut decoder = GzipDecoder::new(compressed.as_slice());
+        decoder.fill_buf().unwrap();
+        let hint = decoder.header().unwrap().compression_hint();
+        assert_eq!(u8::from(hint), extra_flags);
+    }
+
+    let hints = [1, 6, 9].map(CompressionHint::for_level);
+    assert_eq!(
+        hints,
+        [
+            CompressionHint::Fastest,
+            CompressionHint::Other(0),
+            CompressionHint::Slowest
+        ]
+    );
+    assert_eq!(CompressionHint::from(4), CompressionHint::Fastest);
+}
+
+#[test]
+fn header_crc() {
+    let options = CompressOptions {
+        extra: vec![ExtraField::new(*b"xy", b"abc".to_vec())],
+        header_crc: true,
+        ..Default::default()
+    };
+    let mut compressed = round_trip_with(b"header crc", options);
+    assert_eq!(compressed[3], 0x06);
+    let mut decoder = GzipDecoder::new(compressed.as_slice());
+    decoder.fill_buf().unwrap();
+    assert!(decoder.header().unwrap().has_crc);
+
+    // The CRC16 follows the 10 fixed bytes and the 2 + 7 of the extra field.
+    compressed[19] ^= 1;
+    assert!(decompress(compressed.as_slice(), &mut Vec::new()).is_err());
+}
+
+#[test]
+fn header_flags() {
+    let options = CompressOptions {
+        name: Some("name".to_string()),
+        header_crc: true,
+        ..Default::default()
+    };
+    let compressed = round_trip_with(b"flags", options);
+    let mut decoder = GzipDecoder::new(compressed.as_slice());
+    decoder.fill_buf().unwrap();
+    let flags = decoder.header().unwrap().flags();
+    assert_eq!(flags, MemberFlags(compressed[3]));
+    assert!(flags.contains(MemberFlags::FHCRC) && flags.contains(MemberFlags::FNAME));
+    assert!(!flags.contains(MemberFlags::FEXTRA));
+    assert_eq!(flags.to_string(), "FHCRC | FNAME");
+    assert_eq!(format!("{:?}", flags), "MemberFlags(FHCRC | FNAME)");
+
+    assert_eq!(MemberFlags(0).to_string(), "none");
+    assert_eq!(MemberFlags(0x21).to_string(), "FTEXT | 0x20");
+    assert!(MemberFlags(0xff).contains(MemberFlags::RESERVED));
+}
+
+#[test]
+fn header_write_to() {
+    for entry in std::fs::read_dir("data/ok").unwrap() {
+        let data = std::fs::read(entry.unwrap().path()).unwrap();
+        let mut decoder = GzipDecoder::new(data.as_slice());
+        decoder.fill_buf().unwrap();
+        let mut header = Vec::new();
+        decoder.header().unwrap().write_to(&mut header).unwrap();
+        assert_eq!(header, data[..header.len()]);
+    }
+
+    // Splice a header with a comment and a CRC16 in place of the original one.
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut decoder = GzipDecoder::new(data);
+    decoder.fill_buf().unwrap();
+    let mut header = decoder.header().unwrap().clone();
+    header.comment = Some("rewritten".to_string());
+    header.has_crc = true;
+    let mut member = Vec::new();
+    header.write_to(&mut member).unwrap();
+    member.extend_from_slice(&data[10..]);
+    let mut decoder = GzipDecoder::new(member.as_slice());
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, page());
+    assert_eq!(
+        decoder.header().unwrap().comment.as_deref(),
+        Some("rewritten")
+    );
+
+    header.name = Some("a\0b".to_string());
+    assert!(header.write_to(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn latin1_name() {
+    // FNAME "caf\xe9.txt" in ISO 8859-1, with a CRC16 over those bytes.
+    let data = [
+        0x1f, 0x8b, 8, 0x0a, 0, 0, 0, 0, 0, 0xff, b'c', b'a', b'f', 0xe9, b'.', b't', b'x', b't',
+        0, 0x87, 0xde, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xe7, 0x02, 0x00, 0x20, 0x30, 0x3a, 0x36, 6,
+        0, 0, 0,
+    ];
+    let mut decoder = GzipDecoder::new(&data[..]);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"hello\n");
+    let header = decoder.header().unwrap();
+    assert_eq!(header.name.as_deref(), Some("caf\u{e9}.txt"));
+    let mut header_bytes = Vec::new();
+    header.write_to(&mut header_bytes).unwrap();
+    assert_eq!(header_bytes, data[..21]);
+
+    assert_eq!(
+        ripgzip::encode_latin1("caf\u{e9}"),
+        Some(b"caf\xe9".to_vec())
+    );
+    let mut header = header.clone();
+    header.name = Some("\u{65e5}".to_string());
+    let error = header.write_to(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.to_string(), "name is not ISO 8859-1 text");
+}
+
+#[test]
+fn reframe() {
+    // The CRC-32 of the footer is wrong, the rest is fine.
+    let data: &[u8] = include_bytes!("../data/corrupted/01-bad-crc32.gz");
+    assert!(ripgzip::verify(data).is_err());
+    let mut decoder = GzipDecoder::new(data);
+    decoder.fill_buf().unwrap();
+    let header = decoder.header().unwrap().clone();
+    let mut header_bytes = Vec::new();
+    header.write_to(&mut header_bytes).unwrap();
+    let mut fixed = Vec::new();
+    let footer = ripgzip::reframe_member(&header, &data[header_bytes.len()..], &mut fixed).unwrap();
+    assert_eq!(fixed.len(), data.len());
+    assert_eq!(fixed[..fixed.len() - 8], data[..data.len() - 8]);
+    ripgzip::verify(fixed.as_slice()).unwrap();
+    let mut footer_bytes = Vec::new();
+    footer.write_to(&mut footer_bytes).unwrap();
+    assert_eq!(footer_bytes, fixed[fixed.len() - 8..]);
+
+    // A zlib stream, its Adler-32 being left out.
+    let zlib: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    let header = ripgzip::MemberHeader {
+        compression_method: ripgzip::CompressionMethod::Deflate,
+        modification_time: 0,
+        extra: None,
+        name: Some("page.html".to_string()),
+        comment: None,
+        extra_flags: 0,
+        os: 255,
+        has_crc: false,
+        is_text: false,
+    };
+    let mut member = Vec::new();
+    let footer = ripgzip::reframe_member(&header, &zlib[2..], &mut member).unwrap();
+    assert_eq!(member.len(), 20 + zlib.len() - 6 + 8);
+    assert_eq!(footer.data_size as usize, page().len());
+    let mut output = Vec::new();
+    decompress(member.as_slice(), &mut output).unwrap();
+    assert_eq!(output, page());
+}
+
+#[test]
+fn deterministic() {
+    let data = incompressible_data(100_000);
+    let compressed = round_trip_with(&data, CompressOptions::deterministic());
+    assert_eq!(compressed[3], 0);
+    assert_eq!(compressed[4..8], [0; 4]);
+    assert_eq!(compressed[9], 255);
+    assert_eq!(
+        round_trip_with(&data, CompressOptions::deterministic()),
+        compressed
+    );
+
+    // A name or time set on top is left out.
+    for (name, modification_time) in [("a.txt", 1), ("b.txt", 1_700_000_000)] {
+        let options = CompressOptions {
+            name: Some(name.to_string()),
+            modification_time,
+            os: 3,
+            ..CompressOptions::deterministic()
+        };
+        assert_eq!(round_trip_with(&data, options), compressed);
+    }
+}
+
+#[test]
+fn extra_fields() {
+    let options = CompressOptions {
+        extra: vec![
+            ExtraField::bgzf_block_size(0x1234),
+            ExtraField::new(*b"xy", b"abc".to_vec()),
+        ],
+        ..Default::default()
+    };
+    let compressed = round_trip_with(b"extra", options);
+    assert_eq!(compressed[3], 4);
+    assert_eq!(&compressed[10..12], &[13, 0]);
+    assert_eq!(&compressed[12..18], b"BC\x02\x00\x34\x12");
+    assert_eq!(&compressed[18..25], b"xy\x03\x00abc");
+
+    let options = CompressOptions {
+        extra: vec![ExtraField::new(*b"xy", vec![0; 1 << 16])],
+        ..Default::default()
+    };
+    assert!(compress(&b""[..], Vec::new(), options).is_err());
+}
+
+#[test]
+fn write_driven() {
+    let data = page().repeat(3);
+    let mut expected = Vec::new();
+    compress(data.as_slice(), &mut expected, CompressOptions::default()).unwrap();
+
+    let mut encoder = GzipEncoder::new(Vec::new(), CompressOptions::default()).unwrap();
+    for chunk in data.chunks(1000) {
+        encoder.write_all(chunk).unwrap();
+    }
+    assert!(encoder.get_ref().len() < expected.len());
+    assert_eq!(encoder.finish().unwrap(), expected);
+}
+
+#[test]
+fn small_inputs() {
+    for data in [
+        &b""[..],
+        b"a",
+        b"abc",
+        b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+    ] {
+        round_trip(data, 6);
+    }
+}
+
+#[test]
+fn incompressible() {
+    let data = incompressible_data(100_000);
+    let compressed = round_trip(&data, 9);
+    // Stored blocks: 5 bytes per block of 32K, plus the gzip header and footer.
+    assert!(compressed.len() <= data.len() + 5 * 4 + 18);
+}
+
+#[test]
+fn bgzf() {
+    let mut data = page().repeat(2);
+    data.extend(incompressible_data(100_000));
+    let mut encoder = BgzfEncoder::new(Vec::new(), CompressOptions::default()).unwrap();
+    for chunk in data.chunks(10_000) {
+        encoder.write_all(chunk).unwrap();
+    }
+    let compressed = encoder.finish().unwrap();
+
+    // Walk the members with the sizes in their "BC" subfields.
+    let mut pos = 0;
+    let mut members = 0;
+    while pos < compressed.len() {
+        let member = &compressed[pos..];
+        assert_eq!(&member[..4], &[0x1f, 0x8b, 8, 4]);
+        assert_eq!(&member[12..16], b"BC\x02\x00");
+        pos += u16::from_le_bytes([member[16], member[17]]) as usize + 1;
+        members += 1;
+    }
+    assert_eq!(pos, compressed.len());
+    assert_eq!(members, data.len().div_ceil(0xff00) + 1);
+    assert!(compressed.ends_with(&BGZF_EOF));
+
+    let options = DecompressOptions {
+        strict_bgzf: true,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    GzipDecoder::with_options(compressed.as_slice(), options)
+        .read_to_end(&mut output)
+        .unwrap();
+    assert_eq!(output, data);
+}
+
+#[test]
+fn subfields() {
+    let fields = vec![
+        ExtraField::bgzf_block_size(1234),
+        ExtraField::new(*b"RA", vec![1, 0, 0x10, 0, 2, 0, 0x20, 0, 0x30, 0]),
+        ExtraField::new(*b"AP", b"type".to_vec()),
+        ExtraField::new(*b"zz", b"other".to_vec()),
+    ];
+    let options = CompressOptions {
+        extra: fields.clone(),
+        ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    compress(&b"data"[..], &mut compressed, options).unwrap();
+    let mut decoder = GzipDecoder::new(compressed.as_slice());
+    decoder.fill_buf().unwrap();
+    assert_eq!(
+        decoder.header().unwrap().subfields().unwrap(),
+        [
+            Subfield::BgzfBlockSize(1234),
+            Subfield::RandomAccess {
+                version: 1,
+                chunk_length: 16,
+                chunk_sizes: vec![0x20, 0x30],
+            },
+            Subfield::Apollo(b"type".to_vec()),
+            Subfield::Other(fields[3].clone()),
+        ]
+    );
+
+    for (id, data) in [(b"BC", &[1][..]), (b"RA", &[1, 0, 0x10, 0, 2, 0, 0x20, 0])] {
+        let err = ExtraField::new(*id, data.to_vec()).interpret().unwrap_err();
+        assert!(err.to_string().starts_with("malformed"));
+    }
+}
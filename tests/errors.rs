@@ -1,3 +1,5 @@
+#![cfg(feature = "gzip")]
+
 fn check_decompression_error(mut data: &[u8], msg: &'static str) {
     let res = ripgzip::decompress(&mut data, &mut std::io::sink());
     if res.is_ok() {
@@ -45,3 +47,229 @@ fn errors() {
         "nlen check failed",
     );
 }
+
+#[test]
+fn verify() {
+    for entry in std::fs::read_dir("data/corrupted").unwrap() {
+        let data = std::fs::read(entry.unwrap().path()).unwrap();
+        assert!(ripgzip::verify(data.as_slice()).is_err());
+    }
+    for entry in std::fs::read_dir("data/ok").unwrap() {
+        let data = std::fs::read(entry.unwrap().path()).unwrap();
+        ripgzip::verify(data.as_slice()).unwrap();
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn mutations() {
+    use ripgzip::testing::{ErrorClass, Mutation};
+
+    for name in ["00-Cargo.toml.gz", "01-page.gz", "02-doc.pdf.gz"] {
+        let data = std::fs::read(format!("data/ok/{}", name)).unwrap();
+        for mutation in Mutation::ALL {
+            let corrupted = mutation.apply(&data).unwrap();
+            let err = ripgzip::verify(corrupted.as_slice()).unwrap_err();
+            assert_eq!(ErrorClass::of(&err), Some(mutation.expected_error()));
+        }
+    }
+}
+
+#[test]
+fn extra_field() {
+    use std::io::Read;
+
+    use ripgzip::{DecompressOptions, GzipDecoder};
+
+    let header = |xlen: u16, extra: &[u8]| {
+        let mut data = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 255];
+        data.extend(xlen.to_le_bytes());
+        data.extend(extra);
+        data
+    };
+    check_decompression_error(&header(10, b"ab\x02\x00xy"), "extra field is truncated");
+
+    let strict = |data: &[u8]| {
+        let options = DecompressOptions {
+            strict_extra: true,
+            ..Default::default()
+        };
+        let mut decoder = GzipDecoder::with_options(data, options);
+        decoder
+            .read_to_end(&mut Vec::new())
+            .unwrap_err()
+            .to_string()
+    };
+    for data in [header(6, b"ab\x05\x00xy"), header(7, b"ab\x00\x00xyz")] {
+        assert!(strict(&data).contains("extra subfield overruns xlen"));
+    }
+    assert!(!strict(&header(6, b"ab\x02\x00xy")).contains("extra"));
+
+    // Free-form extra data is accepted by default.
+    let mut output = Vec::new();
+    ripgzip::decompress(
+        &include_bytes!("../data/ok/10-header-crc16.gz")[..],
+        &mut output,
+    )
+    .unwrap();
+    assert!(strict(include_bytes!("../data/ok/10-header-crc16.gz")).contains("overruns"));
+}
+
+#[test]
+fn truncated_header() {
+    use std::io::{Cursor, Read};
+
+    use ripgzip::GzipDecoder;
+
+    // FHCRC set, and the input ends before it. Then an unterminated FNAME.
+    let crc16: &[u8] = &[0x1f, 0x8b, 8, 2, 0, 0, 0, 0, 0, 0xff];
+    let name: &[u8] = &[0x1f, 0x8b, 8, 8, 0, 0, 0, 0, 0, 0xff, b'a'];
+    for data in [crc16, name] {
+        check_decompression_error(data, "header is truncated");
+        assert!(ripgzip::verify(data).is_err());
+        assert!(ripgzip::members(data).is_err());
+        assert!(ripgzip::decompress_bounded(data, Vec::new(), u64::MAX).is_err());
+        assert!(ripgzip::estimate_decompressed_size(Cursor::new(data)).is_err());
+
+        let mut decoder = GzipDecoder::new(data);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+        assert!(decoder.resync().is_err());
+
+        #[cfg(feature = "zlib")]
+        {
+            assert!(ripgzip::decompress_nested(data, Vec::new(), 4).is_err());
+            assert!(ripgzip::AnyDecoder::new(data)
+                .and_then(|mut decoder| Ok(decoder.read_to_end(&mut Vec::new())?))
+                .is_err());
+            assert!(ripgzip::GzipIndex::build(data, 1 << 20).is_err());
+        }
+    }
+}
+
+#[test]
+fn validate_header() {
+    use ripgzip::{compress, validate_header, CompressOptions};
+
+    let options = CompressOptions {
+        name: Some("name".to_string()),
+        header_crc: true,
+        ..Default::default()
+    };
+    let mut data = Vec::new();
+    compress(&b"data"[..], &mut data, options).unwrap();
+    let mut reader = data.as_slice();
+    let header = validate_header(&mut reader).unwrap();
+    assert_eq!(header.name.as_deref(), Some("name"));
+    // The reader is left at the deflate data.
+    assert_eq!(reader.len(), data.len() - 10 - 5 - 2);
+    #[cfg(feature = "raw")]
+    {
+        use std::io::Read;
+
+        let mut output = Vec::new();
+        ripgzip::DeflateDecoder::new(reader)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"data");
+    }
+
+    let error = |data: &[u8]| validate_header(data).unwrap_err().to_string();
+    assert_eq!(error(&data[..5]), "header is truncated");
+    assert_eq!(error(&data[..14]), "name is not terminated");
+    assert_eq!(error(&data[..16]), "header is truncated");
+    let mut corrupted = data.clone();
+    corrupted[3] |= 0x80;
+    assert_eq!(error(&corrupted), "reserved flags are set");
+    corrupted = data.clone();
+    corrupted[4] ^= 1;
+    assert_eq!(error(&corrupted), "header crc16 check failed");
+    assert_eq!(
+        error(include_bytes!("../data/corrupted/03-wrong-id.gz")),
+        "wrong id values"
+    );
+    assert_eq!(
+        error(include_bytes!("../data/corrupted/07-invalid-cm.gz")),
+        "unsupported compression method"
+    );
+    // Free-form extra data does not pass, unlike when decompressing by default.
+    assert!(error(include_bytes!("../data/ok/10-header-crc16.gz")).contains("overruns"));
+    let mut extra = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 255, 10, 0];
+    extra.extend(b"ab\x02\x00xy");
+    assert_eq!(error(&extra), "extra field is truncated");
+    // The deflate data is not looked at.
+    validate_header(&include_bytes!("../data/corrupted/06-invalid-btype.gz")[..]).unwrap();
+}
+
+#[test]
+fn resync() {
+    use std::io::Read;
+
+    use ripgzip::GzipDecoder;
+
+    let read_all = |decoder: &mut GzipDecoder<&[u8]>| {
+        let mut output = Vec::new();
+        let result = decoder.read_to_end(&mut output);
+        (output, result.is_ok())
+    };
+
+    // Stored blocks "hello" and "world" separated by an empty one, as written by a
+    // full flush. The NLEN of the first block is broken.
+    let mut member = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    member.extend([0, 5, 0, 0xfa, 0xfe]);
+    member.extend(b"hello");
+    member.extend([0, 0, 0, 0xff, 0xff, 1, 5, 0, 0xfa, 0xff]);
+    member.extend(b"world");
+    member.extend([0; 8]);
+    let mut decoder = GzipDecoder::new(member.as_slice());
+    assert!(!read_all(&mut decoder).1);
+    assert_eq!(decoder.resync().unwrap(), 15);
+    assert_eq!(read_all(&mut decoder), (b"world".to_vec(), true));
+
+    // A broken member followed by a good one.
+    let good = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(&good[..], &mut expected).unwrap();
+    let mut data = include_bytes!("../data/ok/01-page.gz").to_vec();
+    data[1000] ^= 0x55;
+    data.extend(good);
+    let mut decoder = GzipDecoder::new(data.as_slice());
+    loop {
+        let (output, ok) = read_all(&mut decoder);
+        if ok {
+            assert!(output.ends_with(&expected));
+            break;
+        }
+        decoder.resync().unwrap();
+    }
+
+    let mut decoder = GzipDecoder::new(&b"\x1f\x8b\x08\x00 no more"[..]);
+    assert!(!read_all(&mut decoder).1);
+    assert!(decoder.resync().is_err());
+}
+
+#[test]
+fn partial_output() {
+    let data: &[u8] = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let truncated = &data[..data.len() / 2];
+    let mut output = Vec::new();
+    let err = ripgzip::decompress(truncated, &mut output).unwrap_err();
+    let partial = err.downcast_ref::<ripgzip::PartialOutput>().unwrap();
+    assert_eq!(partial.bytes_written, output.len() as u64);
+    assert!(output.len() > expected.len() / 3);
+    assert_eq!(output, expected[..output.len()]);
+    assert!(err.root_cause().to_string().contains("eof"));
+
+    // The data decoded before the error is handed out before it.
+    let mut decoder = ripgzip::GzipDecoder::new(truncated);
+    let mut streamed = Vec::new();
+    assert!(std::io::Read::read_to_end(&mut decoder, &mut streamed).is_err());
+    assert_eq!(streamed, output);
+    assert_eq!(decoder.total_out(), output.len() as u64);
+
+    // Errors of the output are returned as is.
+    let err = ripgzip::decompress_with(data, |_| anyhow::bail!("full")).unwrap_err();
+    assert!(!err.is::<ripgzip::PartialOutput>());
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "gzip")]
+
+use std::io::{Cursor, Write};
+
+use ripgzip::{compress, estimate_decompressed_size, BgzfEncoder, CompressOptions, SizeEstimate};
+
+#[test]
+fn single_member() {
+    let data = include_bytes!("../data/ok/01-page.gz");
+    let estimate = estimate_decompressed_size(Cursor::new(data)).unwrap();
+    assert_eq!(
+        estimate,
+        SizeEstimate {
+            size: 153333,
+            exact: true
+        }
+    );
+
+    let empty = estimate_decompressed_size(Cursor::new([])).unwrap();
+    assert_eq!(empty.size, 0);
+    assert!(estimate_decompressed_size(Cursor::new(&data[..12])).is_err());
+}
+
+#[test]
+fn bgzf_members() {
+    let data = vec![b'x'; 300_000];
+    let mut encoder = BgzfEncoder::new(Vec::new(), CompressOptions::default()).unwrap();
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let estimate = estimate_decompressed_size(Cursor::new(compressed)).unwrap();
+    assert_eq!(estimate.size, 300_000);
+    assert!(estimate.exact);
+}
+
+#[test]
+fn wrap_around() {
+    // Long enough to possibly hold 4 GiB, though it does not.
+    let data = (0..5_000_000u32)
+        .map(|i| i.wrapping_mul(2654435761) as u8)
+        .collect::<Vec<_>>();
+    let mut compressed = Vec::new();
+    let options = CompressOptions {
+        level: 0,
+        ..Default::default()
+    };
+    compress(data.as_slice(), &mut compressed, options).unwrap();
+    let estimate = estimate_decompressed_size(Cursor::new(compressed)).unwrap();
+    assert_eq!(estimate.size, 5_000_000);
+    assert!(!estimate.exact);
+}
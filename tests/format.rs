@@ -0,0 +1,73 @@
+#![cfg(all(feature = "gzip", feature = "zlib"))]
+
+use std::io::{BufRead, Read};
+
+use ripgzip::{decompress, decompress_nested, sniff_format, AnyDecoder, Format};
+
+fn decode(data: &[u8]) -> (Format, Vec<u8>) {
+    let mut decoder = AnyDecoder::new(data).unwrap();
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    (decoder.format(), output)
+}
+
+#[test]
+fn detect() {
+    let gzip: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut page = Vec::new();
+    decompress(gzip, &mut page).unwrap();
+
+    assert_eq!(decode(gzip), (Format::Gzip, page.clone()));
+    let zlib: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    assert_eq!(decode(zlib), (Format::Zlib, page));
+
+    let gzip: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut expected = Vec::new();
+    decompress(gzip, &mut expected).unwrap();
+    let raw = &gzip[10..gzip.len() - 8];
+    assert_eq!(decode(raw), (Format::Raw, expected));
+}
+
+#[test]
+fn sniff() {
+    let gzip: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let zlib: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    for (mut data, format) in [
+        (gzip, Format::Gzip),
+        (zlib, Format::Zlib),
+        (&gzip[10..], Format::Raw),
+        (
+            &[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'][..],
+            Format::Raw,
+        ),
+        (b"hello, world", Format::Unknown),
+        (b"", Format::Unknown),
+    ] {
+        let len = data.len();
+        assert_eq!(sniff_format(&mut data).unwrap(), format);
+        assert_eq!(data.fill_buf().unwrap().len(), len);
+    }
+}
+
+#[test]
+fn nested() {
+    let mut text = Vec::new();
+    decompress(
+        &include_bytes!("../data/ok/06-war-and-peace.txt.gz")[..],
+        &mut text,
+    )
+    .unwrap();
+
+    // Despite its name, the file is gzipped twice.
+    let data: &[u8] = include_bytes!("../data/ok/08-war-and-peace.txt.gz.gz.gz");
+    let mut output = Vec::new();
+    assert_eq!(decompress_nested(data, &mut output, 3).unwrap(), 2);
+    assert_eq!(output, text);
+
+    let err = decompress_nested(data, Vec::new(), 1).unwrap_err();
+    assert_eq!(err.to_string(), "more than 1 compression layers");
+
+    let mut output = Vec::new();
+    assert_eq!(decompress_nested(&text[..], &mut output, 3).unwrap(), 0);
+    assert_eq!(output, text);
+}
@@ -0,0 +1,92 @@
+#![cfg(all(feature = "heapless", feature = "gzip"))]
+
+use ripgzip::{CompressOptions, HeaplessError, HeaplessInflater, HuffmanTables, WINDOW_SIZE};
+
+fn page() -> Vec<u8> {
+    let mut data = Vec::new();
+    ripgzip::decompress(&include_bytes!("../data/ok/01-page.gz")[..], &mut data).unwrap();
+    data
+}
+
+/// Read all of `inflater` in chunks of `chunk_size` bytes.
+fn read_all(inflater: &mut HeaplessInflater, chunk_size: usize) -> Result<Vec<u8>, HeaplessError> {
+    let mut output = Vec::new();
+    let mut chunk = vec![0; chunk_size];
+    loop {
+        let size = inflater.read(&mut chunk)?;
+        if size == 0 {
+            return Ok(output);
+        }
+        output.extend_from_slice(&chunk[..size]);
+    }
+}
+
+#[test]
+fn decode() {
+    let data = page();
+    for level in [0, 1, 6, 9] {
+        let mut gzip = Vec::new();
+        let options = CompressOptions {
+            level,
+            ..Default::default()
+        };
+        ripgzip::compress(&data[..], &mut gzip, options).unwrap();
+        // Without the 10 byte header and the 8 byte footer.
+        let deflate = &gzip[10..gzip.len() - 8];
+
+        let mut window = [0; WINDOW_SIZE];
+        let mut tables = HuffmanTables::new();
+        for chunk_size in [1, 100, 4096] {
+            let mut inflater = HeaplessInflater::new(deflate, &mut window, &mut tables);
+            assert_eq!(read_all(&mut inflater, chunk_size).unwrap(), data);
+            assert!(inflater.is_finished());
+            assert_eq!(inflater.consumed(), deflate.len());
+            assert_eq!(inflater.total_out(), data.len() as u64);
+        }
+    }
+}
+
+#[test]
+fn errors() {
+    let gzip: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut window = [0; WINDOW_SIZE];
+    let mut tables = HuffmanTables::new();
+    let mut inflater = HeaplessInflater::new(&gzip[10..1000], &mut window, &mut tables);
+    assert_eq!(
+        read_all(&mut inflater, 512),
+        Err(HeaplessError::UnexpectedEof)
+    );
+
+    let mut inflater = HeaplessInflater::new(&[0b111], &mut window, &mut tables);
+    assert_eq!(
+        read_all(&mut inflater, 1),
+        Err(HeaplessError::InvalidBlockType)
+    );
+
+    // A stored block whose length isn't followed by its complement.
+    let mut inflater = HeaplessInflater::new(&[1, 5, 0, 0, 0], &mut window, &mut tables);
+    assert_eq!(
+        read_all(&mut inflater, 1),
+        Err(HeaplessError::StoredLengthMismatch)
+    );
+}
+
+#[test]
+fn fixed_block() {
+    let mut window = [0; WINDOW_SIZE];
+    let mut tables = HuffmanTables::new();
+    let input = [203, 72, 205, 201, 201, 87, 200, 64, 39, 185, 0];
+    let mut inflater = HeaplessInflater::new(&input, &mut window, &mut tables);
+    assert_eq!(
+        read_all(&mut inflater, 7).unwrap(),
+        b"hello hello hello hello\n"
+    );
+
+    // Length symbol 257 followed by distance symbol 30, read as 5 plain bits.
+    let mut inflater =
+        HeaplessInflater::new(&[0b00000011, 0b00111110, 0], &mut window, &mut tables);
+    assert_eq!(
+        read_all(&mut inflater, 1),
+        Err(HeaplessError::InvalidSymbol)
+    );
+}
@@ -0,0 +1,92 @@
+#![cfg(all(feature = "gzip", feature = "zlib"))]
+
+use ripgzip::{decompress, GzipIndex};
+
+fn war_and_peace() -> (&'static [u8], Vec<u8>) {
+    let data: &[u8] = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+    let mut text = Vec::new();
+    decompress(data, &mut text).unwrap();
+    (data, text)
+}
+
+#[test]
+fn build() {
+    let (data, text) = war_and_peace();
+    let index = GzipIndex::build(data, 1 << 16).unwrap();
+    assert_eq!(index.compressed_size, data.len() as u64);
+    assert_eq!(index.uncompressed_size, text.len() as u64);
+    assert!(index.points.len() > 5);
+    for pair in index.points.windows(2) {
+        assert!(pair[1].uncompressed_offset - pair[0].uncompressed_offset >= 1 << 16);
+        assert!(pair[1].compressed_offset > pair[0].compressed_offset);
+    }
+    for point in &index.points {
+        assert!(point.bits < 8);
+        let end = point.uncompressed_offset as usize;
+        assert_eq!(point.window, &text[end - point.window.len()..end]);
+    }
+}
+
+#[test]
+fn indexed_gzip_format() {
+    let (data, _) = war_and_peace();
+    let index = GzipIndex::build(data, 1 << 16).unwrap();
+    let mut file = Vec::new();
+    index.write_indexed_gzip(&mut file).unwrap();
+    assert!(file.starts_with(b"GZIDX\x01\x00"));
+    assert_eq!(
+        GzipIndex::read_indexed_gzip(file.as_slice()).unwrap(),
+        index
+    );
+}
+
+#[test]
+fn gztool_format() {
+    let (data, _) = war_and_peace();
+    let index = GzipIndex::build(data, 1 << 16).unwrap();
+    let mut file = Vec::new();
+    index.write_gztool(&mut file).unwrap();
+    assert!(file.starts_with(b"\0\0\0\0\0\0\0\0gzipindx"));
+
+    let imported = GzipIndex::read_gztool(file.as_slice()).unwrap();
+    assert_eq!(imported.points, index.points);
+    assert_eq!(imported.uncompressed_size, index.uncompressed_size);
+
+    // An index still being built lacks the trailer.
+    file.truncate(file.len() - 8);
+    let imported = GzipIndex::read_gztool(file.as_slice()).unwrap();
+    assert_eq!(imported.points, index.points);
+}
+
+#[test]
+fn checkpoint_callback() {
+    use std::sync::{Arc, Mutex};
+
+    use ripgzip::checksum::{Checksum, Crc32};
+    use ripgzip::{CheckpointInfo, GzipDecoder};
+
+    let (data, text) = war_and_peace();
+    let index = GzipIndex::build(data, 1 << 16).unwrap();
+
+    let points = Arc::new(Mutex::new(Vec::<CheckpointInfo>::new()));
+    let mut decoder = GzipDecoder::new(data);
+    let recorded = points.clone();
+    decoder.set_checkpoint_callback(1 << 16, move |point| recorded.lock().unwrap().push(point));
+    let mut output = Vec::new();
+    std::io::Read::read_to_end(&mut decoder, &mut output).unwrap();
+    assert_eq!(output, text);
+
+    let points = points.lock().unwrap();
+    assert_eq!(points.len(), index.points.len());
+    for (point, expected) in points.iter().zip(&index.points) {
+        assert_eq!(point.uncompressed_offset, expected.uncompressed_offset);
+        assert_eq!(
+            point.bit_offset,
+            expected.compressed_offset * 8 - expected.bits as u64
+        );
+        assert_eq!(point.compressed_offset, point.bit_offset / 8);
+        let mut hash = Crc32::default();
+        hash.update(&expected.window);
+        assert_eq!(point.window_hash, hash.value());
+    }
+}
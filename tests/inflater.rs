@@ -0,0 +1,280 @@
+#![cfg(all(feature = "gzip", feature = "zlib"))]
+
+use std::io::Read;
+
+use ripgzip::{GzipHeaderOut, HeaderProgress, Inflater, InflaterStatus};
+
+/// Feed `input` to `inflater` in pieces of `input_size` bytes, with an output
+/// buffer of `output_size` bytes. Returns the output and the input consumed.
+fn run(
+    inflater: &mut Inflater,
+    input: &[u8],
+    input_size: usize,
+    output_size: usize,
+) -> (Vec<u8>, usize) {
+    let mut output = Vec::new();
+    let mut buf = vec![0; output_size];
+    let mut pos = 0;
+    loop {
+        let end = std::cmp::min(pos + input_size, input.len());
+        let progress = inflater.inflate(&input[pos..end], &mut buf).unwrap();
+        pos += progress.consumed;
+        output.extend_from_slice(&buf[..progress.produced]);
+        match progress.status {
+            InflaterStatus::StreamEnd => return (output, pos),
+            InflaterStatus::NeedDictionary => panic!("dictionary required"),
+            InflaterStatus::BufError => panic!("no progress"),
+            InflaterStatus::Ok => {}
+        }
+    }
+}
+
+fn page() -> Vec<u8> {
+    let mut data = Vec::new();
+    ripgzip::decompress(&include_bytes!("../data/ok/01-page.gz")[..], &mut data).unwrap();
+    data
+}
+
+#[test]
+fn formats() {
+    let gzip: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let zlib: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    let expected = page();
+    for (input_size, output_size) in [(1, 1), (7, 100), (1000, 3), (1 << 20, 1 << 20)] {
+        let mut inflater = Inflater::new(31).unwrap();
+        let (output, consumed) = run(&mut inflater, gzip, input_size, output_size);
+        assert_eq!(output, expected);
+        assert_eq!(consumed, gzip.len());
+        assert_eq!(inflater.total_in(), gzip.len() as u64);
+        assert_eq!(inflater.total_out(), expected.len() as u64);
+
+        let mut inflater = Inflater::new(15).unwrap();
+        let (output, consumed) = run(&mut inflater, zlib, input_size, output_size);
+        assert_eq!(output, expected);
+        assert_eq!(consumed, zlib.len());
+
+        // The deflate stream of the gzip member, without its 10 byte header.
+        let mut inflater = Inflater::new(-15).unwrap();
+        let (output, consumed) = run(&mut inflater, &gzip[10..], input_size, output_size);
+        assert_eq!(output, expected);
+        assert_eq!(consumed, gzip.len() - 10 - 8);
+    }
+}
+
+#[test]
+fn trailing_data() {
+    let mut input = include_bytes!("../data/ok/01-page.gz").to_vec();
+    let len = input.len();
+    input.extend_from_slice(include_bytes!("../data/ok/01-page.gz"));
+
+    // Decoding stops at the end of the member, which can then be started over.
+    let mut inflater = Inflater::new(31).unwrap();
+    let (output, consumed) = run(&mut inflater, &input, 1 << 20, 1 << 20);
+    assert_eq!((output.len(), consumed), (page().len(), len));
+    let mut buf = [0; 16];
+    let progress = inflater.inflate(&input[consumed..], &mut buf).unwrap();
+    assert_eq!(
+        (progress.consumed, progress.status),
+        (0, InflaterStatus::StreamEnd)
+    );
+
+    inflater.reset();
+    let (output, consumed) = run(&mut inflater, &input[len..], 1 << 20, 1 << 20);
+    assert_eq!((output, consumed), (page(), len));
+}
+
+#[test]
+fn errors() {
+    assert!(Inflater::new(16).is_err());
+    assert!(Inflater::new(-8).is_err());
+
+    // The stream asks for a larger window.
+    let zlib: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    let mut inflater = Inflater::new(9).unwrap();
+    let err = inflater.inflate(zlib, &mut [0; 16]).unwrap_err();
+    assert_eq!(err.to_string(), "invalid window size");
+
+    let corrupted: &[u8] = include_bytes!("../data/corrupted/01-bad-crc32.gz");
+    let mut inflater = Inflater::new(31).unwrap();
+    let mut buf = vec![0; 1 << 20];
+    let err = inflater.inflate(corrupted, &mut buf).unwrap_err();
+    assert_eq!(err.to_string(), "crc32 check failed");
+
+    // No progress without input.
+    let mut inflater = Inflater::new(31).unwrap();
+    let progress = inflater.inflate(&[], &mut buf).unwrap();
+    assert_eq!(progress.status, InflaterStatus::BufError);
+}
+
+#[test]
+fn get_header() {
+    let options = ripgzip::CompressOptions {
+        name: Some("page.html".to_string()),
+        modification_time: 1_000_000_000,
+        extra: vec![ripgzip::ExtraField::new(*b"xy", b"abc".to_vec())],
+        header_crc: true,
+        ..Default::default()
+    };
+    let mut input = Vec::new();
+    ripgzip::compress(&page()[..], &mut input, options).unwrap();
+
+    let header = GzipHeaderOut::new();
+    let mut inflater = Inflater::new(31).unwrap();
+    inflater.get_header(header.clone()).unwrap();
+    let mut buf = [0; 16];
+    // The fixed fields are reported once their 10 bytes are in.
+    for i in 0..10 {
+        inflater.inflate(&input[i..i + 1], &mut buf).unwrap();
+        let expected = match i {
+            9 => HeaderProgress::Fixed,
+            _ => HeaderProgress::Pending,
+        };
+        assert_eq!(header.progress(), expected);
+    }
+    let fixed = header.header().unwrap();
+    assert_eq!(fixed.modification_time, 1_000_000_000);
+    assert_eq!(fixed.name, None);
+
+    let (output, _) = run(&mut inflater, &input[10..], 5, 1 << 20);
+    assert_eq!(output, page());
+    assert!(header.is_done());
+    let header = header.header().unwrap();
+    assert_eq!(header.name.as_deref(), Some("page.html"));
+    assert_eq!(header.extra.as_deref(), Some(&b"xy\x03\x00abc"[..]));
+    assert!(header.has_crc);
+
+    assert!(Inflater::new(15)
+        .unwrap()
+        .get_header(GzipHeaderOut::new())
+        .is_err());
+}
+
+#[test]
+fn dictionary() {
+    let data = page();
+    let dictionary = &data[..4000];
+    let input: &[u8] = include_bytes!("../data/zlib/01-page-dict.zz");
+
+    let mut inflater = Inflater::new(15).unwrap();
+    assert!(inflater.set_dictionary(dictionary).is_err());
+    let progress = inflater.inflate(&input[..3], &mut [0; 16]).unwrap();
+    assert_eq!(progress.status, InflaterStatus::Ok);
+    let progress = inflater.inflate(&input[3..], &mut [0; 16]).unwrap();
+    assert_eq!(
+        (progress.consumed, progress.status),
+        (3, InflaterStatus::NeedDictionary)
+    );
+    assert_eq!(inflater.dictionary_id(), Some(0x49a77c69));
+    let err = inflater.set_dictionary(&data[..3999]).unwrap_err();
+    assert_eq!(err.to_string(), "incorrect dictionary");
+    inflater.set_dictionary(dictionary).unwrap();
+    let (output, consumed) = run(&mut inflater, &input[6..], 100, 1000);
+    assert_eq!(output, data);
+    assert_eq!(consumed, input.len() - 6);
+    assert_eq!(inflater.get_dictionary(), data[data.len() - 32768..]);
+
+    // A raw stream takes the dictionary up front.
+    let mut inflater = Inflater::new(-15).unwrap();
+    inflater.set_dictionary(dictionary).unwrap();
+    let (output, _) = run(&mut inflater, &input[6..], 1 << 20, 1 << 20);
+    assert_eq!(output, data);
+    assert!(Inflater::new(31)
+        .unwrap()
+        .set_dictionary(dictionary)
+        .is_err());
+}
+
+#[test]
+fn duplicate() {
+    let input: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    let mut inflater = Inflater::new(15).unwrap();
+    let mut buf = vec![0; 50000];
+    let progress = inflater.inflate(&input[..20000], &mut buf).unwrap();
+    let mut output = buf[..progress.produced].to_vec();
+
+    let mut copy = inflater.duplicate();
+    let (rest, _) = run(&mut copy, &input[progress.consumed..], 1000, 1000);
+    let (other, _) = run(&mut inflater, &input[progress.consumed..], 1 << 20, 7);
+    assert_eq!(rest, other);
+    output.extend_from_slice(&rest);
+    assert_eq!(output, page());
+    assert_eq!(copy.total_out(), inflater.total_out());
+}
+
+/// The bits of `data` from bit `offset` on.
+fn shift(data: &[u8], offset: usize) -> Vec<u8> {
+    let (skip, bits) = (offset / 8, offset % 8);
+    (skip..data.len())
+        .map(|i| {
+            let next = data.get(i + 1).copied().unwrap_or(0) as u16;
+            ((data[i] as u16 | next << 8) >> bits) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn prime() {
+    let gzip: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let deflate = &gzip[10..gzip.len() - 8];
+    // Start at a bit offset, its first bits being primed.
+    for offset in [1, 5, 8, 13, 16] {
+        let mut inflater = Inflater::new(-15).unwrap();
+        let value = u32::from_le_bytes(deflate[..4].try_into().unwrap()) as u16;
+        inflater.prime(offset as u8, value).unwrap();
+        let (output, _) = run(&mut inflater, &shift(deflate, offset), 100, 1 << 20);
+        assert_eq!(output, page());
+    }
+
+    let mut inflater = Inflater::new(-15).unwrap();
+    inflater.prime(10, 0).unwrap();
+    assert!(inflater.prime(7, 0).is_err());
+    assert!(Inflater::new(31).unwrap().prime(3, 0).is_err());
+}
+
+#[test]
+fn raw_window_bits() {
+    let zlib: &[u8] = include_bytes!("../data/zlib/02-page-window-512.zz");
+    let deflate = &zlib[2..zlib.len() - 4];
+    let mut expected = Vec::new();
+    ripgzip::ZlibDecoder::new(zlib)
+        .read_to_end(&mut expected)
+        .unwrap();
+    for window_bits in -15..=-9 {
+        let mut inflater = Inflater::new(window_bits).unwrap();
+        let (output, _) = run(&mut inflater, deflate, 1 << 20, 1 << 20);
+        assert_eq!(output, expected);
+    }
+
+    // Matches of the stream reach further back than 512 bytes.
+    let gzip: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut inflater = Inflater::new(-9).unwrap();
+    let mut buf = vec![0; 1 << 20];
+    assert!(inflater.inflate(&gzip[10..], &mut buf).is_err());
+}
+
+#[test]
+fn auto_detect() {
+    let gzip: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let zlib: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    let mut inflater = Inflater::new(32 | 15).unwrap();
+    for input in [gzip, zlib, gzip] {
+        let header = GzipHeaderOut::new();
+        inflater.get_header(header.clone()).unwrap();
+        let (output, consumed) = run(&mut inflater, input, 1, 1000);
+        assert_eq!(output, page());
+        assert_eq!(consumed, input.len());
+        let expected = match input[0] {
+            0x1f => HeaderProgress::Done,
+            _ => HeaderProgress::NotGzip,
+        };
+        assert_eq!(header.progress(), expected);
+        inflater.reset();
+    }
+
+    // The window declared by the zlib header is checked.
+    let mut inflater = Inflater::new(32 | 9).unwrap();
+    let err = inflater.inflate(zlib, &mut [0; 16]).unwrap_err();
+    assert_eq!(err.to_string(), "invalid window size");
+    assert!(Inflater::new(32).is_ok());
+    assert!(Inflater::new(33).is_err());
+}
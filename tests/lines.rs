@@ -0,0 +1,56 @@
+#![cfg(feature = "gzip")]
+
+use ripgzip::{decompress, lines};
+
+#[test]
+fn same_lines() {
+    let data: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut expected = Vec::new();
+    decompress(data, &mut expected).unwrap();
+    let expected = String::from_utf8(expected).unwrap();
+
+    let got = lines(data).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(got, expected.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn stops_on_error() {
+    let data: &[u8] = include_bytes!("../data/corrupted/01-bad-crc32.gz");
+    let results = lines(data).collect::<Vec<_>>();
+    let err = results.last().unwrap().as_ref().unwrap_err();
+    assert_eq!(err.to_string(), "crc32 check failed");
+    assert!(results[..results.len() - 1].iter().all(|line| line.is_ok()));
+}
+
+/// Counts the `key = value` pairs of a TOML-like file, reading it as
+/// deserializers such as `serde_json::from_reader` do.
+fn count_pairs<R: std::io::Read>(mut reader: R) -> std::io::Result<usize> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    Ok(text.lines().filter(|line| line.contains(" = ")).count())
+}
+
+#[test]
+fn buf_reader() {
+    let data: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    assert!(count_pairs(ripgzip::buf_reader(data)).unwrap() > 3);
+
+    // The reader is itself a BufRead, so nested compression unwraps in one go.
+    let nested: &[u8] = include_bytes!("../data/ok/07-war-and-peace.txt.gz.gz");
+    let mut expected = Vec::new();
+    decompress(
+        &include_bytes!("../data/ok/06-war-and-peace.txt.gz")[..],
+        &mut expected,
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    std::io::copy(
+        &mut ripgzip::buf_reader(ripgzip::buf_reader(nested)),
+        &mut output,
+    )
+    .unwrap();
+    assert_eq!(output, expected);
+
+    let corrupted: &[u8] = include_bytes!("../data/corrupted/01-bad-crc32.gz");
+    assert!(count_pairs(ripgzip::buf_reader(corrupted)).is_err());
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "gzip")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use ripgzip::{decompress_parts, PartError};
+
+fn write_parts(name: &str, data: &[u8], splits: &[usize]) -> Vec<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("ripgzip-parts-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut bounds = vec![0];
+    bounds.extend_from_slice(splits);
+    bounds.push(data.len());
+    bounds
+        .windows(2)
+        .enumerate()
+        .map(|(i, range)| {
+            let path = dir.join(format!("data.gz.{:03}", i));
+            fs::write(&path, &data[range[0]..range[1]]).unwrap();
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn parts() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    // Splits in a header, in a deflate stream and in a footer, and an empty part.
+    let splits = [5, 50_000, 100_000, 100_000, data.len() - 4];
+    let paths = write_parts("ok", data, &splits);
+    let mut output = Vec::new();
+    decompress_parts(&paths, &mut output).unwrap();
+    assert_eq!(output, expected);
+
+    // A missing part is reported as such, and a truncated stream in its last part.
+    let err = decompress_parts(&paths[..2], &mut Vec::new()).unwrap_err();
+    let part = err.downcast_ref::<PartError>().unwrap();
+    assert_eq!((part.index, part.offset), (1, 50_000 - 5));
+    assert_eq!(part.path, paths[1]);
+
+    let mut missing = paths.clone();
+    missing[2].set_extension("999");
+    let err = decompress_parts(&missing, &mut Vec::new()).unwrap_err();
+    assert!(format!("{:#}", err).contains("data.gz.999"), "{:#}", err);
+
+    fs::remove_dir_all(paths[0].parent().unwrap()).unwrap();
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "gzip")]
+
+use ripgzip::{decompress, decompress_pipelined};
+
+#[test]
+fn same_output() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut expected = Vec::new();
+    decompress(data, &mut expected).unwrap();
+
+    let mut output = Vec::new();
+    decompress_pipelined(data, &mut output).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn errors() {
+    for (data, msg) in [
+        (
+            &include_bytes!("../data/corrupted/01-bad-crc32.gz")[..],
+            "crc32 check failed",
+        ),
+        (
+            &include_bytes!("../data/corrupted/00-bad-length.gz")[..],
+            "length check failed",
+        ),
+        (
+            &include_bytes!("../data/corrupted/03-wrong-id.gz")[..],
+            "wrong id values",
+        ),
+    ] {
+        let err = decompress_pipelined(data, std::io::sink()).unwrap_err();
+        assert!(err.to_string().contains(msg), "{} vs {}", err, msg);
+    }
+}
@@ -0,0 +1,36 @@
+#![cfg(feature = "gzip")]
+
+use std::io::Read;
+
+use ripgzip::prelude::*;
+
+fn member_name(decoder: &GzipDecoder<&[u8]>) -> Option<String> {
+    let header: &MemberHeader = decoder.header()?;
+    header.name.clone()
+}
+
+fn stats(outcome: Bounded) -> Option<DecompressStats> {
+    match outcome {
+        Bounded::Complete(stats) => Some(stats),
+        Bounded::Truncated { .. } => None,
+    }
+}
+
+#[test]
+fn prelude() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut decoder = GzipDecoder::with_options(data, DecompressOptions::default());
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert!(decoder.header().is_some());
+    assert_eq!(member_name(&decoder), None);
+    let usage: BlockUsage = decoder.block_usage();
+    assert_ne!(usage, BlockUsage::default());
+
+    let outcome = ripgzip::decompress_bounded(data, Vec::new(), u64::MAX).unwrap();
+    let stats = stats(outcome).unwrap();
+    assert_eq!(stats.decompressed_size, output.len() as u64);
+
+    let error = decompress(&data[..data.len() / 2], Vec::new()).unwrap_err();
+    assert!(error.downcast_ref::<PartialOutput>().is_some());
+}
@@ -0,0 +1,108 @@
+#![cfg(feature = "proptest")]
+
+use std::io::Write;
+
+use proptest::prelude::*;
+use ripgzip::checksum::{Checksum, Crc32};
+use ripgzip::testing::{self, TestToken};
+use ripgzip::tracking_writer::TrackingWriter;
+use ripgzip::{CompressOptions, WINDOW_SIZE};
+
+/// CRC-32 computed a bit at a time, as a reference.
+fn crc32_reference(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Update `checksum` with `data` cut at `cuts`.
+fn update_in_pieces<C: Checksum>(checksum: &mut C, data: &[u8], cuts: &[usize]) {
+    let mut cuts = cuts
+        .iter()
+        .map(|cut| cut % (data.len() + 1))
+        .collect::<Vec<_>>();
+    cuts.sort();
+    let mut start = 0;
+    for cut in cuts.into_iter().chain([data.len()]) {
+        checksum.update(&data[start..cut]);
+        start = cut;
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn round_trip(
+        tokens in testing::token_stream(WINDOW_SIZE, 0, 2000),
+        level in 0..=9u32,
+        huffman_only in any::<bool>(),
+    ) {
+        let data = testing::expand(&[], &tokens);
+        let strategy = match huffman_only {
+            true => ripgzip::Strategy::HuffmanOnly,
+            false => ripgzip::Strategy::Default,
+        };
+        let options = CompressOptions {
+            level,
+            strategy,
+            ..Default::default()
+        };
+        testing::round_trip(&data, options).unwrap();
+    }
+
+    #[test]
+    fn window(
+        (window, history, tokens) in testing::window_state().prop_flat_map(|(window, history)| {
+            let available = std::cmp::min(history.len(), window);
+            (Just(window), Just(history), testing::token_stream(window, available, 200))
+        }),
+    ) {
+        let mut writer = TrackingWriter::with_checksum(Vec::new(), Crc32::default());
+        writer.set_window_size(window);
+        writer.set_dictionary(&history);
+        for &token in &tokens {
+            match token {
+                TestToken::Literal(value) => writer.write_all(&[value]).unwrap(),
+                TestToken::Match { distance, length } => {
+                    writer.write_previous(distance, length).unwrap()
+                }
+            }
+        }
+        let data = testing::expand(&history, &tokens);
+        prop_assert_eq!(writer.byte_count(), data.len());
+        prop_assert_eq!(writer.checksum(), crc32_reference(&data));
+
+        // The window holds the last bytes, dictionary included, and no more.
+        let all = [history, data.clone()].concat();
+        let (snapshot, len) = writer.history_snapshot();
+        prop_assert_eq!(&snapshot[..len], &all[all.len().saturating_sub(window)..]);
+        prop_assert!(writer.write_previous(len + 1, 3).is_err());
+        prop_assert_eq!(writer.into_inner(), data);
+    }
+
+    #[test]
+    fn crc32(data in proptest::collection::vec(any::<u8>(), 0..5000), cuts in proptest::collection::vec(any::<usize>(), 0..8)) {
+        let mut crc32 = Crc32::default();
+        update_in_pieces(&mut crc32, &data, &cuts);
+        prop_assert_eq!(crc32.value(), crc32_reference(&data));
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn adler32(data in proptest::collection::vec(any::<u8>(), 0..10000), cuts in proptest::collection::vec(any::<usize>(), 0..8)) {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        let mut adler32 = ripgzip::checksum::Adler32::new();
+        update_in_pieces(&mut adler32, &data, &cuts);
+        prop_assert_eq!(adler32.value(), b << 16 | a);
+    }
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "gzip")]
+
+use std::io::Read;
+
+use ripgzip::CompressOptions;
+
+fn page() -> Vec<u8> {
+    let mut data = Vec::new();
+    ripgzip::decompress(&include_bytes!("../data/ok/01-page.gz")[..], &mut data).unwrap();
+    data
+}
+
+#[test]
+fn read_encoder() {
+    let data = page();
+    let mut encoder =
+        ripgzip::read::GzipEncoder::new(data.as_slice(), CompressOptions::default()).unwrap();
+    // Small reads, which leave compressed data behind each time.
+    let mut compressed = Vec::new();
+    let mut buf = [0; 1000];
+    loop {
+        let len = encoder.read(&mut buf).unwrap();
+        if len == 0 {
+            break;
+        }
+        compressed.extend_from_slice(&buf[..len]);
+    }
+    assert_eq!(encoder.read(&mut buf).unwrap(), 0);
+    assert!(encoder.get_ref().is_empty());
+
+    let mut expected = Vec::new();
+    ripgzip::compress(data.as_slice(), &mut expected, CompressOptions::default()).unwrap();
+    assert_eq!(compressed, expected);
+
+    let mut compressed = Vec::new();
+    ripgzip::read::GzipEncoder::new(&[][..], CompressOptions::default())
+        .unwrap()
+        .read_to_end(&mut compressed)
+        .unwrap();
+    let mut output = Vec::new();
+    ripgzip::read::GzipDecoder::new(compressed.as_slice())
+        .read_to_end(&mut output)
+        .unwrap();
+    assert!(output.is_empty());
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn write_decoder() {
+    use std::io::Write;
+
+    use ripgzip::write::GzipDecoder;
+
+    let concat = [
+        &include_bytes!("../data/ok/00-Cargo.toml.gz")[..],
+        include_bytes!("../data/ok/01-page.gz"),
+    ]
+    .concat();
+    let mut expected = Vec::new();
+    ripgzip::decompress(concat.as_slice(), &mut expected).unwrap();
+
+    for chunk_size in [1, 7, 4096, concat.len()] {
+        let mut decoder = GzipDecoder::new(Vec::new());
+        for chunk in concat.chunks(chunk_size) {
+            decoder.write_all(chunk).unwrap();
+        }
+        assert_eq!(decoder.finish().unwrap(), expected);
+    }
+
+    assert!(GzipDecoder::new(Vec::new()).finish().unwrap().is_empty());
+
+    let mut decoder = GzipDecoder::new(Vec::new());
+    decoder.write_all(&concat[..concat.len() - 1]).unwrap();
+    let err = decoder.finish().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::UnexpectedEof
+    );
+
+    let mut decoder = GzipDecoder::new(Vec::new());
+    let err = decoder
+        .write_all(include_bytes!("../data/corrupted/01-bad-crc32.gz"))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
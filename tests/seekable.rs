@@ -0,0 +1,134 @@
+#![cfg(all(feature = "gzip", feature = "zlib"))]
+
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use ripgzip::{decompress, GzipIndex, SeekableGzipReader, WindowStore};
+
+fn war_and_peace() -> (&'static [u8], Vec<u8>) {
+    let data: &[u8] = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+    let mut text = Vec::new();
+    decompress(data, &mut text).unwrap();
+    (data, text)
+}
+
+fn read_at<R: Read + Seek>(
+    reader: &mut SeekableGzipReader<R>,
+    pos: SeekFrom,
+    len: usize,
+) -> Vec<u8> {
+    reader.seek(pos).unwrap();
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn seek() {
+    let (data, text) = war_and_peace();
+    let mut reader = SeekableGzipReader::build(Cursor::new(data), 1 << 16).unwrap();
+    assert_eq!(reader.len(), text.len() as u64);
+
+    for offset in [1_000_000, 10, 700_000, 700_100, 1_500_000, 0] {
+        let expected = &text[offset..offset + 1000];
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(offset as u64), 1000),
+            expected
+        );
+    }
+    let expected = &text[text.len() - 500..];
+    assert_eq!(read_at(&mut reader, SeekFrom::End(-500), 1000), expected);
+    let position = reader.stream_position().unwrap() as usize;
+    assert_eq!(position, text.len());
+
+    assert_eq!(
+        read_at(&mut reader, SeekFrom::Current(-300_000), 100),
+        &text[position - 300_000..][..100]
+    );
+    assert!(reader
+        .seek(SeekFrom::Current(-(text.len() as i64) - 1))
+        .is_err());
+    assert!(read_at(&mut reader, SeekFrom::Start(text.len() as u64 + 10), 10).is_empty());
+}
+
+#[test]
+fn imported_index() {
+    let (data, text) = war_and_peace();
+    let mut file = Vec::new();
+    GzipIndex::build(data, 1 << 16)
+        .unwrap()
+        .write_gztool(&mut file)
+        .unwrap();
+    let mut index = GzipIndex::read_gztool(file.as_slice()).unwrap();
+    // Fall back to ISIZE for the size.
+    index.uncompressed_size = 0;
+
+    let mut reader = SeekableGzipReader::new(Cursor::new(data), index).unwrap();
+    assert_eq!(reader.len(), text.len() as u64);
+    assert_eq!(
+        read_at(&mut reader, SeekFrom::End(-1000), 1000),
+        &text[text.len() - 1000..]
+    );
+    assert_eq!(
+        read_at(&mut reader, SeekFrom::Start(123_456), 1000),
+        &text[123_456..][..1000]
+    );
+}
+
+#[test]
+fn multi_member() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let mut text = Vec::new();
+    decompress(data, &mut text).unwrap();
+    let mut reader = SeekableGzipReader::build(Cursor::new(data), 1 << 16).unwrap();
+    assert!(reader.index().points.len() > 1);
+    for point in reader.index().points.clone() {
+        let offset = point.uncompressed_offset as usize + 1;
+        let expected = &text[offset..std::cmp::min(offset + 1000, text.len())];
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(offset as u64), 1000),
+            expected
+        );
+    }
+    // Across the following members, whose footers are checked.
+    let offset = reader.index().points[1].uncompressed_offset as usize;
+    assert_eq!(
+        read_at(&mut reader, SeekFrom::Start(offset as u64), text.len()),
+        &text[offset..]
+    );
+}
+
+#[test]
+fn window_store() {
+    let (data, text) = war_and_peace();
+    let path = std::env::temp_dir().join(format!("ripgzip-windows-{}", std::process::id()));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    let (index, _) = WindowStore::build(data, 1 << 16, file).unwrap();
+    assert!(index.points.iter().all(|point| point.window.is_empty()));
+
+    let mut windows = WindowStore::open(File::open(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(windows.len(), index.points.len());
+    let last = index.points.last().unwrap();
+    let end = last.uncompressed_offset as usize;
+    assert_eq!(
+        windows.load(windows.len() - 1).unwrap(),
+        &text[end - 32768..end]
+    );
+
+    let mut reader =
+        SeekableGzipReader::with_window_store(Cursor::new(data), index, windows).unwrap();
+    for offset in [1_000_000, 300_000] {
+        let expected = &text[offset..offset + 1000];
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(offset as u64), 1000),
+            expected
+        );
+    }
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "gzip")]
+
+use ripgzip::{decompress_to, Sink, WriteSink};
+
+#[test]
+fn sinks() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut output = Vec::new();
+    decompress_to(data, &mut output).unwrap();
+    assert_eq!(output, expected);
+
+    let mut written = Vec::new();
+    decompress_to(data, WriteSink(&mut written)).unwrap();
+    assert_eq!(written, expected);
+
+    let mut buf = vec![0; expected.len() + 10];
+    let mut rest = buf.as_mut_slice();
+    decompress_to(data, &mut rest).unwrap();
+    assert_eq!(rest.capacity(), Some(10));
+    assert_eq!(buf[..expected.len()], expected);
+
+    // A sink too small is filled up.
+    let mut buf = vec![0; 100_000];
+    let err = decompress_to(data, buf.as_mut_slice()).unwrap_err();
+    assert_eq!(err.to_string(), "sink is full");
+    assert_eq!(buf, expected[..100_000]);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn bytes_mut() {
+    let data: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let mut output = bytes::BytesMut::new();
+    decompress_to(data, &mut output).unwrap();
+    assert_eq!(output, expected);
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "gzip")]
+
+use std::io::{BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use ripgzip::{ReadTimeout, TimeoutReader};
+
+#[test]
+fn read_timeout() {
+    let data: &[u8] = include_bytes!("../data/ok/01-page.gz");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // Half of the stream is sent, and the connection stalls.
+    let sender = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&data[..data.len() / 2]).unwrap();
+        std::thread::sleep(Duration::from_secs(1));
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    let timeout = Duration::from_millis(200);
+    let reader = TimeoutReader::new(stream, timeout).unwrap();
+    let err = ripgzip::decompress(BufReader::new(reader), &mut Vec::new()).unwrap_err();
+    let expected = ReadTimeout {
+        position: (data.len() / 2) as u64,
+        timeout,
+    };
+    assert_eq!(ReadTimeout::find(&err), Some(&expected));
+    sender.join().unwrap();
+}
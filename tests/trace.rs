@@ -0,0 +1,169 @@
+#![cfg(feature = "trace-decode")]
+
+use std::io::Read;
+
+use ripgzip::deflate::CompressionType;
+use ripgzip::{GzipDecoder, TraceEvent, TraceLog};
+
+#[test]
+fn trace() {
+    let data: &[u8] = include_bytes!("../data/ok/00-Cargo.toml.gz");
+    let mut expected = Vec::new();
+    ripgzip::decompress(data, &mut expected).unwrap();
+
+    let log = TraceLog::new();
+    let mut decoder = GzipDecoder::new(data);
+    decoder.set_trace(log.clone(), 4);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, expected);
+
+    let events = log.events();
+    assert_eq!(events.len(), 7);
+    assert_eq!(events[0], TraceEvent::MemberStart { offset: 0 });
+    assert_eq!(
+        events[1],
+        TraceEvent::BlockHeader {
+            bit_offset: 80,
+            is_final: true,
+            block_type: CompressionType::DynamicTree,
+        }
+    );
+    assert!(matches!(events[2], TraceEvent::Trees { .. }));
+    let literals = expected[..4]
+        .iter()
+        .map(|&value| TraceEvent::Literal(value));
+    assert!(events[3..].iter().cloned().eq(literals));
+
+    assert!(log
+        .to_text()
+        .starts_with("member at byte 0\nblock at bit 80: DynamicTree, final\n"));
+    let json = log.to_json();
+    assert!(json.starts_with(
+        r#"[{"event":"member_start","offset":0},{"event":"block_header","bit_offset":80,"#
+    ));
+    assert!(json.ends_with(&format!(
+        r#"{{"event":"literal","value":{}}}]"#,
+        expected[3]
+    )));
+}
+
+#[test]
+fn trace_error() {
+    let data: &[u8] = include_bytes!("../data/corrupted/06-invalid-btype.gz");
+    let log = TraceLog::new();
+    let mut decoder = GzipDecoder::new(data);
+    decoder.set_trace(log.clone(), 0);
+    assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+
+    let events = log.events();
+    let (header, error) = (&events[events.len() - 2], &events[events.len() - 1]);
+    let TraceEvent::BlockHeader {
+        bit_offset,
+        block_type: CompressionType::Reserved,
+        ..
+    } = header
+    else {
+        panic!("unexpected event {:?}", header);
+    };
+    let TraceEvent::Error {
+        bit_offset: error_offset,
+        message,
+    } = error
+    else {
+        panic!("unexpected event {:?}", error);
+    };
+    assert_eq!(message, "unsupported block type");
+    assert!(error_offset >= bit_offset);
+}
+
+#[test]
+fn trace_all() {
+    for entry in std::fs::read_dir("data/ok").unwrap() {
+        let data = std::fs::read(entry.unwrap().path()).unwrap();
+        let mut expected = Vec::new();
+        ripgzip::decompress(data.as_slice(), &mut expected).unwrap();
+
+        let log = TraceLog::new();
+        let mut decoder = GzipDecoder::new(data.as_slice());
+        decoder.set_trace(log.clone(), 300);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+
+        let mut last = 0;
+        for event in log.events() {
+            if let TraceEvent::BlockHeader { bit_offset, .. } = event {
+                assert!(bit_offset >= last && bit_offset < data.len() as u64 * 8);
+                last = bit_offset;
+            }
+        }
+    }
+}
+
+#[test]
+fn member_starts() {
+    let data: &[u8] = include_bytes!("../data/ok/09-concat.gz");
+    let log = TraceLog::new();
+    let mut decoder = GzipDecoder::new(data);
+    decoder.set_trace(log.clone(), 0);
+    decoder.read_to_end(&mut Vec::new()).unwrap();
+
+    let starts = log
+        .events()
+        .into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::MemberStart { offset } => Some(offset),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let members = ripgzip::members(data).unwrap();
+    assert_eq!(starts.len(), 3);
+    for (start, member) in starts.iter().zip(members) {
+        assert_eq!(*start, member.compressed_range.start);
+    }
+}
+
+#[cfg(feature = "trace-log")]
+#[test]
+fn log_sink() {
+    use std::sync::Mutex;
+
+    use ripgzip::LogSink;
+
+    static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+
+    struct Logger;
+
+    impl log::Log for Logger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if record.target() == "ripgzip" {
+                let message = record.args().to_string();
+                RECORDS.lock().unwrap().push((record.level(), message));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let data: &[u8] = include_bytes!("../data/corrupted/06-invalid-btype.gz");
+    let mut decoder = GzipDecoder::new(data);
+    decoder.set_trace(LogSink, 1);
+    assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+
+    let records = RECORDS.lock().unwrap();
+    assert_eq!(
+        records[0],
+        (log::Level::Debug, "member at byte 0".to_string())
+    );
+    let (level, message) = records.last().unwrap();
+    assert_eq!(*level, log::Level::Error);
+    assert!(message.ends_with(": unsupported block type"));
+}
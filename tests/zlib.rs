@@ -0,0 +1,85 @@
+#![cfg(feature = "zlib")]
+
+use std::io::Read;
+
+use ripgzip::ZlibDecoder;
+
+fn page() -> Vec<u8> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(&include_bytes!("../data/zlib/01-page.zz")[..])
+        .read_to_end(&mut data)
+        .unwrap();
+    data
+}
+
+fn decode_error(data: &[u8]) -> String {
+    let mut output = Vec::new();
+    ZlibDecoder::new(data)
+        .read_to_end(&mut output)
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn decode() {
+    let data = page();
+    assert_eq!(data.len(), 153333);
+    assert!(data.starts_with(b"<!DOCTYPE html>"));
+}
+
+#[test]
+fn preset_dictionary() {
+    let data = page();
+    let dictionary = &data[..4000];
+    let mut decoder = ZlibDecoder::new(&include_bytes!("../data/zlib/01-page-dict.zz")[..]);
+    let mut output = Vec::new();
+    let err = decoder.read_to_end(&mut output).unwrap_err();
+    assert_eq!(err.to_string(), "preset dictionary required");
+    assert_eq!(decoder.dictionary_id(), Some(0x49a77c69));
+
+    assert!(decoder.set_dictionary(&data[..3999]).is_err());
+    decoder.set_dictionary(dictionary).unwrap();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, data);
+    assert!(decoder.set_dictionary(dictionary).is_err());
+}
+
+#[test]
+fn header_errors() {
+    let data: &[u8] = include_bytes!("../data/zlib/01-page.zz");
+    let mut corrupted = data.to_vec();
+    corrupted[1] ^= 1;
+    assert_eq!(decode_error(&corrupted), "header check failed");
+    assert_eq!(
+        decode_error(&[0x79, 0x18]),
+        "unsupported compression method"
+    );
+    assert_eq!(decode_error(&[0x88, 0x1c]), "invalid window size");
+
+    let mut corrupted = data.to_vec();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert_eq!(decode_error(&corrupted), "adler32 check failed");
+}
+
+#[test]
+fn strict_window() {
+    let decode = |data: &[u8], strict: bool| {
+        let mut decoder = ZlibDecoder::new(data);
+        decoder.set_strict_window(strict);
+        let mut output = Vec::new();
+        let result = decoder.read_to_end(&mut output).map(|_| output);
+        (result, decoder.window_size())
+    };
+
+    let data: &[u8] = include_bytes!("../data/zlib/02-page-window-512.zz");
+    let (output, window_size) = decode(data, true);
+    assert_eq!(output.unwrap(), page());
+    assert_eq!(window_size, Some(512));
+
+    // The same header on a stream using the full 32K window.
+    let mut data = include_bytes!("../data/zlib/01-page.zz").to_vec();
+    data[..2].copy_from_slice(&[0x18, 0xd3]);
+    assert_eq!(decode(&data, false).0.unwrap(), page());
+    let err = decode(&data, true).0.unwrap_err();
+    assert_eq!(err.to_string(), "dist is beyond the window");
+}